@@ -0,0 +1,79 @@
+use std::fmt;
+use std::str::FromStr;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use anyhow::{bail, Error};
+
+/// What kind of name an alias is
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum AliasKind {
+    /// The company's registered name, e.g. "APPLE INC" in its SEC filings
+    Legal,
+    /// A name the company used to go by
+    Former,
+    /// A brand or trading name, e.g. "Google" for Alphabet
+    Brand,
+    /// A stock ticker used as a name
+    Ticker,
+    /// Recorded before alias kinds were tracked
+    #[default]
+    Unknown,
+}
+
+impl AliasKind {
+    pub fn all() -> Vec<AliasKind> {
+        vec![AliasKind::Legal, AliasKind::Former, AliasKind::Brand, AliasKind::Ticker, AliasKind::Unknown]
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            AliasKind::Legal => "legal",
+            AliasKind::Former => "former",
+            AliasKind::Brand => "brand",
+            AliasKind::Ticker => "ticker",
+            AliasKind::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for AliasKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for AliasKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match AliasKind::all().into_iter().find(|kind| kind.as_str() == s) {
+            Some(kind) => Ok(kind),
+            None => bail!("Unknown alias kind: {}", s),
+        }
+    }
+}
+
+/// What an alias is and when the company went by it, e.g. from filing dates or the SEC's former names.
+/// Unbounded ends of the validity period are None; valid_to is the first day the name was no longer used.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AliasMetadata {
+    pub kind: AliasKind,
+    pub valid_from: Option<NaiveDate>,
+    pub valid_to: Option<NaiveDate>,
+}
+
+impl AliasMetadata {
+    pub fn new(kind: AliasKind) -> AliasMetadata {
+        AliasMetadata { kind, valid_from: None, valid_to: None }
+    }
+
+    /// A name the company went by from `valid_from` until `valid_to`
+    pub fn former(valid_from: Option<NaiveDate>, valid_to: NaiveDate) -> AliasMetadata {
+        AliasMetadata { kind: AliasKind::Former, valid_from, valid_to: Some(valid_to) }
+    }
+
+    /// Whether the company went by the name on the given day
+    pub fn is_valid_on(&self, date: NaiveDate) -> bool {
+        self.valid_from.is_none_or(|from| from <= date) && self.valid_to.is_none_or(|to| date < to)
+    }
+}
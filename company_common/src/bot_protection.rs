@@ -0,0 +1,83 @@
+use std::fmt;
+use std::str::FromStr;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use anyhow::{bail, Error};
+
+/// The kinds of bot protection that can stand between us and a page
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BotProtectionKind {
+    Recaptcha,
+    Hcaptcha,
+    CloudflareChallenge,
+    /// A captcha we couldn't tell the provider of
+    Captcha,
+    /// The page can only be seen after logging in
+    LoginWall,
+    /// Some protection we can't tell the kind of
+    Unknown,
+}
+
+impl BotProtectionKind {
+    pub fn as_str(&self) -> &str {
+        match self {
+            BotProtectionKind::Recaptcha => "recaptcha",
+            BotProtectionKind::Hcaptcha => "hcaptcha",
+            BotProtectionKind::CloudflareChallenge => "cloudflare_challenge",
+            BotProtectionKind::Captcha => "captcha",
+            BotProtectionKind::LoginWall => "login_wall",
+            BotProtectionKind::Unknown => "unknown",
+        }
+    }
+
+    /// Whether this kind is a captcha, as opposed to some other kind of wall
+    pub fn is_captcha(&self) -> bool {
+        match self {
+            BotProtectionKind::Recaptcha | BotProtectionKind::Hcaptcha | BotProtectionKind::CloudflareChallenge
+            | BotProtectionKind::Captcha => true,
+            BotProtectionKind::LoginWall | BotProtectionKind::Unknown => false,
+        }
+    }
+}
+
+impl fmt::Display for BotProtectionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for BotProtectionKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "recaptcha" => Ok(BotProtectionKind::Recaptcha),
+            "hcaptcha" => Ok(BotProtectionKind::Hcaptcha),
+            "cloudflare_challenge" => Ok(BotProtectionKind::CloudflareChallenge),
+            "captcha" => Ok(BotProtectionKind::Captcha),
+            "login_wall" => Ok(BotProtectionKind::LoginWall),
+            "unknown" => Ok(BotProtectionKind::Unknown),
+            _ => bail!("Unknown bot protection kind: {}", s),
+        }
+    }
+}
+
+/// Bot protection detected on a single URL
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BotProtection {
+    pub kind: BotProtectionKind,
+    /// None until it has been stored
+    pub detected_at: Option<DateTime<Utc>>,
+    /// What gave the protection away, e.g. the script or element that was found
+    pub evidence: Option<String>,
+}
+
+impl BotProtection {
+    pub fn new(kind: BotProtectionKind, evidence: Option<String>) -> BotProtection {
+        BotProtection {
+            kind,
+            detected_at: None,
+            evidence,
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use anyhow::{bail, Error};
+use url::{Host, Url};
+
+/// Query parameters that only say how a link was found, and never change the page.
+/// Parameters starting with "utm_" are always tracking parameters too.
+const TRACKING_PARAMETERS: &[&str] = &[
+    "gclid", "gclsrc", "dclid", "fbclid", "msclkid", "yclid", "twclid", "igshid", "li_fat_id",
+    "mc_cid", "mc_eid", "_hsenc", "_hsmi", "_ga", "_gl", "gh_src", "ref_src", "trk",
+];
+
+/// A URL in the one form we store it in, so the same page is never stored twice under different spellings.
+/// Canonicalizing:
+/// - assumes https when there's no scheme, and upgrades http to https
+/// - lowercases the host, converts international domain names to punycode, and drops a leading "www."
+/// - drops default ports, credentials, the fragment and tracking parameters (see TRACKING_PARAMETERS)
+/// - drops trailing slashes from the path
+///
+/// So "http://WWW.Acme.com/careers/?utm_source=google#jobs" becomes "https://acme.com/careers".
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct CanonicalUrl {
+    url: String,
+    host: String,
+    is_ip: bool,
+}
+
+impl CanonicalUrl {
+    pub fn parse(text: &str) -> Result<CanonicalUrl, Error> {
+        let text = text.trim();
+        if text.is_empty() {
+            bail!("Empty URL");
+        }
+        let url = match Url::parse(text) {
+            // "acme.com:8080/jobs" parses as a URL with the scheme "acme.com"
+            Ok(url) if !text.contains("://") && url.path().starts_with(|c: char| c.is_ascii_digit()) => {
+                Url::parse(&format!("https://{}", text))?
+            },
+            Ok(url) => url,
+            Err(_) => Url::parse(&format!("https://{}", text))?,
+        };
+        if url.scheme() != "http" && url.scheme() != "https" {
+            bail!("Unsupported URL scheme {} in {}", url.scheme(), text);
+        }
+        let (host, is_ip) = match url.host() {
+            Some(Host::Domain(domain)) => {
+                let domain = domain.trim_end_matches('.');
+                (domain.strip_prefix("www.").unwrap_or(domain).to_string(), false)
+            },
+            Some(Host::Ipv4(address)) => (address.to_string(), true),
+            Some(Host::Ipv6(address)) => (format!("[{}]", address), true),
+            None => bail!("URL {} has no host", text),
+        };
+        if host.is_empty() {
+            bail!("URL {} has no host", text);
+        }
+
+        let mut canonical = format!("https://{}", host);
+        // an explicit 443 on an http URL isn't the default port for http, but it is for the https we upgrade to
+        if let Some(port) = url.port().filter(|port| *port != 443) {
+            canonical.push_str(&format!(":{}", port));
+        }
+        canonical.push_str(url.path().trim_end_matches('/'));
+        let query: Vec<&str> = url.query().unwrap_or("").split('&')
+            .filter(|parameter| !parameter.is_empty() && !is_tracking_parameter(parameter))
+            .collect();
+        if !query.is_empty() {
+            canonical.push('?');
+            canonical.push_str(&query.join("&"));
+        }
+        Ok(CanonicalUrl { url: canonical, host, is_ip })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.url
+    }
+
+    /// The host, without a leading "www."
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// The part of the host a company registered, going by the public suffix list, e.g. "acme.co.uk"
+    /// for "careers.acme.co.uk". None for IP addresses and hosts that are themselves a public suffix.
+    pub fn registrable_domain(&self) -> Option<&str> {
+        if self.is_ip {
+            return None;
+        }
+        psl::domain_str(&self.host)
+    }
+
+    /// Whether both URLs are on the same registrable domain, e.g. careers.acme.com and acme.com
+    pub fn same_site(&self, other: &CanonicalUrl) -> bool {
+        match (self.registrable_domain(), other.registrable_domain()) {
+            (Some(domain), Some(other_domain)) => domain == other_domain,
+            _ => self.host == other.host,
+        }
+    }
+}
+
+fn is_tracking_parameter(parameter: &str) -> bool {
+    let name = parameter.split('=').next().unwrap_or(parameter).to_lowercase();
+    name.starts_with("utm_") || TRACKING_PARAMETERS.contains(&name.as_str())
+}
+
+impl fmt::Display for CanonicalUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.url)
+    }
+}
+
+impl FromStr for CanonicalUrl {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CanonicalUrl::parse(s)
+    }
+}
+
+impl TryFrom<String> for CanonicalUrl {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        CanonicalUrl::parse(&value)
+    }
+}
+
+impl From<CanonicalUrl> for String {
+    fn from(url: CanonicalUrl) -> Self {
+        url.url
+    }
+}
+
+/// The canonical form of a URL, or the URL as it is if it can't be parsed.
+/// For looking up stored URLs, and for storing links we keep even when they're malformed.
+pub fn canonicalize_url(url: &str) -> String {
+    match CanonicalUrl::parse(url) {
+        Ok(url) => url.url,
+        Err(_) => url.to_string(),
+    }
+}
@@ -0,0 +1,123 @@
+use std::fmt;
+use std::str::FromStr;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use anyhow::{bail, Error};
+use crate::website::{host_of, on_domain};
+
+/// The applicant tracking system a career page is hosted on
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AtsType {
+    Greenhouse,
+    Lever,
+    Workday,
+    SmartRecruiters,
+    Ashby,
+    Icims,
+    Jobvite,
+    BambooHr,
+    Taleo,
+    Workable,
+    /// An applicant tracking system we don't recognize
+    Other,
+}
+
+impl AtsType {
+    pub fn all() -> Vec<AtsType> {
+        vec![AtsType::Greenhouse, AtsType::Lever, AtsType::Workday, AtsType::SmartRecruiters, AtsType::Ashby,
+             AtsType::Icims, AtsType::Jobvite, AtsType::BambooHr, AtsType::Taleo, AtsType::Workable, AtsType::Other]
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            AtsType::Greenhouse => "greenhouse",
+            AtsType::Lever => "lever",
+            AtsType::Workday => "workday",
+            AtsType::SmartRecruiters => "smartrecruiters",
+            AtsType::Ashby => "ashby",
+            AtsType::Icims => "icims",
+            AtsType::Jobvite => "jobvite",
+            AtsType::BambooHr => "bamboohr",
+            AtsType::Taleo => "taleo",
+            AtsType::Workable => "workable",
+            AtsType::Other => "other",
+        }
+    }
+
+    /// The domains the applicant tracking system hosts career pages on
+    pub fn domains(&self) -> &'static [&'static str] {
+        match self {
+            AtsType::Greenhouse => &["greenhouse.io"],
+            AtsType::Lever => &["lever.co"],
+            AtsType::Workday => &["myworkdayjobs.com", "workday.com"],
+            AtsType::SmartRecruiters => &["smartrecruiters.com"],
+            AtsType::Ashby => &["ashbyhq.com"],
+            AtsType::Icims => &["icims.com"],
+            AtsType::Jobvite => &["jobvite.com"],
+            AtsType::BambooHr => &["bamboohr.com"],
+            AtsType::Taleo => &["taleo.net"],
+            AtsType::Workable => &["workable.com"],
+            AtsType::Other => &[],
+        }
+    }
+
+    /// The applicant tracking system a URL is on, if it's on one we recognize
+    pub fn detect(url: &str) -> Option<AtsType> {
+        let host = host_of(url);
+        AtsType::all().into_iter().find(|ats| on_domain(&host, ats.domains()))
+    }
+}
+
+impl fmt::Display for AtsType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for AtsType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match AtsType::all().into_iter().find(|ats| ats.as_str() == s) {
+            Some(ats) => Ok(ats),
+            None => bail!("Unknown ATS type: {}", s),
+        }
+    }
+}
+
+/// One of a company's career pages. Large employers can have one per region, brand or subsidiary.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CareerPage {
+    pub url: String,
+    /// What sets this career page apart from the company's others, e.g. a brand name
+    pub label: Option<String>,
+    pub region: Option<String>,
+    /// None if the page isn't on an applicant tracking system, or we don't know
+    pub ats_type: Option<AtsType>,
+    /// The status code the last time the page was checked
+    pub http_status: Option<u16>,
+    /// When the page was last checked
+    #[serde(alias = "verified_at")]
+    pub last_verified_at: Option<DateTime<Utc>>,
+    /// When a check last found the page different from what we had stored, e.g. a new status code
+    pub last_changed_at: Option<DateTime<Utc>>,
+    /// The page to use when only one is wanted. A company has at most one primary career page.
+    #[serde(default)]
+    pub is_primary: bool,
+}
+
+impl CareerPage {
+    /// A career page we know nothing about other than its URL, with the ATS detected from the URL
+    pub fn new(url: &str) -> CareerPage {
+        CareerPage {
+            url: url.to_string(),
+            label: None,
+            region: None,
+            ats_type: AtsType::detect(url),
+            http_status: None,
+            last_verified_at: None,
+            last_changed_at: None,
+            is_primary: false,
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+use anyhow::{bail, Error};
+use crate::{host_of, on_domain, CanonicalUrl, WebsiteRole, AGGREGATOR_DOMAINS, SOCIAL_DOMAINS};
+
+/// Domains that can never be a company's own site, because they host pages about many companies:
+/// job boards and directories (Aggregator) and social networks (Social).
+/// A domain covers its subdomains, so "linkedin.com" blocks "uk.linkedin.com" too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomainBlocklist {
+    domains: BTreeMap<String, WebsiteRole>,
+}
+
+impl Default for DomainBlocklist {
+    /// The domains we know about out of the box, see AGGREGATOR_DOMAINS and SOCIAL_DOMAINS
+    fn default() -> Self {
+        let mut blocklist = DomainBlocklist::empty();
+        for domain in AGGREGATOR_DOMAINS {
+            blocklist.domains.insert(domain.to_string(), WebsiteRole::Aggregator);
+        }
+        for domain in SOCIAL_DOMAINS {
+            blocklist.domains.insert(domain.to_string(), WebsiteRole::Social);
+        }
+        blocklist
+    }
+}
+
+impl DomainBlocklist {
+    pub fn empty() -> DomainBlocklist {
+        DomainBlocklist { domains: BTreeMap::new() }
+    }
+
+    /// Blocks a domain, e.g. "indeed.com". Only aggregators and social networks can be blocked.
+    pub fn add(&mut self, domain: &str, role: WebsiteRole) -> Result<(), Error> {
+        if !matches!(role, WebsiteRole::Aggregator | WebsiteRole::Social) {
+            bail!("Only aggregator and social domains can be blocked, not {} ({})", domain, role);
+        }
+        let domain = normalize_domain(domain)?;
+        self.domains.insert(domain, role);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, domain: &str) -> bool {
+        match normalize_domain(domain) {
+            Ok(domain) => self.domains.remove(&domain).is_some(),
+            Err(_) => false,
+        }
+    }
+
+    /// Why the URL can't be a company's own site, or None if it could be
+    pub fn blocked_role(&self, url: &str) -> Option<WebsiteRole> {
+        let host = match CanonicalUrl::parse(url) {
+            Ok(url) => url.host().to_string(),
+            Err(_) => host_of(url),
+        };
+        self.domains.iter()
+            .find(|(domain, _)| on_domain(&host, &[domain.as_str()]))
+            .map(|(_, role)| *role)
+    }
+
+    pub fn is_blocked(&self, url: &str) -> bool {
+        self.blocked_role(url).is_some()
+    }
+
+    /// The blocked domains and why, in alphabetical order
+    pub fn domains(&self) -> impl Iterator<Item = (&str, WebsiteRole)> {
+        self.domains.iter().map(|(domain, role)| (domain.as_str(), *role))
+    }
+
+    pub fn len(&self) -> usize {
+        self.domains.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.domains.is_empty()
+    }
+}
+
+/// The host of a domain typed as a domain or a URL, e.g. "indeed.com" for "https://www.Indeed.com/"
+pub fn normalize_domain(domain: &str) -> Result<String, Error> {
+    let url = CanonicalUrl::parse(domain)?;
+    Ok(url.host().to_string())
+}
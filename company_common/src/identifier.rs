@@ -0,0 +1,127 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use anyhow::{bail, Error};
+use crate::CanonicalUrl;
+
+/// The systems a company can be identified in
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IdentifierKind {
+    /// The SEC's Central Index Key
+    Cik,
+    /// Legal Entity Identifier, 20 characters
+    Lei,
+    /// A stock ticker, optionally prefixed by its exchange, e.g. "NASDAQ:AAPL"
+    Ticker,
+    /// The registrable domain of the company's own site, e.g. "acme.co.uk"
+    Domain,
+    /// UK Companies House company number
+    CompaniesHouse,
+    /// An id given to the company by one of our users, e.g. from their own spreadsheet
+    UserId,
+}
+
+impl IdentifierKind {
+    pub fn all() -> Vec<IdentifierKind> {
+        vec![IdentifierKind::Cik, IdentifierKind::Lei, IdentifierKind::Ticker, IdentifierKind::Domain,
+             IdentifierKind::CompaniesHouse, IdentifierKind::UserId]
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            IdentifierKind::Cik => "cik",
+            IdentifierKind::Lei => "lei",
+            IdentifierKind::Ticker => "ticker",
+            IdentifierKind::Domain => "domain",
+            IdentifierKind::CompaniesHouse => "companies_house",
+            IdentifierKind::UserId => "user_id",
+        }
+    }
+}
+
+impl fmt::Display for IdentifierKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for IdentifierKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match IdentifierKind::all().into_iter().find(|kind| kind.as_str() == s) {
+            Some(kind) => Ok(kind),
+            None => bail!("Unknown identifier kind: {}", s),
+        }
+    }
+}
+
+/// An identifier of a company in some other system, e.g. its LEI.
+/// Values are normalized when the identifier is made, so the same company is always found by the same value:
+/// CIKs lose their leading zeros, LEIs, tickers and Companies House numbers are uppercased,
+/// Companies House numbers are zero padded to 8 characters (after their prefix, if they have one),
+/// and domains are reduced to their registrable domain.
+/// Shows as "kind:value", e.g. "lei:5493001KJTIIGC8Y1R12", and parses from the same.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ExternalIdentifier {
+    pub kind: IdentifierKind,
+    pub value: String,
+}
+
+impl ExternalIdentifier {
+    pub fn new(kind: IdentifierKind, value: &str) -> Result<ExternalIdentifier, Error> {
+        let value = value.trim();
+        if value.is_empty() {
+            bail!("Empty {} identifier", kind);
+        }
+        let value = match kind {
+            IdentifierKind::Cik => value.parse::<i32>().map_err(|_| Error::msg(format!("Invalid CIK: {}", value)))?.to_string(),
+            IdentifierKind::Lei => {
+                let lei = value.to_uppercase();
+                if lei.len() != 20 || !lei.chars().all(|c| c.is_ascii_alphanumeric()) {
+                    bail!("Invalid LEI: {}", value);
+                }
+                lei
+            },
+            IdentifierKind::Ticker => value.to_uppercase(),
+            IdentifierKind::Domain => {
+                let url = CanonicalUrl::parse(value)?;
+                url.registrable_domain().unwrap_or(url.host()).to_string()
+            },
+            IdentifierKind::CompaniesHouse => {
+                let number = value.to_uppercase();
+                if number.len() > 8 || !number.chars().all(|c| c.is_ascii_alphanumeric()) {
+                    bail!("Invalid Companies House number: {}", value);
+                }
+                // Scottish, Northern Irish etc. companies have a two letter prefix, e.g. SC012345
+                match number.char_indices().find(|(_, c)| c.is_ascii_digit()) {
+                    Some((2, _)) => format!("{}{:0>6}", &number[..2], &number[2..]),
+                    _ => format!("{:0>8}", number),
+                }
+            },
+            IdentifierKind::UserId => value.to_string(),
+        };
+        Ok(ExternalIdentifier { kind, value })
+    }
+
+    pub fn cik(cik: i32) -> ExternalIdentifier {
+        ExternalIdentifier { kind: IdentifierKind::Cik, value: cik.to_string() }
+    }
+}
+
+impl fmt::Display for ExternalIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.kind, self.value)
+    }
+}
+
+impl FromStr for ExternalIdentifier {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((kind, value)) => ExternalIdentifier::new(IdentifierKind::from_str(kind)?, value),
+            None => bail!("Identifier {} isn't of the form kind:value", s),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+use std::str::FromStr;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use anyhow::{bail, Error};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EmploymentType {
+    FullTime,
+    PartTime,
+    Contract,
+    Temporary,
+    Internship,
+    Other,
+}
+
+impl EmploymentType {
+    pub fn all() -> Vec<EmploymentType> {
+        vec![EmploymentType::FullTime, EmploymentType::PartTime, EmploymentType::Contract,
+             EmploymentType::Temporary, EmploymentType::Internship, EmploymentType::Other]
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            EmploymentType::FullTime => "full_time",
+            EmploymentType::PartTime => "part_time",
+            EmploymentType::Contract => "contract",
+            EmploymentType::Temporary => "temporary",
+            EmploymentType::Internship => "internship",
+            EmploymentType::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for EmploymentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for EmploymentType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match EmploymentType::all().into_iter().find(|employment_type| employment_type.as_str() == s) {
+            Some(employment_type) => Ok(employment_type),
+            None => bail!("Unknown employment type: {}", s),
+        }
+    }
+}
+
+/// A job posting as scraped from a company's career page or job board
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct JobPosting {
+    /// The posting's id on the site it was scraped from; unique per company and source
+    pub external_id: String,
+    pub url: String,
+    pub title: String,
+    /// The description as scraped, e.g. HTML
+    pub raw_description: Option<String>,
+    /// The description as plain text
+    pub description: Option<String>,
+    pub location: Option<String>,
+    pub department: Option<String>,
+    pub employment_type: Option<EmploymentType>,
+    /// When the posting says it was posted, if it says
+    pub posted_date: Option<NaiveDate>,
+}
+
+impl JobPosting {
+    pub fn new(external_id: &str, url: &str, title: &str) -> JobPosting {
+        JobPosting {
+            external_id: external_id.to_string(),
+            url: url.to_string(),
+            title: title.to_string(),
+            raw_description: None,
+            description: None,
+            location: None,
+            department: None,
+            employment_type: None,
+            posted_date: None,
+        }
+    }
+}
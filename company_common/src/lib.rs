@@ -1,6 +1,37 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use serde::{Deserialize, Serialize};
 use anyhow::{bail, Error};
+use chrono::NaiveDate;
+
+mod provenance;
+mod bot_protection;
+mod website;
+mod tag;
+mod trigram;
+mod career_page;
+mod job_posting;
+mod profile;
+mod relationship;
+mod canonical_url;
+mod domain_blocklist;
+mod patch;
+mod identifier;
+mod alias;
+
+pub use provenance::*;
+pub use bot_protection::*;
+pub use website::*;
+pub use tag::*;
+pub use trigram::*;
+pub use career_page::*;
+pub use job_posting::*;
+pub use profile::*;
+pub use relationship::*;
+pub use canonical_url::*;
+pub use domain_blocklist::*;
+pub use patch::*;
+pub use identifier::*;
+pub use alias::*;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Company {
@@ -15,10 +46,34 @@ pub struct Company {
 pub struct ProcessedCompany {
     pub cik: Option<i32>,
     pub company_aliases: HashSet<String>,
-    pub websites: Option<Vec<(String, String)>>,
-    pub career_page: Option<String>,
+    pub websites: Option<Vec<Website>>,
+    pub career_pages: Option<Vec<CareerPage>>,
+    /// In the string form of Tag, e.g. "industry:fintech"; CompanyDataStore::add_company rejects tags that don't parse
     pub tags: Option<Vec<String>>,
-    pub has_captcha: Option<bool>,
+    /// Bot protection detected on the company's pages, by URL
+    #[serde(default)]
+    pub bot_protection: HashMap<String, Vec<BotProtection>>,
+    /// Where each alias came from, by alias
+    #[serde(default)]
+    pub alias_provenance: HashMap<String, Provenance>,
+    /// Where each website came from, by link
+    #[serde(default)]
+    pub website_provenance: HashMap<String, Provenance>,
+    /// Where each career page came from, by URL
+    #[serde(default)]
+    pub career_page_provenance: HashMap<String, Provenance>,
+    /// Relationships with other companies, on either side
+    #[serde(default)]
+    pub relationships: Vec<CompanyRelationship>,
+    /// Identifiers of the company in other systems, besides its CIK
+    #[serde(default)]
+    pub identifiers: Vec<ExternalIdentifier>,
+    /// The alias to show and search for the company by, see get_company_name
+    #[serde(default)]
+    pub primary_name: Option<String>,
+    /// What each alias is and when it was used, by alias
+    #[serde(default)]
+    pub alias_metadata: HashMap<String, AliasMetadata>,
     // ticker: Option<String> // probably not necessary
 }
 
@@ -32,27 +87,143 @@ impl Company {
             file_name
         }
     }
+
+    /// The day the filing was made, from company.idx's YYYY-MM-DD date column
+    pub fn filing_date(&self) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(&self.date, "%Y-%m-%d").ok()
+    }
 }
 
 impl ProcessedCompany {
     pub fn new(cik: Option<i32>,
                company_aliases: HashSet<String>,
-               websites: Option<Vec<(String, String)>>,
-               career_page: Option<String>,
-    tags: Option<Vec<String>>,
-    has_captcha: Option<bool>) -> ProcessedCompany {
+               websites: Option<Vec<Website>>,
+               career_pages: Option<Vec<CareerPage>>,
+    tags: Option<Vec<String>>) -> ProcessedCompany {
         ProcessedCompany {
             cik,
             company_aliases,
             websites,
-            career_page,
+            career_pages,
             tags,
-            has_captcha,
+            bot_protection: HashMap::new(),
+            alias_provenance: HashMap::new(),
+            website_provenance: HashMap::new(),
+            career_page_provenance: HashMap::new(),
+            relationships: Vec::new(),
+            identifiers: Vec::new(),
+            primary_name: None,
+            alias_metadata: HashMap::new(),
         }
     }
 
+    /// Every identifier of the company, its CIK included
+    pub fn get_identifiers(&self) -> Vec<ExternalIdentifier> {
+        let mut identifiers: Vec<ExternalIdentifier> = self.cik.map(ExternalIdentifier::cik).into_iter().collect();
+        for identifier in &self.identifiers {
+            if !identifiers.contains(identifier) {
+                identifiers.push(identifier.clone());
+            }
+        }
+        identifiers
+    }
+
+    /// Adds an alias, recording where it came from
+    pub fn add_alias(&mut self, alias: String, provenance: Provenance) {
+        self.alias_provenance.insert(alias.clone(), provenance);
+        self.company_aliases.insert(alias);
+    }
+
+    /// Records what an alias is and when it was used, adding the alias if the company doesn't have it
+    pub fn set_alias_metadata(&mut self, alias: &str, metadata: AliasMetadata) {
+        self.company_aliases.insert(alias.to_string());
+        self.alias_metadata.insert(alias.to_string(), metadata);
+    }
+
+    /// What the given alias is, or an unknown kind with no dates if it wasn't recorded
+    pub fn get_alias_metadata(&self, alias: &str) -> AliasMetadata {
+        self.alias_metadata.get(alias).copied().unwrap_or_default()
+    }
+
+    /// Makes the alias the company's primary name, adding it if the company doesn't have it
+    pub fn set_primary_name(&mut self, name: &str) {
+        self.company_aliases.insert(name.to_string());
+        self.primary_name = Some(name.to_string());
+    }
+
+    /// Where the given alias came from, or unknown if it wasn't recorded
+    pub fn get_alias_provenance(&self, alias: &str) -> Provenance {
+        self.alias_provenance.get(alias).cloned().unwrap_or_else(Provenance::unknown)
+    }
+
+    /// Where the given website came from, or unknown if it wasn't recorded
+    pub fn get_website_provenance(&self, link: &str) -> Provenance {
+        self.website_provenance.get(link).cloned().unwrap_or_else(Provenance::unknown)
+    }
+
+    /// Records bot protection detected on one of the company's pages
+    pub fn add_bot_protection(&mut self, url: &str, protection: BotProtection) {
+        let protections = self.bot_protection.entry(url.to_string()).or_default();
+        protections.retain(|existing| existing.kind != protection.kind);
+        protections.push(protection);
+    }
+
+    /// The bot protection detected on the given URL, if any
+    pub fn get_bot_protection(&self, url: &str) -> &[BotProtection] {
+        match self.bot_protection.get(url) {
+            Some(protections) => protections,
+            None => &[],
+        }
+    }
+
+    pub fn is_bot_protected(&self, url: &str) -> bool {
+        !self.get_bot_protection(url).is_empty()
+    }
+
+    /// Whether a captcha was detected on any of the company's pages
+    pub fn has_captcha(&self) -> bool {
+        self.bot_protection.values().flatten().any(|protection| protection.kind.is_captcha())
+    }
+
+    /// Whether a captcha was detected on any of the career pages
+    pub fn career_page_has_captcha(&self) -> bool {
+        self.career_pages.iter().flatten().any(|career_page| {
+            self.get_bot_protection(&career_page.url).iter().any(|protection| protection.kind.is_captcha())
+        })
+    }
+
+    /// The primary career page, or the first one if none is marked primary
+    pub fn get_primary_career_page(&self) -> Option<&CareerPage> {
+        let career_pages = self.career_pages.as_ref()?;
+        career_pages.iter().find(|career_page| career_page.is_primary).or(career_pages.first())
+    }
+
+    /// Where the given career page came from, or unknown if it wasn't recorded
+    pub fn get_career_page_provenance(&self, url: &str) -> Provenance {
+        self.career_page_provenance.get(url).cloned().unwrap_or_else(Provenance::unknown)
+    }
+
+    /// The single name to show and search for the company by: its primary name if it has one.
+    /// Otherwise the best of its aliases, the same every time: names still in use before dropped ones,
+    /// legal names before brands and tickers, newer names before older ones, then by the bytes of the name
+    /// (which the database matches with COLLATE "C", whatever its own collation).
     pub fn get_company_name(&self) -> Result<String, Error> {
-        match self.company_aliases.iter().next() {
+        if let Some(name) = &self.primary_name {
+            return Ok(name.clone());
+        }
+        let today = chrono::Utc::now().date_naive();
+        let kind_rank = |kind: AliasKind| match kind {
+            AliasKind::Legal => 0,
+            AliasKind::Brand => 1,
+            AliasKind::Unknown => 2,
+            AliasKind::Ticker => 3,
+            AliasKind::Former => 4,
+        };
+        let best = self.company_aliases.iter().min_by_key(|alias| {
+            let metadata = self.get_alias_metadata(alias);
+            (!metadata.is_valid_on(today), kind_rank(metadata.kind), std::cmp::Reverse(metadata.valid_from), alias.as_str())
+        });
+        match best {
             Some(name) => Ok(name.clone()),
             None => bail!("No company name found"),
         }
@@ -0,0 +1,173 @@
+use serde::{Deserialize, Serialize};
+use crate::{canonicalize_url, CareerPage, ProcessedCompany, Provenance, Tag, Website};
+
+/// What to do with a company's career pages
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum CareerPageUpdate {
+    /// Makes this the company's only career page, and its primary one
+    Set(CareerPage),
+    /// Removes all of the company's career pages
+    Clear,
+}
+
+/// A set of changes to a company, applied all at once by CompanyDataStore::update_company.
+/// Removals are applied before additions, so removing and adding the same alias keeps it.
+///
+/// ```ignore
+/// let patch = ProcessedCompanyPatch::new()
+///     .add_alias("Acme")
+///     .remove_tag(Tag::stage("seed")?)
+///     .set_career_page(CareerPage::new("https://acme.com/careers"));
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct ProcessedCompanyPatch {
+    #[serde(default)]
+    pub add_aliases: Vec<String>,
+    #[serde(default)]
+    pub remove_aliases: Vec<String>,
+    /// Makes this alias the primary name, adding it if the company doesn't have it
+    #[serde(default)]
+    pub primary_name: Option<String>,
+    #[serde(default)]
+    pub add_tags: Vec<Tag>,
+    #[serde(default)]
+    pub remove_tags: Vec<Tag>,
+    #[serde(default)]
+    pub add_websites: Vec<Website>,
+    /// The URLs of the websites to remove
+    #[serde(default)]
+    pub remove_websites: Vec<String>,
+    /// None leaves the career pages as they are
+    #[serde(default)]
+    pub career_page: Option<CareerPageUpdate>,
+    /// Where the added aliases, websites and career page came from; unknown if None
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+}
+
+impl ProcessedCompanyPatch {
+    pub fn new() -> ProcessedCompanyPatch {
+        ProcessedCompanyPatch::default()
+    }
+
+    pub fn add_alias(mut self, alias: &str) -> Self {
+        self.add_aliases.push(alias.to_string());
+        self
+    }
+
+    pub fn remove_alias(mut self, alias: &str) -> Self {
+        self.remove_aliases.push(alias.to_string());
+        self
+    }
+
+    pub fn set_primary_name(mut self, name: &str) -> Self {
+        self.primary_name = Some(name.to_string());
+        self
+    }
+
+    pub fn add_tag(mut self, tag: Tag) -> Self {
+        self.add_tags.push(tag);
+        self
+    }
+
+    pub fn remove_tag(mut self, tag: Tag) -> Self {
+        self.remove_tags.push(tag);
+        self
+    }
+
+    pub fn add_website(mut self, website: Website) -> Self {
+        self.add_websites.push(website);
+        self
+    }
+
+    pub fn remove_website(mut self, url: &str) -> Self {
+        self.remove_websites.push(url.to_string());
+        self
+    }
+
+    pub fn set_career_page(mut self, career_page: CareerPage) -> Self {
+        self.career_page = Some(CareerPageUpdate::Set(career_page));
+        self
+    }
+
+    pub fn clear_career_page(mut self) -> Self {
+        self.career_page = Some(CareerPageUpdate::Clear);
+        self
+    }
+
+    pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = Some(provenance);
+        self
+    }
+
+    /// Whether applying the patch would change nothing
+    pub fn is_empty(&self) -> bool {
+        self.add_aliases.is_empty() && self.remove_aliases.is_empty() && self.primary_name.is_none() && self.add_tags.is_empty()
+            && self.remove_tags.is_empty() && self.add_websites.is_empty() && self.remove_websites.is_empty()
+            && self.career_page.is_none()
+    }
+
+    /// Applies the patch to a company in memory, the same way update_company applies it to a stored one,
+    /// except that websites on blocked domains are added too
+    pub fn apply(&self, company: &mut ProcessedCompany) {
+        let provenance = self.provenance.clone().unwrap_or_else(Provenance::unknown);
+        for alias in &self.remove_aliases {
+            company.company_aliases.remove(alias);
+            company.alias_provenance.remove(alias);
+            company.alias_metadata.remove(alias);
+            if company.primary_name.as_ref() == Some(alias) {
+                company.primary_name = None;
+            }
+        }
+        for alias in self.add_aliases.iter().chain(&self.primary_name) {
+            if !company.company_aliases.contains(alias) {
+                company.add_alias(alias.clone(), provenance.clone());
+            }
+        }
+        if let Some(name) = &self.primary_name {
+            company.primary_name = Some(name.clone());
+        }
+
+        let removed: Vec<String> = self.remove_tags.iter().map(|tag| tag.to_string()).collect();
+        let tags = company.tags.get_or_insert_with(Vec::new);
+        tags.retain(|tag| !removed.contains(tag));
+        for tag in &self.add_tags {
+            let tag = tag.to_string();
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        if tags.is_empty() {
+            company.tags = None;
+        }
+
+        let removed: Vec<String> = self.remove_websites.iter().map(|url| canonicalize_url(url)).collect();
+        let websites = company.websites.get_or_insert_with(Vec::new);
+        websites.retain(|website| !removed.contains(&canonicalize_url(&website.url)));
+        company.website_provenance.retain(|url, _| !removed.contains(&canonicalize_url(url)));
+        for website in &self.add_websites {
+            let url = canonicalize_url(&website.url);
+            if !websites.iter().any(|existing| canonicalize_url(&existing.url) == url) {
+                websites.push(Website { url: url.clone(), ..website.clone() });
+                company.website_provenance.insert(url, provenance.clone());
+            }
+        }
+        if websites.is_empty() {
+            company.websites = None;
+        }
+
+        match &self.career_page {
+            Some(CareerPageUpdate::Set(career_page)) => {
+                let url = canonicalize_url(&career_page.url);
+                company.career_pages = Some(vec![CareerPage { url: url.clone(), is_primary: true, ..career_page.clone() }]);
+                company.career_page_provenance.clear();
+                company.career_page_provenance.insert(url, provenance);
+            },
+            Some(CareerPageUpdate::Clear) => {
+                company.career_pages = None;
+                company.career_page_provenance.clear();
+            },
+            None => {},
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use anyhow::{bail, Error};
+use crate::{Tag, TagNamespace};
+
+/// Where a candidate is willing to work
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RemotePreference {
+    OnSite,
+    Hybrid,
+    Remote,
+    #[default]
+    Any,
+}
+
+impl RemotePreference {
+    pub fn as_str(&self) -> &str {
+        match self {
+            RemotePreference::OnSite => "on_site",
+            RemotePreference::Hybrid => "hybrid",
+            RemotePreference::Remote => "remote",
+            RemotePreference::Any => "any",
+        }
+    }
+}
+
+impl fmt::Display for RemotePreference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for RemotePreference {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "on_site" => Ok(RemotePreference::OnSite),
+            "hybrid" => Ok(RemotePreference::Hybrid),
+            "remote" => Ok(RemotePreference::Remote),
+            "any" => Ok(RemotePreference::Any),
+            _ => bail!("Unknown remote preference: {}", s),
+        }
+    }
+}
+
+/// One of the job seekers sharing the data store, and what they're looking for
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct CandidateProfile {
+    /// Unique short name, also used as the profile's tag namespace ("user:<handle>:")
+    pub handle: String,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub target_titles: Vec<String>,
+    pub locations: Vec<String>,
+    pub remote_preference: RemotePreference,
+    /// The lowest yearly salary worth applying for, in salary_currency
+    pub salary_floor: Option<i32>,
+    /// ISO 4217 code, e.g. "USD"
+    pub salary_currency: Option<String>,
+    /// Where the candidate may work without sponsorship, e.g. "US citizen" or "EU"
+    pub work_authorization: Vec<String>,
+    /// Sids of companies the candidate never wants to apply to
+    pub excluded_companies: Vec<i32>,
+}
+
+impl CandidateProfile {
+    pub fn new(handle: &str) -> Result<CandidateProfile, Error> {
+        let profile = CandidateProfile {
+            handle: handle.to_string(),
+            ..Default::default()
+        };
+        // the handle has to make a valid tag namespace
+        profile.tag("profile")?;
+        Ok(profile)
+    }
+
+    /// The namespace of the profile's own tags
+    pub fn tag_namespace(&self) -> TagNamespace {
+        TagNamespace::User(self.handle.clone())
+    }
+
+    /// One of the profile's own tags, e.g. "user:alice:dream-jobs"
+    pub fn tag(&self, value: &str) -> Result<Tag, Error> {
+        Tag::new(Some(self.tag_namespace()), value)
+    }
+
+    pub fn is_excluded(&self, sid: &i32) -> bool {
+        self.excluded_companies.contains(sid)
+    }
+}
@@ -0,0 +1,81 @@
+use std::fmt;
+use std::str::FromStr;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use anyhow::{bail, Error};
+
+/// Where a fact about a company (an alias, website, career page, ...) came from
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FactSource {
+    /// SEC EDGAR filings, e.g. company.idx
+    Sec,
+    /// A search engine results page
+    Serp,
+    /// A bulk import from a file
+    Import,
+    /// Entered or edited by hand
+    Manual,
+    /// Recorded before provenance was tracked
+    Unknown,
+}
+
+impl FactSource {
+    pub fn as_str(&self) -> &str {
+        match self {
+            FactSource::Sec => "sec",
+            FactSource::Serp => "serp",
+            FactSource::Import => "import",
+            FactSource::Manual => "manual",
+            FactSource::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for FactSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for FactSource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sec" => Ok(FactSource::Sec),
+            "serp" => Ok(FactSource::Serp),
+            "import" => Ok(FactSource::Import),
+            "manual" => Ok(FactSource::Manual),
+            "unknown" => Ok(FactSource::Unknown),
+            _ => bail!("Unknown fact source: {}", s),
+        }
+    }
+}
+
+/// Where a single stored fact came from, what produced it, and how much we trust it
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Provenance {
+    pub source: FactSource,
+    /// The component (and version) that produced the fact, e.g. "website_discovery/0.1.0"
+    pub producer: String,
+    /// From 0.0 (a guess) to 1.0 (authoritative)
+    pub confidence: f32,
+    /// When the fact was stored; None until it has been
+    pub recorded_at: Option<DateTime<Utc>>,
+}
+
+impl Provenance {
+    pub fn new(source: FactSource, producer: &str, confidence: f32) -> Provenance {
+        Provenance {
+            source,
+            producer: producer.to_string(),
+            confidence: confidence.clamp(0.0, 1.0),
+            recorded_at: None,
+        }
+    }
+
+    /// For facts recorded before provenance was tracked, or whose origin isn't known
+    pub fn unknown() -> Provenance {
+        Provenance::new(FactSource::Unknown, "unknown", 0.0)
+    }
+}
@@ -0,0 +1,86 @@
+use std::fmt;
+use std::str::FromStr;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use anyhow::{bail, Error};
+
+/// How the child company of a relationship belongs to the parent
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RelationshipKind {
+    /// The child is a subsidiary of the parent
+    Subsidiary,
+    /// The child is a brand or trading name of the parent
+    Brand,
+    /// The child was acquired by the parent
+    AcquiredBy,
+}
+
+impl RelationshipKind {
+    pub fn all() -> Vec<RelationshipKind> {
+        vec![RelationshipKind::Subsidiary, RelationshipKind::Brand, RelationshipKind::AcquiredBy]
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            RelationshipKind::Subsidiary => "subsidiary",
+            RelationshipKind::Brand => "brand",
+            RelationshipKind::AcquiredBy => "acquired_by",
+        }
+    }
+}
+
+impl fmt::Display for RelationshipKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for RelationshipKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match RelationshipKind::all().into_iter().find(|kind| kind.as_str() == s) {
+            Some(kind) => Ok(kind),
+            None => bail!("Unknown relationship kind: {}", s),
+        }
+    }
+}
+
+/// A link between two companies, e.g. a subsidiary and its parent.
+/// Unbounded ends of the validity period are None; valid_to is the first day it no longer holds.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CompanyRelationship {
+    /// The subsidiary, brand or acquired company
+    pub child_sid: i32,
+    /// The parent, brand owner or acquirer
+    pub parent_sid: i32,
+    pub kind: RelationshipKind,
+    pub valid_from: Option<NaiveDate>,
+    pub valid_to: Option<NaiveDate>,
+}
+
+impl CompanyRelationship {
+    pub fn new(child_sid: i32, parent_sid: i32, kind: RelationshipKind) -> CompanyRelationship {
+        CompanyRelationship {
+            child_sid,
+            parent_sid,
+            kind,
+            valid_from: None,
+            valid_to: None,
+        }
+    }
+
+    /// Whether the relationship held on the given day
+    pub fn is_valid_on(&self, date: NaiveDate) -> bool {
+        self.valid_from.is_none_or(|from| from <= date) && self.valid_to.is_none_or(|to| date < to)
+    }
+
+    /// The company on the other side of the relationship from the given one
+    pub fn other_sid(&self, sid: i32) -> i32 {
+        if self.child_sid == sid {
+            self.parent_sid
+        } else {
+            self.child_sid
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use anyhow::{bail, Error};
+
+/// The namespace a tag belongs to, written before the tag's value, e.g. "industry:fintech"
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub enum TagNamespace {
+    Industry,
+    /// Headcount bucket, e.g. "size:51-200"
+    Size,
+    /// Funding stage, e.g. "stage:series-a"
+    Stage,
+    /// A user's own tags, e.g. "user:alice:shortlist"
+    User(String),
+}
+
+impl TagNamespace {
+    /// The namespace as it's written before a tag's value, without the trailing colon
+    pub fn prefix(&self) -> String {
+        match self {
+            TagNamespace::Industry => "industry".to_string(),
+            TagNamespace::Size => "size".to_string(),
+            TagNamespace::Stage => "stage".to_string(),
+            TagNamespace::User(user) => format!("user:{}", user),
+        }
+    }
+}
+
+impl fmt::Display for TagNamespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.prefix())
+    }
+}
+
+/// A company tag. Tags without a namespace are the flat tags from before namespaces existed.
+/// Tags are stored in CompanyTags as their string form, see Display and FromStr.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Tag {
+    pub namespace: Option<TagNamespace>,
+    pub value: String,
+}
+
+impl Tag {
+    pub fn new(namespace: Option<TagNamespace>, value: &str) -> Result<Tag, Error> {
+        let value = value.trim();
+        if value.is_empty() {
+            bail!("Tag value can't be empty");
+        }
+        if value.contains(':') {
+            bail!("Tag value can't contain ':': {}", value);
+        }
+        if let Some(TagNamespace::User(user)) = &namespace {
+            if user.is_empty() || user.contains(':') {
+                bail!("Invalid user in tag namespace: {:?}", user);
+            }
+        }
+        Ok(Tag {
+            namespace,
+            value: value.to_string(),
+        })
+    }
+
+    pub fn industry(value: &str) -> Result<Tag, Error> {
+        Tag::new(Some(TagNamespace::Industry), value)
+    }
+
+    pub fn size(value: &str) -> Result<Tag, Error> {
+        Tag::new(Some(TagNamespace::Size), value)
+    }
+
+    pub fn stage(value: &str) -> Result<Tag, Error> {
+        Tag::new(Some(TagNamespace::Stage), value)
+    }
+
+    pub fn user(user: &str, value: &str) -> Result<Tag, Error> {
+        Tag::new(Some(TagNamespace::User(user.to_string())), value)
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.namespace {
+            Some(namespace) => write!(f, "{}:{}", namespace, self.value),
+            None => write!(f, "{}", self.value),
+        }
+    }
+}
+
+impl FromStr for Tag {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        match parts.as_slice() {
+            [value] => Tag::new(None, value),
+            ["industry", value] => Tag::industry(value),
+            ["size", value] => Tag::size(value),
+            ["stage", value] => Tag::stage(value),
+            ["user", user, value] => Tag::user(user, value),
+            _ => bail!("Unknown tag namespace: {}", s),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use std::collections::HashSet;
+
+/// Normalizes an alias for fuzzy matching: lowercase, with runs of anything other than letters
+/// and digits collapsed to a single space. Must match normalize_company_alias in the data store's SQL.
+pub fn normalize_alias(alias: &str) -> String {
+    alias.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// The trigrams of some text, the same way pg_trgm extracts them: each word is lowercased
+/// and padded with two spaces in front and one behind
+pub fn trigrams(text: &str) -> HashSet<String> {
+    let mut trigrams = HashSet::new();
+    for word in normalize_alias(text).split(' ').filter(|word| !word.is_empty()) {
+        let padded: Vec<char> = format!("  {} ", word).chars().collect();
+        for window in padded.windows(3) {
+            trigrams.insert(window.iter().collect());
+        }
+    }
+    trigrams
+}
+
+/// How alike two texts are, from 0 (no trigrams shared) to 1 (the same trigrams),
+/// the same as pg_trgm's similarity()
+pub fn trigram_similarity(a: &str, b: &str) -> f32 {
+    let a = trigrams(a);
+    let b = trigrams(b);
+    let shared = a.intersection(&b).count();
+    let total = a.len() + b.len() - shared;
+    if total == 0 {
+        return 0.0;
+    }
+    shared as f32 / total as f32
+}
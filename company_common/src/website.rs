@@ -0,0 +1,152 @@
+use std::fmt;
+use std::str::FromStr;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use anyhow::{bail, Error};
+use crate::AtsType;
+
+/// What a website is to the company it's attached to
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum WebsiteRole {
+    /// The company's own main site
+    OfficialHomepage,
+    /// The company's own careers site or page
+    Careers,
+    /// The company's board on an applicant tracking system, e.g. Greenhouse or Lever
+    AtsBoard,
+    /// A job board or directory listing many companies, e.g. Indeed
+    Aggregator,
+    /// A social media profile
+    Social,
+    /// An article about the company
+    News,
+    #[default]
+    Unknown,
+}
+
+/// Domains of job boards and company directories
+pub(crate) const AGGREGATOR_DOMAINS: &[&str] = &[
+    "indeed.com", "glassdoor.com", "ziprecruiter.com", "monster.com", "simplyhired.com",
+    "builtin.com", "wellfound.com", "crunchbase.com", "zoominfo.com", "bloomberg.com", "wikipedia.org",
+    "careerbuilder.com", "dice.com", "pitchbook.com", "dnb.com", "yelp.com", "comparably.com",
+];
+
+pub(crate) const SOCIAL_DOMAINS: &[&str] = &[
+    "linkedin.com", "facebook.com", "twitter.com", "x.com", "instagram.com", "youtube.com", "tiktok.com",
+    "pinterest.com", "threads.net",
+];
+
+const NEWS_DOMAINS: &[&str] = &[
+    "reuters.com", "cnbc.com", "forbes.com", "businesswire.com", "prnewswire.com", "globenewswire.com",
+];
+
+/// The lowercased host of a URL, e.g. "careers.acme.com" for "https://Careers.Acme.com/jobs?page=2"
+pub fn host_of(url: &str) -> String {
+    let without_scheme = url.split("://").nth(1).unwrap_or(url);
+    let host = without_scheme.split(['/', '?', '#']).next().unwrap_or("");
+    host.to_lowercase()
+}
+
+pub(crate) fn on_domain(host: &str, domains: &[&str]) -> bool {
+    domains.iter().any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)))
+}
+
+impl WebsiteRole {
+    pub fn as_str(&self) -> &str {
+        match self {
+            WebsiteRole::OfficialHomepage => "official_homepage",
+            WebsiteRole::Careers => "careers",
+            WebsiteRole::AtsBoard => "ats_board",
+            WebsiteRole::Aggregator => "aggregator",
+            WebsiteRole::Social => "social",
+            WebsiteRole::News => "news",
+            WebsiteRole::Unknown => "unknown",
+        }
+    }
+
+    /// A best guess at a website's role from its URL alone.
+    /// This can't tell a company's homepage apart from any other site, so those are Unknown.
+    pub fn guess_from_url(url: &str) -> WebsiteRole {
+        if AtsType::detect(url).is_some() {
+            return WebsiteRole::AtsBoard;
+        }
+        let host = host_of(url);
+        if on_domain(&host, AGGREGATOR_DOMAINS) {
+            return WebsiteRole::Aggregator;
+        }
+        if on_domain(&host, SOCIAL_DOMAINS) {
+            return WebsiteRole::Social;
+        }
+        if on_domain(&host, NEWS_DOMAINS) {
+            return WebsiteRole::News;
+        }
+        let url = url.to_lowercase();
+        if host.starts_with("careers.") || host.starts_with("jobs.") || url.contains("/careers") || url.contains("/jobs") {
+            return WebsiteRole::Careers;
+        }
+        WebsiteRole::Unknown
+    }
+}
+
+impl fmt::Display for WebsiteRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for WebsiteRole {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "official_homepage" => Ok(WebsiteRole::OfficialHomepage),
+            "careers" => Ok(WebsiteRole::Careers),
+            "ats_board" => Ok(WebsiteRole::AtsBoard),
+            "aggregator" => Ok(WebsiteRole::Aggregator),
+            "social" => Ok(WebsiteRole::Social),
+            "news" => Ok(WebsiteRole::News),
+            "unknown" => Ok(WebsiteRole::Unknown),
+            _ => bail!("Unknown website role: {}", s),
+        }
+    }
+}
+
+/// A website attached to a company, along with how we found it
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Website {
+    pub title: String,
+    pub url: String,
+    #[serde(default)]
+    pub role: WebsiteRole,
+    /// Position in the search results it was found in, starting at 1
+    pub search_rank: Option<u32>,
+    /// The search engine's summary of the page
+    pub snippet: Option<String>,
+    /// The search engine it was found with, e.g. "google"
+    pub source_engine: Option<String>,
+    pub discovered_at: Option<DateTime<Utc>>,
+    /// The status code the last time the page was fetched
+    pub http_status: Option<u16>,
+    /// When the page was last fetched to check it
+    pub last_verified_at: Option<DateTime<Utc>>,
+    /// When a check last found the page different from what we had stored, e.g. a new status code
+    pub last_changed_at: Option<DateTime<Utc>>,
+}
+
+impl Website {
+    /// A website we know nothing about other than its title and URL
+    pub fn new(title: &str, url: &str) -> Website {
+        Website {
+            title: title.to_string(),
+            url: url.to_string(),
+            role: WebsiteRole::Unknown,
+            search_rank: None,
+            snippet: None,
+            source_engine: None,
+            discovered_at: None,
+            http_status: None,
+            last_verified_at: None,
+            last_changed_at: None,
+        }
+    }
+}
@@ -0,0 +1,243 @@
+#[cfg(test)]
+mod processed_company_tests {
+    use std::collections::HashSet;
+    use std::str::FromStr;
+    use company_common::*;
+
+    #[test]
+    fn bot_protection_per_url_test() {
+        let mut company = ProcessedCompany::new(None, HashSet::new(), None, Some(vec![CareerPage::new("https://x.com/careers")]), None);
+        assert!(!company.has_captcha());
+        company.add_bot_protection("https://x.com", BotProtection::new(BotProtectionKind::LoginWall, None));
+        assert!(company.is_bot_protected("https://x.com"));
+        assert!(!company.is_bot_protected("https://x.com/careers"));
+        assert!(!company.has_captcha());
+
+        company.add_bot_protection("https://x.com/careers", BotProtection::new(BotProtectionKind::Hcaptcha, None));
+        company.add_bot_protection("https://x.com/careers", BotProtection::new(BotProtectionKind::Hcaptcha, Some("iframe".to_string())));
+        assert_eq!(company.get_bot_protection("https://x.com/careers").len(), 1);
+        assert!(company.has_captcha());
+        assert!(company.career_page_has_captcha());
+    }
+
+    #[test]
+    fn kind_round_trip_test() {
+        for kind in [BotProtectionKind::Recaptcha, BotProtectionKind::Hcaptcha, BotProtectionKind::CloudflareChallenge, BotProtectionKind::Captcha,
+                     BotProtectionKind::LoginWall, BotProtectionKind::Unknown] {
+            assert_eq!(BotProtectionKind::from_str(kind.as_str()).unwrap(), kind);
+        }
+        for role in [WebsiteRole::OfficialHomepage, WebsiteRole::Careers, WebsiteRole::AtsBoard, WebsiteRole::Aggregator,
+                     WebsiteRole::Social, WebsiteRole::News, WebsiteRole::Unknown] {
+            assert_eq!(WebsiteRole::from_str(role.as_str()).unwrap(), role);
+        }
+        for employment_type in EmploymentType::all() {
+            assert_eq!(EmploymentType::from_str(employment_type.as_str()).unwrap(), employment_type);
+        }
+        for source in [FactSource::Sec, FactSource::Serp, FactSource::Import, FactSource::Manual, FactSource::Unknown] {
+            assert_eq!(FactSource::from_str(source.as_str()).unwrap(), source);
+        }
+    }
+
+    #[test]
+    fn guess_website_role_test() {
+        assert_eq!(WebsiteRole::guess_from_url("https://boards.greenhouse.io/acme"), WebsiteRole::AtsBoard);
+        assert_eq!(WebsiteRole::guess_from_url("https://www.indeed.com/cmp/Acme"), WebsiteRole::Aggregator);
+        assert_eq!(WebsiteRole::guess_from_url("https://www.linkedin.com/company/acme"), WebsiteRole::Social);
+        assert_eq!(WebsiteRole::guess_from_url("https://careers.acme.com"), WebsiteRole::Careers);
+        assert_eq!(WebsiteRole::guess_from_url("https://acme.com/about/careers"), WebsiteRole::Careers);
+        assert_eq!(WebsiteRole::guess_from_url("https://acme.com"), WebsiteRole::Unknown);
+        // a lookalike domain isn't the aggregator
+        assert_eq!(WebsiteRole::guess_from_url("https://notindeed.com"), WebsiteRole::Unknown);
+    }
+
+    #[test]
+    fn namespaced_tag_test() {
+        let tag = Tag::from_str("user:alice:shortlist").unwrap();
+        assert_eq!(tag, Tag::user("alice", "shortlist").unwrap());
+        assert_eq!(tag.namespace, Some(TagNamespace::User("alice".to_string())));
+        assert_eq!(tag.to_string(), "user:alice:shortlist");
+        for text in ["industry:fintech", "size:51-200", "stage:series-a", "tech"] {
+            assert_eq!(Tag::from_str(text).unwrap().to_string(), text);
+        }
+        assert_eq!(Tag::from_str("tech").unwrap().namespace, None);
+        assert!(Tag::from_str("color:blue").is_err());
+        assert!(Tag::from_str("industry:").is_err());
+        assert!(Tag::from_str("user::shortlist").is_err());
+    }
+
+    #[test]
+    fn trigram_similarity_test() {
+        assert_eq!(normalize_alias("  AT&T,  Inc. "), "at t inc");
+        assert_eq!(trigrams("APPLE Inc.").len(), 10);
+        assert_eq!(trigram_similarity("apple", "APPLE INC"), 0.6);
+        assert_eq!(trigram_similarity("Apple", "apple"), 1.0);
+        assert_eq!(trigram_similarity("apple", "zzz"), 0.0);
+        assert_eq!(trigram_similarity("", ""), 0.0);
+    }
+
+    #[test]
+    fn primary_career_page_test() {
+        let mut company = ProcessedCompany::new(None, HashSet::new(), None, None, None);
+        assert!(company.get_primary_career_page().is_none());
+        let mut europe = CareerPage::new("https://jobs.lever.co/acme-eu");
+        europe.region = Some("EU".to_string());
+        company.career_pages = Some(vec![CareerPage::new("https://acme.com/careers"), europe.clone()]);
+        assert_eq!(company.get_primary_career_page().unwrap().url, "https://acme.com/careers");
+        company.career_pages.as_mut().unwrap()[1].is_primary = true;
+        assert_eq!(company.get_primary_career_page().unwrap().region.as_deref(), Some("EU"));
+        assert_eq!(europe.ats_type, Some(AtsType::Lever));
+        assert_eq!(CareerPage::new("https://acme.com/careers").ats_type, None);
+        for ats in AtsType::all() {
+            assert_eq!(AtsType::from_str(ats.as_str()).unwrap(), ats);
+        }
+    }
+    #[test]
+    fn candidate_profile_test() {
+        let mut profile = CandidateProfile::new("alice").unwrap();
+        assert_eq!(profile.tag("dream-jobs").unwrap().to_string(), "user:alice:dream-jobs");
+        assert_eq!(profile.remote_preference, RemotePreference::Any);
+        profile.excluded_companies.push(7);
+        assert!(profile.is_excluded(&7));
+        assert!(!profile.is_excluded(&8));
+        assert!(CandidateProfile::new("").is_err());
+        assert!(CandidateProfile::new("a:b").is_err());
+        for preference in [RemotePreference::OnSite, RemotePreference::Hybrid, RemotePreference::Remote, RemotePreference::Any] {
+            assert_eq!(RemotePreference::from_str(preference.as_str()).unwrap(), preference);
+        }
+    }
+    #[test]
+    fn relationship_validity_test() {
+        let date = |day: u32| chrono::NaiveDate::from_ymd_opt(2020, 1, day).unwrap();
+        let mut relationship = CompanyRelationship::new(2, 1, RelationshipKind::AcquiredBy);
+        assert!(relationship.is_valid_on(date(1)));
+        relationship.valid_from = Some(date(10));
+        relationship.valid_to = Some(date(20));
+        assert!(!relationship.is_valid_on(date(9)));
+        assert!(relationship.is_valid_on(date(10)));
+        assert!(relationship.is_valid_on(date(19)));
+        assert!(!relationship.is_valid_on(date(20)));
+        assert_eq!(relationship.other_sid(2), 1);
+        assert_eq!(relationship.other_sid(1), 2);
+        for kind in RelationshipKind::all() {
+            assert_eq!(RelationshipKind::from_str(kind.as_str()).unwrap(), kind);
+        }
+    }
+    #[test]
+    fn canonical_url_test() {
+        let canonical = |url: &str| CanonicalUrl::parse(url).unwrap().to_string();
+        assert_eq!(canonical("http://x.com"), "https://x.com");
+        assert_eq!(canonical("https://www.x.com/"), "https://x.com");
+        assert_eq!(canonical("https://x.com/?utm_source=google&UTM_medium=cpc&gclid=1"), "https://x.com");
+        assert_eq!(canonical("HTTP://WWW.Acme.com:80/Careers/?page=2&fbclid=abc#top"), "https://acme.com/Careers?page=2");
+        assert_eq!(canonical("acme.com/jobs"), "https://acme.com/jobs");
+        assert_eq!(canonical("acme.com:8080/jobs"), "https://acme.com:8080/jobs");
+        assert_eq!(canonical("https://acme.com:8443/jobs"), "https://acme.com:8443/jobs");
+        assert_eq!(canonical("https://bücher.de/karriere"), "https://xn--bcher-kva.de/karriere");
+        assert!(CanonicalUrl::parse("mailto:jobs@acme.com").is_err());
+        assert!(CanonicalUrl::parse("  ").is_err());
+        assert_eq!(canonicalize_url("not a url"), "not a url");
+
+        let careers = CanonicalUrl::parse("https://careers.acme.co.uk/jobs").unwrap();
+        assert_eq!(careers.host(), "careers.acme.co.uk");
+        assert_eq!(careers.registrable_domain(), Some("acme.co.uk"));
+        assert!(careers.same_site(&CanonicalUrl::parse("www.acme.co.uk").unwrap()));
+        assert!(!careers.same_site(&CanonicalUrl::parse("https://other.co.uk").unwrap()));
+        assert_eq!(CanonicalUrl::parse("http://127.0.0.1:8080/").unwrap().registrable_domain(), None);
+    }
+
+    #[test]
+    fn domain_blocklist_test() {
+        let mut blocklist = DomainBlocklist::default();
+        assert_eq!(blocklist.blocked_role("https://uk.linkedin.com/company/acme"), Some(WebsiteRole::Social));
+        assert_eq!(blocklist.blocked_role("http://www.Indeed.com/cmp/Acme?utm_source=x"), Some(WebsiteRole::Aggregator));
+        assert!(!blocklist.is_blocked("https://notindeed.com"));
+        assert!(!blocklist.is_blocked("https://acme.com"));
+
+        assert!(blocklist.add("https://www.Acme-Directory.com/", WebsiteRole::Aggregator).is_ok());
+        assert!(blocklist.is_blocked("https://companies.acme-directory.com/acme"));
+        assert!(blocklist.add("acme.com", WebsiteRole::OfficialHomepage).is_err());
+        assert!(blocklist.remove("indeed.com"));
+        assert!(!blocklist.is_blocked("https://indeed.com/cmp/Acme"));
+        assert!(!blocklist.remove("indeed.com"));
+    }
+
+    #[test]
+    fn company_patch_test() {
+        let aliases: HashSet<String> = ["Acme".to_string(), "Acme Corp".to_string()].into_iter().collect();
+        let mut company = ProcessedCompany::new(None, aliases, Some(vec![Website::new("Acme", "https://acme.com")]),
+                                                Some(vec![CareerPage::new("https://acme.com/jobs"), CareerPage::new("https://boards.greenhouse.io/acme")]),
+                                                Some(vec!["tech".to_string()]));
+        let patch = ProcessedCompanyPatch::new()
+            .remove_alias("Acme Corp")
+            .add_alias("Acme Inc")
+            .remove_tag(Tag::from_str("tech").unwrap())
+            .add_tag(Tag::industry("robotics").unwrap())
+            .add_website(Website::new("Acme blog", "http://www.blog.acme.com/"))
+            .remove_website("https://www.acme.com/")
+            .set_career_page(CareerPage::new("https://careers.acme.com/"));
+        assert!(!patch.is_empty());
+        patch.apply(&mut company);
+
+        let mut aliases: Vec<&String> = company.company_aliases.iter().collect();
+        aliases.sort();
+        assert_eq!(aliases, vec!["Acme", "Acme Inc"]);
+        assert_eq!(company.tags, Some(vec!["industry:robotics".to_string()]));
+        let websites: Vec<&str> = company.websites.iter().flatten().map(|website| website.url.as_str()).collect();
+        assert_eq!(websites, vec!["https://blog.acme.com"]);
+        let career_pages = company.career_pages.clone().unwrap();
+        assert_eq!(career_pages.len(), 1);
+        assert_eq!(career_pages[0].url, "https://careers.acme.com");
+        assert!(career_pages[0].is_primary);
+
+        ProcessedCompanyPatch::new().clear_career_page().apply(&mut company);
+        assert!(company.career_pages.is_none());
+        assert!(ProcessedCompanyPatch::new().is_empty());
+    }
+
+    #[test]
+    fn external_identifier_test() {
+        assert_eq!(ExternalIdentifier::new(IdentifierKind::Cik, "0000320193").unwrap(), ExternalIdentifier::cik(320193));
+        assert!(ExternalIdentifier::new(IdentifierKind::Cik, "apple").is_err());
+        let lei = ExternalIdentifier::from_str("lei:5493001kjtiigc8y1r12").unwrap();
+        assert_eq!(lei.value, "5493001KJTIIGC8Y1R12");
+        assert_eq!(lei.to_string(), "lei:5493001KJTIIGC8Y1R12");
+        assert!(ExternalIdentifier::new(IdentifierKind::Lei, "5493001KJ").is_err());
+        assert_eq!(ExternalIdentifier::new(IdentifierKind::Ticker, " nasdaq:aapl ").unwrap().value, "NASDAQ:AAPL");
+        assert_eq!(ExternalIdentifier::new(IdentifierKind::Domain, "https://www.shop.acme.co.uk/about").unwrap().value, "acme.co.uk");
+        assert_eq!(ExternalIdentifier::new(IdentifierKind::CompaniesHouse, "sc12345").unwrap().value, "SC012345");
+        assert_eq!(ExternalIdentifier::new(IdentifierKind::CompaniesHouse, "2065").unwrap().value, "00002065");
+        assert!(ExternalIdentifier::from_str("isin:US0378331005").is_err());
+        assert!(ExternalIdentifier::from_str("AAPL").is_err());
+
+        let mut company = ProcessedCompany::new(Some(320193), HashSet::new(), None, None, None);
+        company.identifiers = vec![ExternalIdentifier::cik(320193), lei.clone()];
+        assert_eq!(company.get_identifiers(), vec![ExternalIdentifier::cik(320193), lei]);
+    }
+
+    #[test]
+    fn company_name_test() {
+        let date = |year, month, day| chrono::NaiveDate::from_ymd_opt(year, month, day).unwrap();
+        let aliases: HashSet<String> = ["FACEBOOK INC", "Meta", "META PLATFORMS INC", "META"].iter().map(|alias| alias.to_string()).collect();
+        let mut company = ProcessedCompany::new(Some(1326801), aliases, None, None, None);
+        // without metadata, the name is still the same every time
+        assert_eq!(company.get_company_name().unwrap(), "FACEBOOK INC");
+
+        company.set_alias_metadata("FACEBOOK INC", AliasMetadata::former(Some(date(2012, 2, 1)), date(2021, 10, 28)));
+        company.set_alias_metadata("META PLATFORMS INC", AliasMetadata { kind: AliasKind::Legal, valid_from: Some(date(2021, 10, 28)), valid_to: None });
+        company.set_alias_metadata("Meta", AliasMetadata::new(AliasKind::Brand));
+        company.set_alias_metadata("META", AliasMetadata::new(AliasKind::Ticker));
+        assert_eq!(company.get_company_name().unwrap(), "META PLATFORMS INC");
+        assert!(!company.get_alias_metadata("FACEBOOK INC").is_valid_on(date(2024, 1, 1)));
+        assert_eq!(company.get_alias_metadata("unrecorded").kind, AliasKind::Unknown);
+        assert_eq!(AliasKind::from_str("former").unwrap(), AliasKind::Former);
+
+        company.set_primary_name("Meta");
+        assert_eq!(company.get_company_name().unwrap(), "Meta");
+        ProcessedCompanyPatch::new().remove_alias("Meta").apply(&mut company);
+        assert_eq!(company.primary_name, None);
+        assert_eq!(company.get_company_name().unwrap(), "META PLATFORMS INC");
+        ProcessedCompanyPatch::new().set_primary_name("Facebook").apply(&mut company);
+        assert_eq!(company.get_company_name().unwrap(), "Facebook");
+        assert!(company.company_aliases.contains("Facebook"));
+    }
+}
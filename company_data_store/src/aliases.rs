@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use anyhow::{bail, Error};
+use chrono::NaiveDate;
+use company_common::{AliasKind, AliasMetadata, Provenance};
+use tokio_postgres::GenericClient;
+use crate::{insert_with_provenance, CompanyDataStore, CompanyTables};
+
+/// Makes the best alias of company $1 its primary name if it has none, choosing the same way as
+/// ProcessedCompany::get_company_name: names in use before dropped ones, legal names before brands and tickers,
+/// newer names before older ones, then by the bytes of the name. Rust compares strings byte by byte,
+/// so the name is compared with the "C" collation rather than the database's.
+pub(crate) const PROMOTE_PRIMARY_ALIAS: &str =
+    "UPDATE CompanyAliases SET is_primary = true WHERE sid = $1 AND CompanyAlias = \
+        (SELECT CompanyAlias FROM CompanyAliases WHERE sid = $1 \
+        ORDER BY COALESCE(valid_from > CURRENT_DATE, false) OR COALESCE(valid_to <= CURRENT_DATE, false), \
+            CASE kind WHEN 'legal' THEN 0 WHEN 'brand' THEN 1 WHEN 'ticker' THEN 3 WHEN 'former' THEN 4 ELSE 2 END, \
+            valid_from DESC NULLS LAST, CompanyAlias COLLATE \"C\" LIMIT 1) \
+    AND NOT EXISTS (SELECT 1 FROM CompanyAliases WHERE sid = $1 AND is_primary)";
+
+/// Adds an alias, making it the primary name if the company has none
+pub(crate) async fn insert_alias<C: GenericClient>(client: &C, sid: &i32, alias: &str, provenance: &Provenance) -> Result<(), Error> {
+    insert_with_provenance(client, CompanyTables::CompanyAliases, &["CompanyAlias", "sid"], vec![&alias, sid], provenance).await?;
+    client.execute(PROMOTE_PRIMARY_ALIAS, &[sid]).await?;
+    Ok(())
+}
+
+const UPDATE_ALIAS_METADATA: &str =
+    "UPDATE CompanyAliases SET kind = $3, valid_from = $4, valid_to = $5 WHERE sid = $1 AND CompanyAlias = $2";
+
+pub(crate) async fn update_alias_metadata<C: GenericClient>(client: &C, sid: &i32, alias: &str, metadata: &AliasMetadata) -> Result<(), Error> {
+    let updated = client.execute(UPDATE_ALIAS_METADATA, &[sid, &alias, &metadata.kind.as_str(),
+        &metadata.valid_from, &metadata.valid_to]).await?;
+    if updated == 0 {
+        bail!("Company {} has no alias {}", sid, alias);
+    }
+    Ok(())
+}
+
+/// Must run inside a transaction, so there is never more than one primary name
+pub(crate) async fn make_primary_alias<C: GenericClient>(client: &C, sid: &i32, name: &str) -> Result<(), Error> {
+    let results = client.query("SELECT 1 FROM CompanyAliases WHERE sid = $1 AND CompanyAlias = $2", &[sid, &name]).await?;
+    if results.is_empty() {
+        bail!("Company {} has no alias {}", sid, name);
+    }
+    // clear the old primary before setting the new one, so there is never more than one
+    client.execute("UPDATE CompanyAliases SET is_primary = false WHERE sid = $1 AND is_primary AND CompanyAlias <> $2",
+                   &[sid, &name]).await?;
+    client.execute("UPDATE CompanyAliases SET is_primary = true WHERE sid = $1 AND CompanyAlias = $2", &[sid, &name]).await?;
+    Ok(())
+}
+
+impl CompanyDataStore {
+    /// Records what one of the company's aliases is and when it was used, replacing what was recorded before
+    pub async fn set_alias_metadata(&mut self, sid: &i32, alias: &str, metadata: &AliasMetadata, dry_run: bool) -> Result<(), Error> {
+        if dry_run {
+            println!("{}", UPDATE_ALIAS_METADATA);
+            return Ok(());
+        }
+        update_alias_metadata(&self.postgres_client, sid, alias, metadata).await
+    }
+
+    /// Makes one of the company's aliases its primary name
+    pub async fn set_primary_name(&mut self, sid: &i32, name: &str, dry_run: bool) -> Result<(), Error> {
+        let query = "UPDATE CompanyAliases SET is_primary = true WHERE sid = $1 AND CompanyAlias = $2".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(());
+        }
+        let transaction = self.postgres_client.transaction().await?;
+        make_primary_alias(&transaction, sid, name).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Records that the company changed its name to `new_name` (one of its aliases) on the given day:
+    /// the new name becomes its primary, legal name from that day, and the old primary name a former one until then.
+    pub async fn rename_company(&mut self, sid: &i32, new_name: &str, on: Option<NaiveDate>, dry_run: bool) -> Result<(), Error> {
+        let old_name = self.get_primary_name_from_sid(sid).await?;
+        if let Some(old_name) = old_name.as_deref().filter(|old_name| *old_name != new_name) {
+            let mut metadata = self.get_alias_metadata_from_sid(sid).await?.remove(old_name).unwrap_or_default();
+            metadata.kind = AliasKind::Former;
+            metadata.valid_to = on.or(metadata.valid_to);
+            self.set_alias_metadata(sid, old_name, &metadata, dry_run).await?;
+        }
+        let metadata = AliasMetadata { kind: AliasKind::Legal, valid_from: on, valid_to: None };
+        self.set_alias_metadata(sid, new_name, &metadata, dry_run).await?;
+        self.set_primary_name(sid, new_name, dry_run).await
+    }
+
+    pub async fn get_primary_name_from_sid(&self, sid: &i32) -> Result<Option<String>, Error> {
+        let results = self.postgres_client.query(
+            "SELECT CompanyAlias FROM CompanyAliases WHERE sid = $1 AND is_primary", &[sid],
+        ).await?;
+        Ok(results.first().map(|row| row.get(0)))
+    }
+
+    /// What each of the company's aliases is, by alias; aliases recorded before kinds were tracked are unknown
+    pub async fn get_alias_metadata_from_sid(&self, sid: &i32) -> Result<HashMap<String, AliasMetadata>, Error> {
+        let results = self.postgres_client.query(
+            "SELECT CompanyAlias, kind, valid_from, valid_to FROM CompanyAliases WHERE sid = $1", &[sid],
+        ).await?;
+        let mut metadata = HashMap::new();
+        for row in results {
+            let kind: Option<String> = row.get(1);
+            let kind = match kind {
+                Some(kind) => AliasKind::from_str(&kind)?,
+                None => AliasKind::Unknown,
+            };
+            metadata.insert(row.get(0), AliasMetadata { kind, valid_from: row.get(2), valid_to: row.get(3) });
+        }
+        Ok(metadata)
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use anyhow::Error;
+use company_common::{canonicalize_url, BotProtection, BotProtectionKind};
+use tokio_postgres::GenericClient;
+use crate::CompanyDataStore;
+
+const UPSERT_BOT_PROTECTION: &str =
+    "INSERT INTO WebsiteBotProtection (sid, url, kind, detected_at, evidence) \
+    VALUES ($1, $2, $3, COALESCE($4, now()), $5) \
+    ON CONFLICT (sid, url, kind) DO UPDATE SET detected_at = EXCLUDED.detected_at, evidence = EXCLUDED.evidence";
+
+pub(crate) async fn insert_bot_protection<C: GenericClient>(client: &C, sid: &i32, url: &str, protection: &BotProtection) -> Result<(), Error> {
+    client.execute(UPSERT_BOT_PROTECTION, &[
+        &sid, &canonicalize_url(url), &protection.kind.as_str(), &protection.detected_at, &protection.evidence,
+    ]).await?;
+    Ok(())
+}
+
+impl CompanyDataStore {
+    /// Records bot protection detected on one of a company's URLs (a website, career page, etc.).
+    /// Detecting the same kind of protection on the same URL again updates its evidence and detection time.
+    pub async fn record_bot_protection(&mut self, sid: &i32, url: &str, protection: &BotProtection, dry_run: bool) -> Result<(), Error> {
+        if dry_run {
+            println!("{}", UPSERT_BOT_PROTECTION);
+            return Ok(());
+        }
+        insert_bot_protection(&self.postgres_client, sid, url, protection).await
+    }
+
+    /// Forgets the bot protection on a URL, e.g. once it's gone. If kind is None, every kind is forgotten.
+    /// Returns how many records were removed.
+    pub async fn clear_bot_protection(&mut self, sid: &i32, url: &str, kind: Option<BotProtectionKind>) -> Result<u64, Error> {
+        let query = "DELETE FROM WebsiteBotProtection WHERE sid = $1 AND url = $2 AND ($3::TEXT IS NULL OR kind = $3)".to_string();
+        let kind = kind.map(|kind| kind.as_str().to_string());
+        Ok(self.postgres_client.execute(&query, &[&sid, &canonicalize_url(url), &kind]).await?)
+    }
+
+    /// Returns the bot protection detected on each of a company's URLs, by URL
+    pub async fn get_bot_protection_from_sid(&self, sid: &i32) -> Result<HashMap<String, Vec<BotProtection>>, Error> {
+        let query = "SELECT url, kind, detected_at, evidence FROM WebsiteBotProtection \
+            WHERE sid = $1 ORDER BY url, detected_at".to_string();
+        let results = self.postgres_client.query(&query, &[&sid]).await?;
+        let mut protections: HashMap<String, Vec<BotProtection>> = HashMap::new();
+        for row in results {
+            let kind: String = row.get(1);
+            protections.entry(row.get(0)).or_default().push(BotProtection {
+                kind: BotProtectionKind::from_str(&kind).unwrap_or(BotProtectionKind::Unknown),
+                detected_at: row.get(2),
+                evidence: row.get(3),
+            });
+        }
+        Ok(protections)
+    }
+}
@@ -0,0 +1,136 @@
+use std::str::FromStr;
+use anyhow::{bail, Error};
+use company_common::{canonicalize_url, AtsType, CanonicalUrl, CareerPage, Provenance};
+use tokio_postgres::GenericClient;
+use crate::CompanyDataStore;
+
+/// Makes the oldest career page of company $1 its primary one if it has none
+pub(crate) const PROMOTE_PRIMARY_CAREER_PAGE: &str =
+    "UPDATE CompanyCareerPage SET is_primary = true WHERE sid = $1 AND career_page_link = \
+        (SELECT career_page_link FROM CompanyCareerPage WHERE sid = $1 ORDER BY recorded_at NULLS FIRST, career_page_link LIMIT 1) \
+    AND NOT EXISTS (SELECT 1 FROM CompanyCareerPage WHERE sid = $1 AND is_primary)";
+
+/// Adds career page $2 of company $1, or updates it if the company already has it
+const UPSERT_CAREER_PAGE: &str =
+    "INSERT INTO CompanyCareerPage (sid, career_page_link, label, region, ats_type, http_status, last_verified_at, \
+        is_primary, source, producer, confidence, recorded_at) \
+    VALUES ($1, $2, $3, $4, $5, $6, $7, \
+        $8 OR NOT EXISTS (SELECT 1 FROM CompanyCareerPage p WHERE p.sid = $1 AND p.is_primary), \
+        $9, $10, $11, COALESCE($12, now())) \
+    ON CONFLICT (sid, career_page_link) DO UPDATE SET \
+        label = COALESCE(EXCLUDED.label, CompanyCareerPage.label), \
+        region = COALESCE(EXCLUDED.region, CompanyCareerPage.region), \
+        ats_type = COALESCE(EXCLUDED.ats_type, CompanyCareerPage.ats_type), \
+        http_status = COALESCE(EXCLUDED.http_status, CompanyCareerPage.http_status), \
+        last_verified_at = GREATEST(EXCLUDED.last_verified_at, CompanyCareerPage.last_verified_at), \
+        is_primary = CompanyCareerPage.is_primary OR EXCLUDED.is_primary, \
+        source = EXCLUDED.source, producer = EXCLUDED.producer, \
+        confidence = EXCLUDED.confidence, recorded_at = EXCLUDED.recorded_at";
+
+/// Adds or updates a career page, see add_career_page. Must run inside a transaction, so there is never more than one primary page.
+pub(crate) async fn insert_career_page<C: GenericClient>(client: &C, sid: &i32, career_page: &CareerPage, provenance: &Provenance) -> Result<(), Error> {
+    let url = CanonicalUrl::parse(&career_page.url)?.to_string();
+    let ats_type = career_page.ats_type.map(|ats| ats.as_str().to_string());
+    let http_status = career_page.http_status.map(|status| status as i32);
+    let source = provenance.source.as_str();
+    if career_page.is_primary {
+        client.execute(
+            "UPDATE CompanyCareerPage SET is_primary = false WHERE sid = $1 AND is_primary AND career_page_link <> $2",
+            &[sid, &url],
+        ).await?;
+    }
+    client.execute(UPSERT_CAREER_PAGE, &[sid, &url, &career_page.label, &career_page.region, &ats_type,
+        &http_status, &career_page.last_verified_at, &career_page.is_primary, &source, &provenance.producer,
+        &provenance.confidence, &provenance.recorded_at]).await?;
+    Ok(())
+}
+
+impl CompanyDataStore {
+    /// Adds a career page, or updates it if the company already has a career page with the same canonical URL.
+    /// When updating, fields left as None keep their stored values and the provenance is replaced.
+    /// A company's first career page becomes its primary one; marking another page primary demotes the old one.
+    pub async fn add_career_page(&mut self, sid: &i32, career_page: &CareerPage, provenance: &Provenance, dry_run: bool) -> Result<(), Error> {
+        if dry_run {
+            println!("{}", UPSERT_CAREER_PAGE);
+            return Ok(());
+        }
+        let transaction = self.postgres_client.transaction().await?;
+        insert_career_page(&transaction, sid, career_page, provenance).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Makes one of the company's career pages its primary one
+    pub async fn set_primary_career_page(&mut self, sid: &i32, url: &str, dry_run: bool) -> Result<(), Error> {
+        let query = "UPDATE CompanyCareerPage SET is_primary = true WHERE sid = $1 AND career_page_link = $2".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(());
+        }
+        let url = canonicalize_url(url);
+        let results = self.postgres_client.query(
+            "SELECT 1 FROM CompanyCareerPage WHERE sid = $1 AND career_page_link = $2", &[sid, &url],
+        ).await?;
+        if results.is_empty() {
+            bail!("Company {} has no career page {}", sid, url);
+        }
+        // clear the old primary before setting the new one, so there is never more than one
+        let transaction = self.postgres_client.transaction().await?;
+        transaction.execute("UPDATE CompanyCareerPage SET is_primary = false WHERE sid = $1 AND is_primary", &[sid]).await?;
+        transaction.execute(&query, &[sid, &url]).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Records the status code a career page returned when it was checked just now
+    pub async fn verify_career_page(&mut self, sid: &i32, url: &str, http_status: u16, dry_run: bool) -> Result<(), Error> {
+        let query = "UPDATE CompanyCareerPage SET http_status = $3, last_verified_at = now() \
+            WHERE sid = $1 AND career_page_link = $2".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(());
+        }
+        self.postgres_client.execute(&query, &[sid, &canonicalize_url(url), &(http_status as i32)]).await?;
+        Ok(())
+    }
+
+    /// Removes a career page. If it was the primary one, the oldest remaining career page becomes primary.
+    pub async fn remove_career_page(&mut self, sid: &i32, url: &str, dry_run: bool) -> Result<(), Error> {
+        let query = "DELETE FROM CompanyCareerPage WHERE sid = $1 AND career_page_link = $2".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(());
+        }
+        let transaction = self.postgres_client.transaction().await?;
+        transaction.execute(&query, &[sid, &canonicalize_url(url)]).await?;
+        transaction.execute(PROMOTE_PRIMARY_CAREER_PAGE, &[sid]).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// The company's career pages, primary first
+    pub async fn get_career_pages_from_sid(&self, sid: &i32) -> Result<Option<Vec<CareerPage>>, Error> {
+        let query = "SELECT career_page_link, label, region, ats_type, http_status, last_verified_at, last_changed_at, is_primary \
+            FROM CompanyCareerPage WHERE sid = $1 ORDER BY is_primary DESC, career_page_link".to_string();
+        let results = self.postgres_client.query(&query, &[&sid]).await?;
+        let mut career_pages = Vec::new();
+        for row in results {
+            let ats_type: Option<String> = row.get(3);
+            let http_status: Option<i32> = row.get(4);
+            career_pages.push(CareerPage {
+                url: row.get(0),
+                label: row.get(1),
+                region: row.get(2),
+                ats_type: ats_type.and_then(|ats| AtsType::from_str(&ats).ok()),
+                http_status: http_status.map(|status| status as u16),
+                last_verified_at: row.get(5),
+                last_changed_at: row.get(6),
+                is_primary: row.get(7),
+            });
+        }
+        if career_pages.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(career_pages))
+    }
+}
@@ -0,0 +1,146 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use anyhow::Error;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver};
+use futures::{stream, Stream, StreamExt};
+use serde::Deserialize;
+use tokio_postgres::{connect, AsyncMessage, Client, NoTls};
+use crate::{database_url, CompanyDataStore};
+
+/// The channel change notifications are sent on
+pub const CHANGE_CHANNEL: &str = "company_changes";
+
+/// Which kind of company data changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeTable {
+    Company,
+    Alias,
+    Tag,
+    Website,
+    CareerPage,
+    Posting,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeOperation {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// A single row of company data changing, as sent by the database
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ChangeEvent {
+    pub table: ChangeTable,
+    pub operation: ChangeOperation,
+    pub sid: i32,
+}
+
+/// Installs the triggers that send a notification on CHANGE_CHANNEL whenever a row changes.
+/// Each trigger passes the ChangeTable name of its table as its argument.
+/// Updates to CompanyTable that only bump the company's version are left out, since the change
+/// that bumped it sends its own notification.
+pub(crate) const CHANGE_TRIGGERS: &[&str] = &[
+    "CREATE OR REPLACE FUNCTION notify_company_change() RETURNS trigger AS $$
+    DECLARE
+        changed_sid INTEGER;
+    BEGIN
+        IF TG_OP = 'DELETE' THEN
+            changed_sid := OLD.sid;
+        ELSE
+            changed_sid := NEW.sid;
+        END IF;
+        PERFORM pg_notify('company_changes', json_build_object(
+            'table', TG_ARGV[0], 'operation', lower(TG_OP), 'sid', changed_sid)::text);
+        RETURN NULL;
+    END;
+    $$ LANGUAGE plpgsql",
+    "DROP TRIGGER IF EXISTS notify_company_change ON CompanyTable",
+    "CREATE TRIGGER notify_company_change AFTER INSERT OR DELETE ON CompanyTable \
+        FOR EACH ROW EXECUTE FUNCTION notify_company_change('company')",
+    "DROP TRIGGER IF EXISTS notify_company_update ON CompanyTable",
+    "CREATE TRIGGER notify_company_update AFTER UPDATE ON CompanyTable \
+        FOR EACH ROW WHEN ((to_jsonb(OLD) - 'version') IS DISTINCT FROM (to_jsonb(NEW) - 'version')) \
+        EXECUTE FUNCTION notify_company_change('company')",
+    "DROP TRIGGER IF EXISTS notify_company_change ON CompanyAliases",
+    "CREATE TRIGGER notify_company_change AFTER INSERT OR UPDATE OR DELETE ON CompanyAliases \
+        FOR EACH ROW EXECUTE FUNCTION notify_company_change('alias')",
+    "DROP TRIGGER IF EXISTS notify_company_change ON CompanyTags",
+    "CREATE TRIGGER notify_company_change AFTER INSERT OR UPDATE OR DELETE ON CompanyTags \
+        FOR EACH ROW EXECUTE FUNCTION notify_company_change('tag')",
+    "DROP TRIGGER IF EXISTS notify_company_change ON CompanyWebsites",
+    "CREATE TRIGGER notify_company_change AFTER INSERT OR UPDATE OR DELETE ON CompanyWebsites \
+        FOR EACH ROW EXECUTE FUNCTION notify_company_change('website')",
+    "DROP TRIGGER IF EXISTS notify_company_change ON CompanyCareerPage",
+    "CREATE TRIGGER notify_company_change AFTER INSERT OR UPDATE OR DELETE ON CompanyCareerPage \
+        FOR EACH ROW EXECUTE FUNCTION notify_company_change('career_page')",
+    "DROP TRIGGER IF EXISTS notify_company_change ON JobPostings",
+    "CREATE TRIGGER notify_company_change AFTER INSERT OR UPDATE OR DELETE ON JobPostings \
+        FOR EACH ROW EXECUTE FUNCTION notify_company_change('posting')",
+];
+
+/// A stream of changes to the company data, see CompanyDataStore::subscribe_to_changes.
+/// Listening stops when the stream is dropped.
+pub struct ChangeStream {
+    // keeps the listening connection open
+    _client: Client,
+    receiver: UnboundedReceiver<ChangeEvent>,
+}
+
+impl Stream for ChangeStream {
+    type Item = ChangeEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_next_unpin(cx)
+    }
+}
+
+impl CompanyDataStore {
+    /// Listens for changes to companies, aliases, tags, websites and career pages, made by anyone.
+    /// This opens a connection of its own, so the data store can still be used while listening.
+    ///
+    /// ```ignore
+    /// let mut changes = data_store.subscribe_to_changes().await?;
+    /// while let Some(change) = changes.next().await {
+    ///     if change.table == ChangeTable::Website && change.operation == ChangeOperation::Insert {
+    ///         // look for the company's career page
+    ///     }
+    /// }
+    /// ```
+    pub async fn subscribe_to_changes(&self) -> Result<ChangeStream, Error> {
+        let (client, mut connection) = connect(&database_url()?, NoTls).await?;
+        let (sender, receiver) = unbounded();
+        let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+        tokio::spawn(async move {
+            while let Some(message) = messages.next().await {
+                match message {
+                    Ok(AsyncMessage::Notification(notification)) => {
+                        let event: ChangeEvent = match serde_json::from_str(notification.payload()) {
+                            Ok(event) => event,
+                            Err(e) => {
+                                eprintln!("Could not parse change notification {}: {:?}", notification.payload(), e);
+                                continue;
+                            }
+                        };
+                        if sender.unbounded_send(event).is_err() {
+                            // the stream was dropped
+                            break;
+                        }
+                    },
+                    Ok(_) => {},
+                    Err(e) => {
+                        eprintln!("connection error: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+        client.batch_execute(&format!("LISTEN {}", CHANGE_CHANNEL)).await?;
+        Ok(ChangeStream {
+            _client: client,
+            receiver,
+        })
+    }
+}
@@ -0,0 +1,202 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use anyhow::{bail, Error};
+use chrono::{DateTime, Utc};
+use company_common::ProcessedCompany;
+use crate::CompanyDataStore;
+
+/// Failed companies are retried until they have been attempted this many times
+pub const MAX_DISCOVERY_ATTEMPTS: i32 = 5;
+/// The delay before the first retry of a failed company, doubled for each further attempt
+pub const DISCOVERY_RETRY_BASE: Duration = Duration::from_secs(60);
+/// The longest we'll wait before retrying a failed company
+pub const DISCOVERY_RETRY_MAX: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Selects the sids of the companies due for discovery, in the order they should be claimed:
+/// pending ones, failed ones due for a retry, and ones whose lease expired. A lease expiring counts as
+/// a failed attempt (claiming counts the attempt), so a company that keeps crashing its worker is given up on too.
+/// `max_attempts` is the placeholder MAX_DISCOVERY_ATTEMPTS is bound to, e.g. "$1".
+pub(crate) fn due_for_discovery(max_attempts: &str) -> String {
+    format!(
+        "SELECT d.sid FROM CompanyDiscovery d JOIN CompanyTable c ON c.sid = d.sid \
+        WHERE c.deleted_at IS NULL AND d.next_attempt_at <= now() AND (\
+            d.status = 'pending' \
+            OR (d.status = 'failed' AND d.attempts < {max_attempts}) \
+            OR (d.status = 'in_progress' AND d.leased_until < now() AND d.attempts < {max_attempts})) \
+        ORDER BY d.next_attempt_at, d.sid",
+        max_attempts = max_attempts,
+    )
+}
+
+/// Where a company is in the website discovery process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiscoveryStatus {
+    /// Waiting to be claimed by a worker
+    Pending,
+    /// Leased by a worker; claimable again once the lease expires
+    InProgress,
+    /// Websites were found and stored
+    Discovered,
+    /// The search succeeded but found nothing
+    NoResults,
+    /// The last attempt failed; retried after next_attempt_at until MAX_DISCOVERY_ATTEMPTS
+    Failed,
+    /// Deliberately left out of discovery
+    Skipped,
+}
+
+impl DiscoveryStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            DiscoveryStatus::Pending => "pending",
+            DiscoveryStatus::InProgress => "in_progress",
+            DiscoveryStatus::Discovered => "discovered",
+            DiscoveryStatus::NoResults => "no_results",
+            DiscoveryStatus::Failed => "failed",
+            DiscoveryStatus::Skipped => "skipped",
+        }
+    }
+
+    pub fn all() -> Vec<DiscoveryStatus> {
+        vec![
+            DiscoveryStatus::Pending,
+            DiscoveryStatus::InProgress,
+            DiscoveryStatus::Discovered,
+            DiscoveryStatus::NoResults,
+            DiscoveryStatus::Failed,
+            DiscoveryStatus::Skipped,
+        ]
+    }
+}
+
+impl fmt::Display for DiscoveryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for DiscoveryStatus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match DiscoveryStatus::all().into_iter().find(|status| status.as_str() == s) {
+            Some(status) => Ok(status),
+            None => bail!("Unknown discovery status: {}", s),
+        }
+    }
+}
+
+/// A company's row in the discovery work queue
+#[derive(Debug, Clone)]
+pub struct DiscoveryRecord {
+    pub sid: i32,
+    pub status: DiscoveryStatus,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub lease_owner: Option<String>,
+    pub leased_until: Option<DateTime<Utc>>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// The company whose career page the last discovery reused, because this company had none of its own
+    pub career_page_from: Option<i32>,
+}
+
+impl CompanyDataStore {
+    /// Leases the next company that is due for discovery to the given worker.
+    /// Pending companies, failed companies due for a retry, and companies whose lease expired
+    /// are claimable, until they have been attempted MAX_DISCOVERY_ATTEMPTS times. Rows locked by other workers are skipped, so two workers never get the same company.
+    /// Returns None if there is nothing to do right now.
+    pub async fn claim_next_company(&self, worker: &str, lease: Duration) -> Result<Option<(i32, ProcessedCompany)>, Error> {
+        let query = format!("UPDATE CompanyDiscovery SET status = 'in_progress', lease_owner = $1, \
+            leased_until = now() + make_interval(secs => $2), attempts = attempts + 1, career_page_from = NULL, updated_at = now() \
+            WHERE sid = ({} LIMIT 1 FOR UPDATE OF d SKIP LOCKED) \
+            RETURNING sid", due_for_discovery("$3"));
+        let results = self.postgres_client.query(&query, &[&worker, &lease.as_secs_f64(), &MAX_DISCOVERY_ATTEMPTS]).await?;
+        if results.is_empty() {
+            return Ok(None);
+        }
+        let sid: i32 = results[0].get(0);
+        println!("Worker {} claimed company with sid {}", worker, sid);
+        Ok(Some((sid, self.construct_processed_company_from_sid(&sid).await?)))
+    }
+
+    /// Finishes a company claimed by the worker with the given status and releases its lease.
+    /// Fails if the worker no longer holds the company, e.g. because its lease expired and another worker claimed it.
+    pub async fn complete_discovery(&self, sid: &i32, worker: &str, status: DiscoveryStatus) -> Result<(), Error> {
+        let query = "UPDATE CompanyDiscovery SET status = $3, last_error = NULL, lease_owner = NULL, \
+            leased_until = NULL, updated_at = now() \
+            WHERE sid = $1 AND lease_owner = $2 AND status = 'in_progress'".to_string();
+        let updated = self.postgres_client.execute(&query, &[&sid, &worker, &status.as_str()]).await?;
+        if updated == 0 {
+            bail!("Worker {} doesn't hold company with sid {}", worker, sid);
+        }
+        Ok(())
+    }
+
+    /// Records that the company claimed by the worker reuses the career page of the company with sid `parent_sid`,
+    /// an ancestor of it, having none of its own (see get_inherited_career_pages).
+    /// Fails if the worker no longer holds the company.
+    pub async fn record_inherited_career_page(&self, sid: &i32, worker: &str, parent_sid: &i32) -> Result<(), Error> {
+        let query = "UPDATE CompanyDiscovery SET career_page_from = $3, updated_at = now() \
+            WHERE sid = $1 AND lease_owner = $2 AND status = 'in_progress'".to_string();
+        let updated = self.postgres_client.execute(&query, &[&sid, &worker, &parent_sid]).await?;
+        if updated == 0 {
+            bail!("Worker {} doesn't hold company with sid {}", worker, sid);
+        }
+        Ok(())
+    }
+
+    /// Records a failed attempt on a company claimed by the worker and releases its lease.
+    /// The company is retried after an exponential backoff, until MAX_DISCOVERY_ATTEMPTS is reached.
+    /// Fails if the worker no longer holds the company.
+    pub async fn fail_discovery(&self, sid: &i32, worker: &str, error: &str) -> Result<(), Error> {
+        let query = "UPDATE CompanyDiscovery SET status = 'failed', last_error = $3, lease_owner = NULL, \
+            leased_until = NULL, updated_at = now(), \
+            next_attempt_at = now() + make_interval(secs => LEAST($4 * power(2, GREATEST(attempts - 1, 0)), $5)) \
+            WHERE sid = $1 AND lease_owner = $2 AND status = 'in_progress'".to_string();
+        let updated = self.postgres_client.execute(&query, &[
+            &sid, &worker, &error, &DISCOVERY_RETRY_BASE.as_secs_f64(), &DISCOVERY_RETRY_MAX.as_secs_f64(),
+        ]).await?;
+        if updated == 0 {
+            bail!("Worker {} doesn't hold company with sid {}", worker, sid);
+        }
+        println!("Discovery failed for company with sid {}: {}", sid, error);
+        Ok(())
+    }
+
+    /// Puts every company with one of the given statuses back into the queue, with its attempts reset.
+    /// Returns how many companies were requeued.
+    pub async fn requeue_companies(&self, statuses: Vec<DiscoveryStatus>) -> Result<u64, Error> {
+        let statuses: Vec<&str> = statuses.iter().map(|status| status.as_str()).collect();
+        let query = "UPDATE CompanyDiscovery SET status = 'pending', attempts = 0, last_error = NULL, \
+            lease_owner = NULL, leased_until = NULL, next_attempt_at = now(), updated_at = now() \
+            WHERE status = ANY($1)".to_string();
+        let requeued = self.postgres_client.execute(&query, &[&statuses]).await?;
+        println!("Requeued {} companies", requeued);
+        Ok(requeued)
+    }
+
+    pub async fn get_discovery_record(&self, sid: &i32) -> Result<Option<DiscoveryRecord>, Error> {
+        let query = "SELECT sid, status, attempts, last_error, lease_owner, leased_until, next_attempt_at, updated_at, \
+                career_page_from \
+            FROM CompanyDiscovery WHERE sid = $1".to_string();
+        let results = self.postgres_client.query(&query, &[&sid]).await?;
+        if results.is_empty() {
+            return Ok(None);
+        }
+        let row = &results[0];
+        let status: String = row.get(1);
+        Ok(Some(DiscoveryRecord {
+            sid: row.get(0),
+            status: DiscoveryStatus::from_str(&status)?,
+            attempts: row.get(2),
+            last_error: row.get(3),
+            lease_owner: row.get(4),
+            leased_until: row.get(5),
+            next_attempt_at: row.get(6),
+            updated_at: row.get(7),
+            career_page_from: row.get(8),
+        }))
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use anyhow::Error;
+use company_common::{host_of, normalize_domain, AtsType, CanonicalUrl, DomainBlocklist, WebsiteRole};
+use crate::CompanyDataStore;
+
+/// A domain the websites of several unrelated companies are on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedDomain {
+    /// The registrable domain, e.g. "indeed.com"
+    pub domain: String,
+    /// How many unrelated companies link to it; companies in the same hierarchy count once
+    pub companies: usize,
+    /// Every company linking to it, related or not
+    pub sids: Vec<i32>,
+    /// The stored links on the domain, as (sid, url)
+    pub links: Vec<(i32, String)>,
+    /// Why the domain is blocked, or None if it isn't on the blocklist (yet)
+    pub blocked: Option<WebsiteRole>,
+}
+
+/// What detach_shared_domains removed, or would remove when run as a dry run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DetachReport {
+    pub dry_run: bool,
+    /// The domains links were detached from, in alphabetical order
+    pub domains: Vec<String>,
+    pub links: u64,
+    pub companies: u64,
+}
+
+impl fmt::Display for DetachReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = if self.dry_run { "Would detach" } else { "Detached" };
+        writeln!(f, "{} {} links from {} companies, on {} domains", verb, self.links, self.companies, self.domains.len())?;
+        for domain in &self.domains {
+            writeln!(f, "  {}", domain)?;
+        }
+        Ok(())
+    }
+}
+
+/// The registrable domain of a link, or its host if it hasn't got one
+fn domain_of(url: &str) -> String {
+    match CanonicalUrl::parse(url) {
+        Ok(canonical) => canonical.registrable_domain().unwrap_or(canonical.host()).to_string(),
+        Err(_) => host_of(url),
+    }
+}
+
+/// Splits the companies into groups of related ones: two companies are in the same group if a chain of
+/// relationships connects them. `relationships` are (child_sid, parent_sid) pairs, and may mention other companies.
+pub fn related_groups(sids: &[i32], relationships: &[(i32, i32)]) -> Vec<Vec<i32>> {
+    let mut parents: HashMap<i32, i32> = HashMap::new();
+    fn find(parents: &mut HashMap<i32, i32>, sid: i32) -> i32 {
+        let parent = *parents.entry(sid).or_insert(sid);
+        if parent == sid {
+            return sid;
+        }
+        let root = find(parents, parent);
+        parents.insert(sid, root);
+        root
+    }
+    for (child, parent) in relationships {
+        let (child, parent) = (find(&mut parents, *child), find(&mut parents, *parent));
+        if child != parent {
+            parents.insert(child.max(parent), child.min(parent));
+        }
+    }
+    let mut groups: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+    for sid in sids {
+        let root = find(&mut parents, *sid);
+        let group = groups.entry(root).or_default();
+        if !group.contains(sid) {
+            group.push(*sid);
+        }
+    }
+    groups.into_values().collect()
+}
+
+impl CompanyDataStore {
+    /// Adds the built in blocklist (see DomainBlocklist::default) to the BlockedDomains table.
+    /// Domains that were unblocked stay unblocked.
+    pub async fn seed_blocked_domains(&mut self, dry_run: bool) -> Result<(), Error> {
+        let query = "INSERT INTO BlockedDomains (domain, role, note) VALUES ($1, $2, 'built in') \
+            ON CONFLICT (domain) DO NOTHING".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(());
+        }
+        for (domain, role) in DomainBlocklist::default().domains() {
+            self.postgres_client.execute(&query, &[&domain, &role.as_str()]).await?;
+        }
+        Ok(())
+    }
+
+    /// Adds a domain to the blocklist, so its links are never attached to a company as a website.
+    /// Links already attached stay until detach_shared_domains is run.
+    /// @param role: Aggregator or Social
+    pub async fn block_domain(&mut self, domain: &str, role: WebsiteRole, note: Option<&str>, dry_run: bool) -> Result<(), Error> {
+        // checks the role, and normalizes the domain the same way the blocklist does
+        let mut blocklist = DomainBlocklist::empty();
+        blocklist.add(domain, role)?;
+        let domain = normalize_domain(domain)?;
+        let query = "INSERT INTO BlockedDomains (domain, role, note) VALUES ($1, $2, $3) \
+            ON CONFLICT (domain) DO UPDATE SET role = EXCLUDED.role, note = EXCLUDED.note, blocked = true".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(());
+        }
+        self.postgres_client.execute(&query, &[&domain, &role.as_str(), &note]).await?;
+        println!("Blocked {} ({})", domain, role);
+        Ok(())
+    }
+
+    /// Takes a domain off the blocklist. It's remembered as unblocked, so seeding doesn't block it again.
+    pub async fn unblock_domain(&mut self, domain: &str, dry_run: bool) -> Result<(), Error> {
+        let query = "UPDATE BlockedDomains SET blocked = false WHERE domain = $1".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(());
+        }
+        self.postgres_client.execute(&query, &[&normalize_domain(domain)?]).await?;
+        Ok(())
+    }
+
+    pub async fn get_domain_blocklist(&self) -> Result<DomainBlocklist, Error> {
+        let results = self.postgres_client.query("SELECT domain, role FROM BlockedDomains WHERE blocked", &[]).await?;
+        let mut blocklist = DomainBlocklist::empty();
+        for row in results {
+            let domain: String = row.get(0);
+            let role: String = row.get(1);
+            blocklist.add(&domain, WebsiteRole::from_str(&role)?)?;
+        }
+        Ok(blocklist)
+    }
+
+    /// Domains the websites of more than `more_than` unrelated companies are on, most shared first.
+    /// These are almost always directories or social networks rather than anyone's own site.
+    /// Companies are related if a relationship connects them, in any period, since an acquired company
+    /// often links to its acquirer's site. Links to applicant tracking systems are left out.
+    pub async fn get_shared_domains(&self, more_than: usize) -> Result<Vec<SharedDomain>, Error> {
+        let blocklist = self.get_domain_blocklist().await?;
+        let query = "SELECT w.sid, w.website_link FROM CompanyWebsites w \
+            JOIN CompanyTable c ON c.sid = w.sid WHERE c.deleted_at IS NULL".to_string();
+        let mut links_by_domain: BTreeMap<String, Vec<(i32, String)>> = BTreeMap::new();
+        for row in self.postgres_client.query(&query, &[]).await? {
+            let url: String = row.get(1);
+            if AtsType::detect(&url).is_some() {
+                continue;
+            }
+            links_by_domain.entry(domain_of(&url)).or_default().push((row.get(0), url));
+        }
+
+        let results = self.postgres_client.query("SELECT child_sid, parent_sid FROM CompanyRelationships", &[]).await?;
+        let relationships: Vec<(i32, i32)> = results.iter().map(|row| (row.get(0), row.get(1))).collect();
+        let mut shared = Vec::new();
+        for (domain, links) in links_by_domain {
+            let mut sids: Vec<i32> = links.iter().map(|(sid, _)| *sid).collect();
+            sids.sort();
+            sids.dedup();
+            if sids.len() <= more_than {
+                continue;
+            }
+            let companies = related_groups(&sids, &relationships).len();
+            if companies <= more_than {
+                continue;
+            }
+            let blocked = blocklist.blocked_role(&format!("https://{}", domain));
+            shared.push(SharedDomain { domain, companies, sids, links, blocked });
+        }
+        shared.sort_by(|a, b| b.companies.cmp(&a.companies).then_with(|| a.domain.cmp(&b.domain)));
+        Ok(shared)
+    }
+
+    /// Detaches website links that can't be the company's own site: every link on a blocked domain,
+    /// and every link on a domain shared by more than `more_than` unrelated companies (see get_shared_domains).
+    /// Pass None to only detach links on blocked domains.
+    pub async fn detach_shared_domains(&mut self, more_than: Option<usize>, dry_run: bool) -> Result<DetachReport, Error> {
+        let blocklist = self.get_domain_blocklist().await?;
+        let mut links: BTreeMap<String, Vec<(i32, String)>> = BTreeMap::new();
+        let query = "SELECT w.sid, w.website_link FROM CompanyWebsites w \
+            JOIN CompanyTable c ON c.sid = w.sid WHERE c.deleted_at IS NULL".to_string();
+        for row in self.postgres_client.query(&query, &[]).await? {
+            let url: String = row.get(1);
+            if blocklist.is_blocked(&url) {
+                links.entry(domain_of(&url)).or_default().push((row.get(0), url));
+            }
+        }
+        if let Some(more_than) = more_than {
+            for shared in self.get_shared_domains(more_than).await? {
+                links.entry(shared.domain).or_default().extend(shared.links);
+            }
+        }
+
+        let mut report = DetachReport { dry_run, ..Default::default() };
+        let mut sids: Vec<i32> = Vec::new();
+        for (domain, domain_links) in &mut links {
+            domain_links.sort();
+            domain_links.dedup();
+            report.domains.push(domain.clone());
+            report.links += domain_links.len() as u64;
+            sids.extend(domain_links.iter().map(|(sid, _)| *sid));
+        }
+        sids.sort();
+        sids.dedup();
+        report.companies = sids.len() as u64;
+        if dry_run {
+            print!("{}", report);
+            return Ok(report);
+        }
+
+        let transaction = self.postgres_client.transaction().await?;
+        for (sid, url) in links.values().flatten() {
+            transaction.execute("DELETE FROM CompanyWebsites WHERE sid = $1 AND website_link = $2", &[sid, url]).await?;
+        }
+        transaction.commit().await?;
+        print!("{}", report);
+        Ok(report)
+    }
+}
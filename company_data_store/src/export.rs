@@ -0,0 +1,497 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use anyhow::{bail, Error};
+use arrow_array::types::Date32Type;
+use arrow_array::{Array, BooleanArray, Date32Array, Float32Array, Int32Array, Int64Array, RecordBatch, StringArray, TimestampMicrosecondArray};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use chrono::{DateTime, NaiveDate, Utc};
+use company_common::{AliasKind, AliasMetadata, AtsType, BotProtection, BotProtectionKind, CareerPage, ExternalIdentifier, FactSource, ProcessedCompany, Provenance, Website, WebsiteRole};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use serde::{Deserialize, Serialize};
+use crate::{CompanyDataStore, CompanyQuery};
+
+/// The file formats companies can be exported to and imported from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One ProcessedCompany as JSON per line
+    JsonLines,
+    /// One FlatCompanyFact per row
+    Csv,
+    /// One FlatCompanyFact per row
+    Parquet,
+}
+
+impl ExportFormat {
+    /// Guesses the format from a file's extension (.jsonl, .csv or .parquet)
+    pub fn from_path(path: &Path) -> Result<ExportFormat, Error> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("jsonl") | Some("ndjson") => Ok(ExportFormat::JsonLines),
+            Some("csv") => Ok(ExportFormat::Csv),
+            Some("parquet") => Ok(ExportFormat::Parquet),
+            _ => bail!("Can't tell the export format of {:?}", path),
+        }
+    }
+}
+
+/// A single fact about a company, for the flat (CSV and Parquet) formats.
+/// Every company gets a "company" fact, followed by one fact per alias, tag, website, etc.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlatCompanyFact {
+    /// Groups the facts of one company; the company's position in the export
+    pub company: i64,
+    pub cik: Option<i32>,
+    /// company, alias, tag, identifier, website, career_page or bot_protection
+    pub fact: String,
+    /// The alias, tag or URL
+    pub value: String,
+    /// The website title, for websites
+    pub title: Option<String>,
+    /// The website's role, search rank, snippet, search engine, discovery time and HTTP status, for websites
+    pub role: Option<String>,
+    pub search_rank: Option<i32>,
+    pub snippet: Option<String>,
+    pub source_engine: Option<String>,
+    pub discovered_at: Option<DateTime<Utc>>,
+    /// For websites and career pages
+    pub http_status: Option<i32>,
+    /// The career page's label, region and ATS type, for career pages
+    pub label: Option<String>,
+    pub region: Option<String>,
+    pub ats_type: Option<String>,
+    /// When the website or career page was last checked. When it last changed isn't exported.
+    pub verified_at: Option<DateTime<Utc>>,
+    /// Whether the career page is the company's primary one, or the alias its primary name
+    pub is_primary: Option<bool>,
+    /// The kind of bot protection, or of alias
+    pub kind: Option<String>,
+    /// What gave the bot protection away, for bot protection
+    pub evidence: Option<String>,
+    /// When the company went by the alias, for aliases
+    pub valid_from: Option<NaiveDate>,
+    pub valid_to: Option<NaiveDate>,
+    pub source: Option<String>,
+    pub producer: Option<String>,
+    pub confidence: Option<f32>,
+    pub recorded_at: Option<DateTime<Utc>>,
+}
+
+impl FlatCompanyFact {
+    fn new(company: i64, cik: Option<i32>, fact: &str, value: &str, provenance: Option<&Provenance>) -> FlatCompanyFact {
+        FlatCompanyFact {
+            company,
+            cik,
+            fact: fact.to_string(),
+            value: value.to_string(),
+            title: None,
+            role: None,
+            search_rank: None,
+            snippet: None,
+            source_engine: None,
+            discovered_at: None,
+            http_status: None,
+            label: None,
+            region: None,
+            ats_type: None,
+            verified_at: None,
+            is_primary: None,
+            kind: None,
+            evidence: None,
+            valid_from: None,
+            valid_to: None,
+            source: provenance.map(|p| p.source.as_str().to_string()),
+            producer: provenance.map(|p| p.producer.clone()),
+            confidence: provenance.map(|p| p.confidence),
+            recorded_at: provenance.and_then(|p| p.recorded_at),
+        }
+    }
+
+    fn provenance(&self) -> Option<Provenance> {
+        let source = self.source.as_ref()?;
+        Some(Provenance {
+            source: FactSource::from_str(source).unwrap_or(FactSource::Unknown),
+            producer: self.producer.clone().unwrap_or_else(|| "unknown".to_string()),
+            confidence: self.confidence.unwrap_or(0.0),
+            recorded_at: self.recorded_at,
+        })
+    }
+}
+
+/// Flattens a company into its facts
+pub fn flatten_company(index: i64, company: &ProcessedCompany) -> Vec<FlatCompanyFact> {
+    let cik = company.cik;
+    let mut facts = vec![FlatCompanyFact::new(index, cik, "company", "", None)];
+    let mut aliases: Vec<&String> = company.company_aliases.iter().collect();
+    aliases.sort();
+    for alias in aliases {
+        let mut fact = FlatCompanyFact::new(index, cik, "alias", alias, company.alias_provenance.get(alias));
+        fact.is_primary = company.primary_name.as_ref().map(|name| name == alias);
+        if let Some(metadata) = company.alias_metadata.get(alias) {
+            fact.kind = Some(metadata.kind.as_str().to_string());
+            fact.valid_from = metadata.valid_from;
+            fact.valid_to = metadata.valid_to;
+        }
+        facts.push(fact);
+    }
+    for tag in company.tags.iter().flatten() {
+        facts.push(FlatCompanyFact::new(index, cik, "tag", tag, None));
+    }
+    for identifier in &company.identifiers {
+        facts.push(FlatCompanyFact::new(index, cik, "identifier", &identifier.to_string(), None));
+    }
+    for website in company.websites.iter().flatten() {
+        let mut fact = FlatCompanyFact::new(index, cik, "website", &website.url, company.website_provenance.get(&website.url));
+        fact.title = Some(website.title.clone());
+        fact.role = Some(website.role.as_str().to_string());
+        fact.search_rank = website.search_rank.map(|rank| rank as i32);
+        fact.snippet = website.snippet.clone();
+        fact.source_engine = website.source_engine.clone();
+        fact.discovered_at = website.discovered_at;
+        fact.http_status = website.http_status.map(|status| status as i32);
+        fact.verified_at = website.last_verified_at;
+        facts.push(fact);
+    }
+    for career_page in company.career_pages.iter().flatten() {
+        let mut fact = FlatCompanyFact::new(index, cik, "career_page", &career_page.url,
+                                            company.career_page_provenance.get(&career_page.url));
+        fact.label = career_page.label.clone();
+        fact.region = career_page.region.clone();
+        fact.ats_type = career_page.ats_type.map(|ats| ats.as_str().to_string());
+        fact.http_status = career_page.http_status.map(|status| status as i32);
+        fact.verified_at = career_page.last_verified_at;
+        fact.is_primary = Some(career_page.is_primary);
+        facts.push(fact);
+    }
+    let mut protected_urls: Vec<&String> = company.bot_protection.keys().collect();
+    protected_urls.sort();
+    for url in protected_urls {
+        for protection in company.get_bot_protection(url) {
+            let mut fact = FlatCompanyFact::new(index, cik, "bot_protection", url, None);
+            fact.kind = Some(protection.kind.as_str().to_string());
+            fact.evidence = protection.evidence.clone();
+            fact.recorded_at = protection.detected_at;
+            facts.push(fact);
+        }
+    }
+    facts
+}
+
+/// Rebuilds companies from their facts, in export order
+pub fn unflatten_companies(facts: Vec<FlatCompanyFact>) -> Result<Vec<ProcessedCompany>, Error> {
+    let mut companies: BTreeMap<i64, ProcessedCompany> = BTreeMap::new();
+    for fact in facts {
+        let company = companies.entry(fact.company)
+            .or_insert_with(|| ProcessedCompany::new(fact.cik, HashSet::new(), None, None, None));
+        let provenance = fact.provenance();
+        match fact.fact.as_str() {
+            "company" => {},
+            "alias" => {
+                company.company_aliases.insert(fact.value.clone());
+                if let Some(kind) = &fact.kind {
+                    company.alias_metadata.insert(fact.value.clone(), AliasMetadata {
+                        kind: AliasKind::from_str(kind)?,
+                        valid_from: fact.valid_from,
+                        valid_to: fact.valid_to,
+                    });
+                }
+                if fact.is_primary == Some(true) {
+                    company.primary_name = Some(fact.value.clone());
+                }
+                if let Some(provenance) = provenance {
+                    company.alias_provenance.insert(fact.value, provenance);
+                }
+            },
+            "tag" => company.tags.get_or_insert_with(Vec::new).push(fact.value),
+            "identifier" => company.identifiers.push(ExternalIdentifier::from_str(&fact.value)?),
+            "website" => {
+                if let Some(provenance) = provenance {
+                    company.website_provenance.insert(fact.value.clone(), provenance);
+                }
+                let role = match &fact.role {
+                    Some(role) => WebsiteRole::from_str(role)?,
+                    None => WebsiteRole::Unknown,
+                };
+                company.websites.get_or_insert_with(Vec::new).push(Website {
+                    title: fact.title.unwrap_or_default(),
+                    url: fact.value,
+                    role,
+                    search_rank: fact.search_rank.map(|rank| rank as u32),
+                    snippet: fact.snippet,
+                    source_engine: fact.source_engine,
+                    discovered_at: fact.discovered_at,
+                    http_status: fact.http_status.map(|status| status as u16),
+                    last_verified_at: fact.verified_at,
+                    last_changed_at: None,
+                });
+            },
+            "career_page" => {
+                if let Some(provenance) = provenance {
+                    company.career_page_provenance.insert(fact.value.clone(), provenance);
+                }
+                let ats_type = match &fact.ats_type {
+                    Some(ats_type) => Some(AtsType::from_str(ats_type)?),
+                    None => None,
+                };
+                company.career_pages.get_or_insert_with(Vec::new).push(CareerPage {
+                    url: fact.value,
+                    label: fact.label,
+                    region: fact.region,
+                    ats_type,
+                    http_status: fact.http_status.map(|status| status as u16),
+                    last_verified_at: fact.verified_at,
+                    last_changed_at: None,
+                    is_primary: fact.is_primary.unwrap_or(false),
+                });
+            },
+            "bot_protection" => {
+                let kind = BotProtectionKind::from_str(fact.kind.as_deref().unwrap_or("unknown"))?;
+                company.add_bot_protection(&fact.value, BotProtection {
+                    kind,
+                    detected_at: fact.recorded_at,
+                    evidence: fact.evidence,
+                });
+            },
+            other => bail!("Unknown fact type: {}", other),
+        }
+    }
+    Ok(companies.into_values().collect())
+}
+
+/// Writes companies to a file in the given format, replacing the file if it exists
+pub fn write_companies(path: &Path, format: ExportFormat, companies: &Vec<ProcessedCompany>) -> Result<(), Error> {
+    let facts = || companies.iter().enumerate()
+        .flat_map(|(index, company)| flatten_company(index as i64, company));
+    match format {
+        ExportFormat::JsonLines => {
+            let mut writer = BufWriter::new(File::create(path)?);
+            for company in companies {
+                serde_json::to_writer(&mut writer, company)?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()?;
+        },
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_path(path)?;
+            for fact in facts() {
+                writer.serialize(fact)?;
+            }
+            writer.flush()?;
+        },
+        ExportFormat::Parquet => {
+            let batch = facts_to_record_batch(facts().collect())?;
+            let mut writer = ArrowWriter::try_new(File::create(path)?, batch.schema(), None)?;
+            writer.write(&batch)?;
+            writer.close()?;
+        },
+    }
+    Ok(())
+}
+
+/// Reads companies written by write_companies
+pub fn read_companies(path: &Path, format: ExportFormat) -> Result<Vec<ProcessedCompany>, Error> {
+    match format {
+        ExportFormat::JsonLines => {
+            let mut companies = Vec::new();
+            for line in BufReader::new(File::open(path)?).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                companies.push(serde_json::from_str(&line)?);
+            }
+            Ok(companies)
+        },
+        ExportFormat::Csv => {
+            let mut reader = csv::Reader::from_path(path)?;
+            let facts = reader.deserialize().collect::<Result<Vec<FlatCompanyFact>, csv::Error>>()?;
+            unflatten_companies(facts)
+        },
+        ExportFormat::Parquet => {
+            let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?.build()?;
+            let mut facts = Vec::new();
+            for batch in reader {
+                facts.extend(record_batch_to_facts(&batch?)?);
+            }
+            unflatten_companies(facts)
+        },
+    }
+}
+
+fn fact_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("company", DataType::Int64, false),
+        Field::new("cik", DataType::Int32, true),
+        Field::new("fact", DataType::Utf8, false),
+        Field::new("value", DataType::Utf8, false),
+        Field::new("title", DataType::Utf8, true),
+        Field::new("role", DataType::Utf8, true),
+        Field::new("search_rank", DataType::Int32, true),
+        Field::new("snippet", DataType::Utf8, true),
+        Field::new("source_engine", DataType::Utf8, true),
+        Field::new("discovered_at", DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())), true),
+        Field::new("http_status", DataType::Int32, true),
+        Field::new("label", DataType::Utf8, true),
+        Field::new("region", DataType::Utf8, true),
+        Field::new("ats_type", DataType::Utf8, true),
+        Field::new("verified_at", DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())), true),
+        Field::new("is_primary", DataType::Boolean, true),
+        Field::new("kind", DataType::Utf8, true),
+        Field::new("evidence", DataType::Utf8, true),
+        Field::new("valid_from", DataType::Date32, true),
+        Field::new("valid_to", DataType::Date32, true),
+        Field::new("source", DataType::Utf8, true),
+        Field::new("producer", DataType::Utf8, true),
+        Field::new("confidence", DataType::Float32, true),
+        Field::new("recorded_at", DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())), true),
+    ])
+}
+
+fn facts_to_record_batch(facts: Vec<FlatCompanyFact>) -> Result<RecordBatch, Error> {
+    let columns: Vec<Arc<dyn Array>> = vec![
+        Arc::new(Int64Array::from_iter_values(facts.iter().map(|f| f.company))),
+        Arc::new(Int32Array::from_iter(facts.iter().map(|f| f.cik))),
+        Arc::new(StringArray::from_iter_values(facts.iter().map(|f| f.fact.as_str()))),
+        Arc::new(StringArray::from_iter_values(facts.iter().map(|f| f.value.as_str()))),
+        Arc::new(StringArray::from_iter(facts.iter().map(|f| f.title.as_deref()))),
+        Arc::new(StringArray::from_iter(facts.iter().map(|f| f.role.as_deref()))),
+        Arc::new(Int32Array::from_iter(facts.iter().map(|f| f.search_rank))),
+        Arc::new(StringArray::from_iter(facts.iter().map(|f| f.snippet.as_deref()))),
+        Arc::new(StringArray::from_iter(facts.iter().map(|f| f.source_engine.as_deref()))),
+        Arc::new(TimestampMicrosecondArray::from_iter(facts.iter().map(|f| f.discovered_at.map(|t| t.timestamp_micros())))
+            .with_timezone("UTC")),
+        Arc::new(Int32Array::from_iter(facts.iter().map(|f| f.http_status))),
+        Arc::new(StringArray::from_iter(facts.iter().map(|f| f.label.as_deref()))),
+        Arc::new(StringArray::from_iter(facts.iter().map(|f| f.region.as_deref()))),
+        Arc::new(StringArray::from_iter(facts.iter().map(|f| f.ats_type.as_deref()))),
+        Arc::new(TimestampMicrosecondArray::from_iter(facts.iter().map(|f| f.verified_at.map(|t| t.timestamp_micros())))
+            .with_timezone("UTC")),
+        Arc::new(BooleanArray::from_iter(facts.iter().map(|f| f.is_primary))),
+        Arc::new(StringArray::from_iter(facts.iter().map(|f| f.kind.as_deref()))),
+        Arc::new(StringArray::from_iter(facts.iter().map(|f| f.evidence.as_deref()))),
+        Arc::new(Date32Array::from_iter(facts.iter().map(|f| f.valid_from.map(Date32Type::from_naive_date)))),
+        Arc::new(Date32Array::from_iter(facts.iter().map(|f| f.valid_to.map(Date32Type::from_naive_date)))),
+        Arc::new(StringArray::from_iter(facts.iter().map(|f| f.source.as_deref()))),
+        Arc::new(StringArray::from_iter(facts.iter().map(|f| f.producer.as_deref()))),
+        Arc::new(Float32Array::from_iter(facts.iter().map(|f| f.confidence))),
+        Arc::new(TimestampMicrosecondArray::from_iter(facts.iter().map(|f| f.recorded_at.map(|t| t.timestamp_micros())))
+            .with_timezone("UTC")),
+    ];
+    Ok(RecordBatch::try_new(Arc::new(fact_schema()), columns)?)
+}
+
+fn record_batch_to_facts(batch: &RecordBatch) -> Result<Vec<FlatCompanyFact>, Error> {
+    fn column<'a, T: 'static>(batch: &'a RecordBatch, name: &str) -> Result<&'a T, Error> {
+        match batch.column_by_name(name).and_then(|column| column.as_any().downcast_ref::<T>()) {
+            Some(column) => Ok(column),
+            None => bail!("Parquet file is missing the {} column, or it has the wrong type", name),
+        }
+    }
+    fn optional_string(column: &StringArray, row: usize) -> Option<String> {
+        if column.is_null(row) { None } else { Some(column.value(row).to_string()) }
+    }
+    fn optional_i32(column: &Int32Array, row: usize) -> Option<i32> {
+        if column.is_null(row) { None } else { Some(column.value(row)) }
+    }
+    fn optional_date(column: &Date32Array, row: usize) -> Option<NaiveDate> {
+        if column.is_null(row) { None } else { Some(Date32Type::to_naive_date(column.value(row))) }
+    }
+    fn optional_timestamp(column: &TimestampMicrosecondArray, row: usize) -> Option<DateTime<Utc>> {
+        if column.is_null(row) { None } else { DateTime::from_timestamp_micros(column.value(row)) }
+    }
+
+    let company = column::<Int64Array>(batch, "company")?;
+    let cik = column::<Int32Array>(batch, "cik")?;
+    let fact = column::<StringArray>(batch, "fact")?;
+    let value = column::<StringArray>(batch, "value")?;
+    let title = column::<StringArray>(batch, "title")?;
+    let role = column::<StringArray>(batch, "role")?;
+    let search_rank = column::<Int32Array>(batch, "search_rank")?;
+    let snippet = column::<StringArray>(batch, "snippet")?;
+    let source_engine = column::<StringArray>(batch, "source_engine")?;
+    let discovered_at = column::<TimestampMicrosecondArray>(batch, "discovered_at")?;
+    let http_status = column::<Int32Array>(batch, "http_status")?;
+    let label = column::<StringArray>(batch, "label")?;
+    let region = column::<StringArray>(batch, "region")?;
+    let ats_type = column::<StringArray>(batch, "ats_type")?;
+    let verified_at = column::<TimestampMicrosecondArray>(batch, "verified_at")?;
+    let is_primary = column::<BooleanArray>(batch, "is_primary")?;
+    let kind = column::<StringArray>(batch, "kind")?;
+    let evidence = column::<StringArray>(batch, "evidence")?;
+    let valid_from = column::<Date32Array>(batch, "valid_from")?;
+    let valid_to = column::<Date32Array>(batch, "valid_to")?;
+    let source = column::<StringArray>(batch, "source")?;
+    let producer = column::<StringArray>(batch, "producer")?;
+    let confidence = column::<Float32Array>(batch, "confidence")?;
+    let recorded_at = column::<TimestampMicrosecondArray>(batch, "recorded_at")?;
+
+    let mut facts = Vec::new();
+    for row in 0..batch.num_rows() {
+        facts.push(FlatCompanyFact {
+            company: company.value(row),
+            cik: optional_i32(cik, row),
+            fact: fact.value(row).to_string(),
+            value: value.value(row).to_string(),
+            title: optional_string(title, row),
+            role: optional_string(role, row),
+            search_rank: optional_i32(search_rank, row),
+            snippet: optional_string(snippet, row),
+            source_engine: optional_string(source_engine, row),
+            discovered_at: optional_timestamp(discovered_at, row),
+            http_status: optional_i32(http_status, row),
+            label: optional_string(label, row),
+            region: optional_string(region, row),
+            ats_type: optional_string(ats_type, row),
+            verified_at: optional_timestamp(verified_at, row),
+            is_primary: if is_primary.is_null(row) { None } else { Some(is_primary.value(row)) },
+            kind: optional_string(kind, row),
+            evidence: optional_string(evidence, row),
+            valid_from: optional_date(valid_from, row),
+            valid_to: optional_date(valid_to, row),
+            source: optional_string(source, row),
+            producer: optional_string(producer, row),
+            confidence: if confidence.is_null(row) { None } else { Some(confidence.value(row)) },
+            recorded_at: optional_timestamp(recorded_at, row),
+        });
+    }
+    Ok(facts)
+}
+
+impl CompanyDataStore {
+    /// Writes every company graph to a file. Returns how many companies were exported.
+    ///
+    /// The export isn't a complete backup of the store: soft deleted companies are left out (so a restore
+    /// drops them and their tombstones), and so is each company's discovery state (imported companies
+    /// are queued for discovery again) and the relationships between companies (which refer to sids,
+    /// and an import doesn't keep sids). How many deleted companies were left out is printed.
+    pub async fn export_companies(&self, path: &Path, format: ExportFormat) -> Result<usize, Error> {
+        let companies = self.query_companies(&CompanyQuery::new()).await?;
+        write_companies(path, format, &companies)?;
+        let deleted = self.count_companies(&CompanyQuery::new().include_deleted(true)).await? - companies.len() as i64;
+        println!("Exported {} companies to {:?}, leaving out {} soft deleted companies, the discovery state and relationships",
+                 companies.len(), path, deleted);
+        Ok(companies.len())
+    }
+
+    /// Adds every company in a file written by export_companies, keeping its provenance.
+    /// Meant for restoring into an empty store; companies with an identifier that already exists are skipped.
+    /// Imported companies start out pending discovery, like any newly added company.
+    /// Returns how many companies were imported.
+    pub async fn import_companies(&mut self, path: &Path, format: ExportFormat, dry_run: bool) -> Result<usize, Error> {
+        let companies = read_companies(path, format)?;
+        let mut imported = 0;
+        for company in companies {
+            if let Some(sid) = self.find_sid_by_identifiers(&company.get_identifiers()).await? {
+                println!("Company with sid {} already exists, skipping", sid);
+                continue;
+            }
+            self.add_company(company, dry_run).await?;
+            imported += 1;
+        }
+        println!("Imported {} companies from {:?}", imported, path);
+        Ok(imported)
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use anyhow::{bail, Error};
+use chrono::{DateTime, Utc};
+use crate::CompanyDataStore;
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Installs the triggers that set last_changed_at when a website or career page is updated with different content.
+/// Each trigger passes the columns that don't count as content, like when the row was last checked.
+pub(crate) const FRESHNESS_TRIGGERS: &[&str] = &[
+    "CREATE OR REPLACE FUNCTION touch_last_changed_at() RETURNS trigger AS $$
+    BEGIN
+        IF (to_jsonb(NEW) - TG_ARGV) IS DISTINCT FROM (to_jsonb(OLD) - TG_ARGV) THEN
+            NEW.last_changed_at := now();
+        END IF;
+        RETURN NEW;
+    END;
+    $$ LANGUAGE plpgsql",
+    "DROP TRIGGER IF EXISTS touch_last_changed_at ON CompanyWebsites",
+    "CREATE TRIGGER touch_last_changed_at BEFORE UPDATE ON CompanyWebsites \
+        FOR EACH ROW EXECUTE FUNCTION touch_last_changed_at('last_verified_at', 'last_changed_at', \
+            'search_rank', 'snippet', 'source_engine', 'discovered_at', 'source', 'producer', 'confidence', 'recorded_at')",
+    "DROP TRIGGER IF EXISTS touch_last_changed_at ON CompanyCareerPage",
+    "CREATE TRIGGER touch_last_changed_at BEFORE UPDATE ON CompanyCareerPage \
+        FOR EACH ROW EXECUTE FUNCTION touch_last_changed_at('last_verified_at', 'last_changed_at', \
+            'is_primary', 'source', 'producer', 'confidence', 'recorded_at')",
+];
+
+/// The kinds of stored links a verification worker checks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VerificationTarget {
+    Website,
+    CareerPage,
+}
+
+impl VerificationTarget {
+    pub fn as_str(&self) -> &str {
+        match self {
+            VerificationTarget::Website => "website",
+            VerificationTarget::CareerPage => "career_page",
+        }
+    }
+}
+
+impl fmt::Display for VerificationTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for VerificationTarget {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "website" => Ok(VerificationTarget::Website),
+            "career_page" => Ok(VerificationTarget::CareerPage),
+            _ => bail!("Unknown verification target: {}", s),
+        }
+    }
+}
+
+/// How often one kind of link is checked again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefreshPolicy {
+    /// How long after a successful check the link is checked again
+    pub interval: Duration,
+    /// How long after a failed check (a 4xx or 5xx status) the link is checked again, usually sooner
+    pub failing_interval: Duration,
+}
+
+impl RefreshPolicy {
+    pub fn new(interval: Duration, failing_interval: Duration) -> RefreshPolicy {
+        RefreshPolicy { interval, failing_interval }
+    }
+
+    /// When a link checked at `last_verified_at` (returning `http_status`) is next due.
+    /// Links that were never checked are due as soon as they're stored, so this returns None for them.
+    pub fn next_check(&self, last_verified_at: Option<DateTime<Utc>>, http_status: Option<u16>) -> Option<DateTime<Utc>> {
+        let interval = match http_status {
+            Some(status) if status >= 400 => self.failing_interval,
+            _ => self.interval,
+        };
+        Some(last_verified_at? + chrono::Duration::from_std(interval).ok()?)
+    }
+}
+
+/// How often each kind of link is checked again, see CompanyDataStore::get_overdue_links
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefreshPolicies {
+    pub websites: RefreshPolicy,
+    pub career_pages: RefreshPolicy,
+}
+
+impl Default for RefreshPolicies {
+    /// Career pages every 14 days and websites every 30, with failing ones retried after a day
+    fn default() -> Self {
+        RefreshPolicies {
+            websites: RefreshPolicy::new(Duration::from_secs(30 * DAY), Duration::from_secs(DAY)),
+            career_pages: RefreshPolicy::new(Duration::from_secs(14 * DAY), Duration::from_secs(DAY)),
+        }
+    }
+}
+
+impl RefreshPolicies {
+    pub fn for_target(&self, target: VerificationTarget) -> &RefreshPolicy {
+        match target {
+            VerificationTarget::Website => &self.websites,
+            VerificationTarget::CareerPage => &self.career_pages,
+        }
+    }
+}
+
+/// A website or career page that is due to be checked again
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverdueLink {
+    pub target: VerificationTarget,
+    pub sid: i32,
+    pub url: String,
+    /// The status code the last time the link was checked
+    pub http_status: Option<u16>,
+    /// None if the link was never checked
+    pub last_verified_at: Option<DateTime<Utc>>,
+    pub last_changed_at: Option<DateTime<Utc>>,
+    /// When the link became due; when it was stored, if it was never checked
+    pub due_at: Option<DateTime<Utc>>,
+}
+
+impl CompanyDataStore {
+    /// The websites and career pages of live companies that are due to be checked again under the policies,
+    /// most overdue first; links that were never checked come before the rest.
+    /// A verification worker checks them and records the results with set_website_http_status
+    /// or verify_career_page, which moves them to the back of the line.
+    pub async fn get_overdue_links(&self, policies: &RefreshPolicies, limit: i64) -> Result<Vec<OverdueLink>, Error> {
+        let due_at = |table: &str, interval: &str, failing_interval: &str| format!(
+            "CASE WHEN {table}.last_verified_at IS NULL THEN {table}.recorded_at \
+                WHEN {table}.http_status >= 400 THEN {table}.last_verified_at + make_interval(secs => {failing}) \
+                ELSE {table}.last_verified_at + make_interval(secs => {interval}) END",
+            table = table, interval = interval, failing = failing_interval);
+        let query = format!(
+            "SELECT target, sid, url, http_status, last_verified_at, last_changed_at, due_at FROM (\
+                SELECT 'website' AS target, w.sid, w.website_link AS url, w.http_status, w.last_verified_at, \
+                    w.last_changed_at, {website_due_at} AS due_at \
+                FROM CompanyWebsites w JOIN CompanyTable c ON c.sid = w.sid WHERE c.deleted_at IS NULL \
+                UNION ALL \
+                SELECT 'career_page', p.sid, p.career_page_link, p.http_status, p.last_verified_at, \
+                    p.last_changed_at, {career_page_due_at} \
+                FROM CompanyCareerPage p JOIN CompanyTable c ON c.sid = p.sid WHERE c.deleted_at IS NULL) due \
+            WHERE due_at IS NULL OR due_at <= now() \
+            ORDER BY last_verified_at IS NOT NULL, due_at NULLS FIRST, sid, url LIMIT $5",
+            website_due_at = due_at("w", "$1", "$2"), career_page_due_at = due_at("p", "$3", "$4"));
+        let results = self.postgres_client.query(&query, &[
+            &policies.websites.interval.as_secs_f64(), &policies.websites.failing_interval.as_secs_f64(),
+            &policies.career_pages.interval.as_secs_f64(), &policies.career_pages.failing_interval.as_secs_f64(),
+            &limit,
+        ]).await?;
+        let mut links = Vec::new();
+        for row in results {
+            let target: String = row.get(0);
+            let http_status: Option<i32> = row.get(3);
+            links.push(OverdueLink {
+                target: VerificationTarget::from_str(&target)?,
+                sid: row.get(1),
+                url: row.get(2),
+                http_status: http_status.map(|status| status as u16),
+                last_verified_at: row.get(4),
+                last_changed_at: row.get(5),
+                due_at: row.get(6),
+            });
+        }
+        Ok(links)
+    }
+}
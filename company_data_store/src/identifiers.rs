@@ -0,0 +1,103 @@
+use std::str::FromStr;
+use anyhow::{bail, Error};
+use company_common::{ExternalIdentifier, IdentifierKind};
+use tokio_postgres::GenericClient;
+use crate::CompanyDataStore;
+
+/// Fails if another company got the identifier first, leaving the transaction it runs in to roll back
+pub(crate) async fn insert_identifier<C: GenericClient>(client: &C, sid: &i32, identifier: &ExternalIdentifier) -> Result<(), Error> {
+    let inserted = client.execute(
+        "INSERT INTO CompanyIdentifiers (kind, value, sid) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+        &[&identifier.kind.as_str(), &identifier.value, sid],
+    ).await?;
+    if inserted == 0 {
+        bail!("Identifier {} already belongs to another company", identifier);
+    }
+    if identifier.kind == IdentifierKind::Cik {
+        let cik: i32 = identifier.value.parse()?;
+        client.execute("INSERT INTO CikToSid (cik, sid) VALUES ($1, $2) ON CONFLICT DO NOTHING", &[&cik, sid]).await?;
+    }
+    Ok(())
+}
+
+impl CompanyDataStore {
+    /// Records an identifier of the company in another system. An identifier can only belong to one company;
+    /// adding one that belongs to another company fails. CIKs are recorded in CikToSid too.
+    pub async fn add_identifier(&mut self, sid: &i32, identifier: &ExternalIdentifier, dry_run: bool) -> Result<(), Error> {
+        let query = "INSERT INTO CompanyIdentifiers (kind, value, sid) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(());
+        }
+        if let Some(owner) = self.get_sid_from_identifier(identifier).await? {
+            if owner != *sid {
+                bail!("Identifier {} already belongs to company {}", identifier, owner);
+            }
+            return Ok(());
+        }
+        // the identifier and its CikToSid entry are added together
+        let transaction = self.postgres_client.transaction().await?;
+        insert_identifier(&transaction, sid, identifier).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    pub async fn remove_identifier(&mut self, sid: &i32, identifier: &ExternalIdentifier, dry_run: bool) -> Result<(), Error> {
+        let query = "DELETE FROM CompanyIdentifiers WHERE sid = $1 AND kind = $2 AND value = $3".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(());
+        }
+        let transaction = self.postgres_client.transaction().await?;
+        transaction.execute(&query, &[sid, &identifier.kind.as_str(), &identifier.value]).await?;
+        if identifier.kind == IdentifierKind::Cik {
+            let cik: i32 = identifier.value.parse()?;
+            transaction.execute("DELETE FROM CikToSid WHERE sid = $1 AND cik = $2", &[sid, &cik]).await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Every identifier of the company, its CIK included, sorted by kind
+    pub async fn get_identifiers_from_sid(&self, sid: &i32) -> Result<Vec<ExternalIdentifier>, Error> {
+        let results = self.postgres_client.query(
+            "SELECT kind, value FROM CompanyIdentifiers WHERE sid = $1", &[sid],
+        ).await?;
+        let mut identifiers = Vec::new();
+        for row in results {
+            let kind: String = row.get(0);
+            identifiers.push(ExternalIdentifier { kind: IdentifierKind::from_str(&kind)?, value: row.get(1) });
+        }
+        identifiers.sort();
+        Ok(identifiers)
+    }
+
+    /// The company with the given identifier, even if it has been soft deleted
+    pub async fn get_sid_from_identifier(&self, identifier: &ExternalIdentifier) -> Result<Option<i32>, Error> {
+        let results = self.postgres_client.query(
+            "SELECT sid FROM CompanyIdentifiers WHERE kind = $1 AND value = $2", &[&identifier.kind.as_str(), &identifier.value],
+        ).await?;
+        Ok(results.first().map(|row| row.get(0)))
+    }
+
+    /// The company any of the identifiers belong to, or None if none of them are known.
+    /// Fails if they belong to different companies, since they can't all be the same company then.
+    pub async fn find_sid_by_identifiers(&self, identifiers: &[ExternalIdentifier]) -> Result<Option<i32>, Error> {
+        let mut found: Option<(i32, &ExternalIdentifier)> = None;
+        for identifier in identifiers {
+            let sid = match self.get_sid_from_identifier(identifier).await? {
+                Some(sid) => sid,
+                None => continue,
+            };
+            match found {
+                Some((found_sid, found_identifier)) if found_sid != sid => {
+                    bail!("Identifiers {} and {} belong to different companies ({} and {})",
+                        found_identifier, identifier, found_sid, sid);
+                },
+                Some(_) => {},
+                None => found = Some((sid, identifier)),
+            }
+        }
+        Ok(found.map(|(sid, _)| sid))
+    }
+}
@@ -3,8 +3,51 @@ extern crate serde;
 extern crate tokio_postgres;
 extern crate anyhow;
 
+mod query;
+mod prune;
+mod tombstone;
+mod provenance;
+mod discovery;
+mod changes;
+mod stats;
+mod export;
+mod bot_protection;
+mod tags;
+mod search;
+mod career_pages;
+mod postings;
+mod profiles;
+mod relationships;
+mod snapshots;
+mod validation;
+mod urls;
+mod domains;
+mod freshness;
+mod updates;
+mod identifiers;
+mod aliases;
+
+pub use query::*;
+pub use prune::*;
+pub use tombstone::*;
+pub use provenance::*;
+pub use discovery::*;
+pub use changes::*;
+pub use stats::*;
+pub use export::*;
+pub use search::*;
+pub use postings::*;
+pub use profiles::*;
+pub use relationships::*;
+pub use snapshots::*;
+pub use validation::*;
+pub use urls::*;
+pub use domains::*;
+pub use freshness::*;
+pub use updates::*;
+
 use std::env;
-use company_common::{ProcessedCompany};
+use company_common::{canonicalize_url, CanonicalUrl, ExternalIdentifier, IdentifierKind, ProcessedCompany, Provenance, Tag, Website, WebsiteRole};
 use tokio_postgres::*;
 use anyhow::{bail, Error};
 use std::collections::HashSet;
@@ -17,19 +60,59 @@ pub enum CompanyTables {
     CompanyTags,
     CompanyWebsites,
     CompanyCareerPage,
+    CompanyArchive,
+    CompanyDiscovery,
+    WebsiteBotProtection,
+    JobPostings,
+    CandidateProfiles,
+    ProfileResumes,
+    ProfileShortlist,
+    CompanyRelationships,
+    CompanyChangeLog,
+    CompanySnapshots,
+    BlockedDomains,
+    CompanyIdentifiers,
+    DataMigrations,
 }
 
 impl CompanyTables {
+    /// Every table, in the order they need to be created in
+    pub fn all() -> Vec<CompanyTables> {
+        vec![
+            CompanyTables::CompanyTable,
+            CompanyTables::CikToSid,
+            CompanyTables::CompanyAliases,
+            CompanyTables::CompanyTags,
+            CompanyTables::CompanyWebsites,
+            CompanyTables::CompanyCareerPage,
+            CompanyTables::CompanyArchive,
+            CompanyTables::CompanyDiscovery,
+            CompanyTables::WebsiteBotProtection,
+            CompanyTables::JobPostings,
+            CompanyTables::CandidateProfiles,
+            CompanyTables::ProfileResumes,
+            CompanyTables::ProfileShortlist,
+            CompanyTables::CompanyRelationships,
+            CompanyTables::CompanyChangeLog,
+            CompanyTables::CompanySnapshots,
+            CompanyTables::BlockedDomains,
+            CompanyTables::CompanyIdentifiers,
+            CompanyTables::DataMigrations,
+        ]
+    }
+
     fn as_sql(&self) -> &str {
         match self {
             CompanyTables::CompanyTable => {
-                "sid SERIAL PRIMARY KEY"
+                "sid SERIAL PRIMARY KEY, deleted_at TIMESTAMPTZ, deleted_reason TEXT, created_at TIMESTAMPTZ DEFAULT now(), \
+                version BIGINT NOT NULL DEFAULT 1"
             },
             CompanyTables::CikToSid => {
                 "cik INTEGER PRIMARY KEY, sid INTEGER REFERENCES CompanyTable(sid) ON DELETE CASCADE"
             },
             CompanyTables::CompanyAliases => {
-                "CompanyAlias VARCHAR(255), sid INTEGER, \
+                "CompanyAlias VARCHAR(255), sid INTEGER, kind VARCHAR(16), valid_from DATE, valid_to DATE, \
+                is_primary BOOLEAN NOT NULL DEFAULT false, source VARCHAR(32), producer VARCHAR(128), confidence REAL, recorded_at TIMESTAMPTZ DEFAULT now(), \
                 PRIMARY KEY (CompanyAlias, sid), FOREIGN KEY (sid) REFERENCES CompanyTable(sid) ON DELETE CASCADE"
             },
             CompanyTables::CompanyTags => {
@@ -37,12 +120,89 @@ impl CompanyTables {
                 PRIMARY KEY (sid, tag), FOREIGN KEY (sid) REFERENCES CompanyTable(sid) ON DELETE CASCADE"
             },
             CompanyTables::CompanyWebsites => {
-                "sid INTEGER, website_title VARCHAR(512), website_link VARCHAR(512), has_captcha BOOLEAN, \
+                "sid INTEGER, website_title VARCHAR(512), website_link VARCHAR(512), \
+                role VARCHAR(32) DEFAULT 'unknown', search_rank INTEGER, snippet TEXT, source_engine VARCHAR(32), \
+                discovered_at TIMESTAMPTZ, http_status INTEGER, last_verified_at TIMESTAMPTZ, last_changed_at TIMESTAMPTZ DEFAULT now(), \
+                source VARCHAR(32), producer VARCHAR(128), confidence REAL, recorded_at TIMESTAMPTZ DEFAULT now(), \
                 PRIMARY KEY (sid, website_link), FOREIGN KEY (sid) REFERENCES CompanyTable(sid) ON DELETE CASCADE"
             },
             CompanyTables::CompanyCareerPage => {
-                "sid INTEGER PRIMARY KEY, career_page_link VARCHAR(255),\
-                FOREIGN KEY (sid) REFERENCES CompanyTable(sid) ON DELETE CASCADE"
+                "sid INTEGER, career_page_link VARCHAR(2048), label TEXT, region VARCHAR(64), ats_type VARCHAR(32), \
+                http_status INTEGER, last_verified_at TIMESTAMPTZ, last_changed_at TIMESTAMPTZ DEFAULT now(), \
+                is_primary BOOLEAN NOT NULL DEFAULT false, \
+                source VARCHAR(32), producer VARCHAR(128), confidence REAL, recorded_at TIMESTAMPTZ DEFAULT now(), \
+                PRIMARY KEY (sid, career_page_link), FOREIGN KEY (sid) REFERENCES CompanyTable(sid) ON DELETE CASCADE"
+            },
+            CompanyTables::CompanyArchive => {
+                "archive_id SERIAL PRIMARY KEY, sid INTEGER, archived_at TIMESTAMPTZ DEFAULT now(), \
+                reason TEXT, company JSONB, postings JSONB"
+            },
+            CompanyTables::CompanyDiscovery => {
+                "sid INTEGER PRIMARY KEY REFERENCES CompanyTable(sid) ON DELETE CASCADE, \
+                status VARCHAR(16) NOT NULL DEFAULT 'pending', attempts INTEGER NOT NULL DEFAULT 0, last_error TEXT, \
+                lease_owner VARCHAR(128), leased_until TIMESTAMPTZ, next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now(), \
+                updated_at TIMESTAMPTZ NOT NULL DEFAULT now(), \
+                career_page_from INTEGER REFERENCES CompanyTable(sid) ON DELETE SET NULL"
+            },
+            CompanyTables::WebsiteBotProtection => {
+                "sid INTEGER, url VARCHAR(2048), kind VARCHAR(32), detected_at TIMESTAMPTZ DEFAULT now(), evidence TEXT, \
+                PRIMARY KEY (sid, url, kind), FOREIGN KEY (sid) REFERENCES CompanyTable(sid) ON DELETE CASCADE"
+            },
+            CompanyTables::JobPostings => {
+                "posting_id BIGSERIAL PRIMARY KEY, sid INTEGER NOT NULL REFERENCES CompanyTable(sid) ON DELETE CASCADE, \
+                source VARCHAR(64) NOT NULL, external_id VARCHAR(256) NOT NULL, url VARCHAR(2048), title TEXT, \
+                raw_description TEXT, description TEXT, location TEXT, department TEXT, employment_type VARCHAR(32), \
+                posted_date DATE, first_seen_at TIMESTAMPTZ NOT NULL DEFAULT now(), last_seen_at TIMESTAMPTZ NOT NULL DEFAULT now(), \
+                status VARCHAR(16) NOT NULL DEFAULT 'open', closed_at TIMESTAMPTZ, \
+                UNIQUE (sid, source, external_id)"
+            },
+            CompanyTables::CandidateProfiles => {
+                "profile_id SERIAL PRIMARY KEY, handle VARCHAR(64) NOT NULL UNIQUE, name TEXT, email TEXT, phone TEXT, \
+                target_titles TEXT[] NOT NULL DEFAULT '{}', locations TEXT[] NOT NULL DEFAULT '{}', \
+                remote_preference VARCHAR(16) NOT NULL DEFAULT 'any', salary_floor INTEGER, salary_currency VARCHAR(3), \
+                work_authorization TEXT[] NOT NULL DEFAULT '{}', excluded_sids INTEGER[] NOT NULL DEFAULT '{}', \
+                created_at TIMESTAMPTZ NOT NULL DEFAULT now(), updated_at TIMESTAMPTZ NOT NULL DEFAULT now()"
+            },
+            CompanyTables::ProfileResumes => {
+                "resume_id SERIAL PRIMARY KEY, \
+                profile_id INTEGER NOT NULL REFERENCES CandidateProfiles(profile_id) ON DELETE CASCADE, \
+                name TEXT NOT NULL, location TEXT NOT NULL, added_at TIMESTAMPTZ NOT NULL DEFAULT now()"
+            },
+            CompanyTables::ProfileShortlist => {
+                "profile_id INTEGER REFERENCES CandidateProfiles(profile_id) ON DELETE CASCADE, \
+                sid INTEGER REFERENCES CompanyTable(sid) ON DELETE CASCADE, note TEXT, \
+                added_at TIMESTAMPTZ NOT NULL DEFAULT now(), PRIMARY KEY (profile_id, sid)"
+            },
+            CompanyTables::CompanyRelationships => {
+                "child_sid INTEGER NOT NULL REFERENCES CompanyTable(sid) ON DELETE CASCADE, \
+                parent_sid INTEGER NOT NULL REFERENCES CompanyTable(sid) ON DELETE CASCADE, \
+                kind VARCHAR(32) NOT NULL, valid_from DATE, valid_to DATE, created_at TIMESTAMPTZ NOT NULL DEFAULT now(), \
+                CHECK (child_sid <> parent_sid), CHECK (valid_to > valid_from)"
+            },
+            // no foreign key on sid, so the changes to deleted companies are kept
+            CompanyTables::CompanyChangeLog => {
+                "change_id BIGSERIAL PRIMARY KEY, changed_at TIMESTAMPTZ NOT NULL DEFAULT now(), \
+                table_name VARCHAR(32) NOT NULL, operation VARCHAR(8) NOT NULL, sid INTEGER NOT NULL, \
+                row_key TEXT NOT NULL, old_row JSONB, new_row JSONB, txid XID8 DEFAULT pg_current_xact_id()"
+            },
+            CompanyTables::CompanySnapshots => {
+                "name VARCHAR(128) PRIMARY KEY, change_id BIGINT NOT NULL, xid_snapshot TEXT, \
+                created_at TIMESTAMPTZ NOT NULL DEFAULT now(), note TEXT"
+            },
+            // unblocked domains are kept, so seeding the built in blocklist doesn't block them again
+            CompanyTables::BlockedDomains => {
+                "domain VARCHAR(253) PRIMARY KEY, role VARCHAR(32) NOT NULL, blocked BOOLEAN NOT NULL DEFAULT true, \
+                note TEXT, added_at TIMESTAMPTZ NOT NULL DEFAULT now()"
+            },
+            // an identifier belongs to at most one company
+            CompanyTables::CompanyIdentifiers => {
+                "kind VARCHAR(32), value VARCHAR(255), \
+                sid INTEGER NOT NULL REFERENCES CompanyTable(sid) ON DELETE CASCADE, \
+                recorded_at TIMESTAMPTZ NOT NULL DEFAULT now(), PRIMARY KEY (kind, value)"
+            },
+            // the one-off data migrations that have run on the database, by name
+            CompanyTables::DataMigrations => {
+                "name VARCHAR(64) PRIMARY KEY, applied_at TIMESTAMPTZ NOT NULL DEFAULT now()"
             },
         }
     }
@@ -67,13 +227,158 @@ impl CompanyTables {
             CompanyTables::CompanyCareerPage => {
                 "CompanyCareerPage"
             },
+            CompanyTables::CompanyArchive => {
+                "CompanyArchive"
+            },
+            CompanyTables::CompanyDiscovery => {
+                "CompanyDiscovery"
+            },
+            CompanyTables::WebsiteBotProtection => {
+                "WebsiteBotProtection"
+            },
+            CompanyTables::JobPostings => {
+                "JobPostings"
+            },
+            CompanyTables::CandidateProfiles => {
+                "CandidateProfiles"
+            },
+            CompanyTables::ProfileResumes => {
+                "ProfileResumes"
+            },
+            CompanyTables::ProfileShortlist => {
+                "ProfileShortlist"
+            },
+            CompanyTables::CompanyRelationships => {
+                "CompanyRelationships"
+            },
+            CompanyTables::CompanyChangeLog => {
+                "CompanyChangeLog"
+            },
+            CompanyTables::CompanySnapshots => {
+                "CompanySnapshots"
+            },
+            CompanyTables::BlockedDomains => {
+                "BlockedDomains"
+            },
+            CompanyTables::CompanyIdentifiers => {
+                "CompanyIdentifiers"
+            },
+            CompanyTables::DataMigrations => {
+                "DataMigrations"
+            },
         }
     }
 }
 
-pub async fn establish_connection() ->  Result<Client, Error>{
+/// Rewriting the URLs stored before URLs were canonicalized reads every stored URL, so unlike MIGRATIONS
+/// it only runs the first time the database is initialized, and is recorded in DataMigrations under this name.
+/// canonicalize_stored_urls can still be run by hand.
+const DATA_MIGRATION_URLS: &str = "canonicalize_stored_urls";
+
+/// Changes to tables that already existed before the change was made. Every statement must be
+/// safe to run again on an up to date database, since these run each time the database is initialized.
+const MIGRATIONS: &[&str] = &[
+    "ALTER TABLE CompanyTable ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ",
+    "ALTER TABLE CompanyTable ADD COLUMN IF NOT EXISTS deleted_reason TEXT",
+    // companies from before creation times were tracked have no created_at
+    "ALTER TABLE CompanyTable ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ",
+    "ALTER TABLE CompanyTable ALTER COLUMN created_at SET DEFAULT now()",
+    "ALTER TABLE CompanyTable ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 1",
+    "ALTER TABLE CompanyDiscovery ADD COLUMN IF NOT EXISTS career_page_from INTEGER \
+        REFERENCES CompanyTable(sid) ON DELETE SET NULL",
+    // changes and snapshots from before transactions were recorded are ordered by change_id alone
+    "ALTER TABLE CompanyChangeLog ADD COLUMN IF NOT EXISTS txid XID8",
+    "ALTER TABLE CompanyChangeLog ALTER COLUMN txid SET DEFAULT pg_current_xact_id()",
+    "ALTER TABLE CompanySnapshots ADD COLUMN IF NOT EXISTS xid_snapshot TEXT",
+    // companies archived before postings existed have none
+    "ALTER TABLE CompanyArchive ADD COLUMN IF NOT EXISTS postings JSONB",
+    // facts from before provenance was tracked have no recorded_at; when they were recorded isn't known
+    "ALTER TABLE CompanyAliases ADD COLUMN IF NOT EXISTS source VARCHAR(32), \
+        ADD COLUMN IF NOT EXISTS producer VARCHAR(128), ADD COLUMN IF NOT EXISTS confidence REAL, \
+        ADD COLUMN IF NOT EXISTS recorded_at TIMESTAMPTZ",
+    "ALTER TABLE CompanyAliases ALTER COLUMN recorded_at SET DEFAULT now()",
+    "ALTER TABLE CompanyAliases ADD COLUMN IF NOT EXISTS kind VARCHAR(16), \
+        ADD COLUMN IF NOT EXISTS valid_from DATE, ADD COLUMN IF NOT EXISTS valid_to DATE, \
+        ADD COLUMN IF NOT EXISTS is_primary BOOLEAN NOT NULL DEFAULT false",
+    "ALTER TABLE CompanyWebsites ADD COLUMN IF NOT EXISTS source VARCHAR(32), \
+        ADD COLUMN IF NOT EXISTS producer VARCHAR(128), ADD COLUMN IF NOT EXISTS confidence REAL, \
+        ADD COLUMN IF NOT EXISTS recorded_at TIMESTAMPTZ",
+    "ALTER TABLE CompanyWebsites ALTER COLUMN recorded_at SET DEFAULT now()",
+    "ALTER TABLE CompanyCareerPage ADD COLUMN IF NOT EXISTS source VARCHAR(32), \
+        ADD COLUMN IF NOT EXISTS producer VARCHAR(128), ADD COLUMN IF NOT EXISTS confidence REAL, \
+        ADD COLUMN IF NOT EXISTS recorded_at TIMESTAMPTZ",
+    "ALTER TABLE CompanyCareerPage ALTER COLUMN recorded_at SET DEFAULT now()",
+    "ALTER TABLE CompanyWebsites ADD COLUMN IF NOT EXISTS role VARCHAR(32) DEFAULT 'unknown', \
+        ADD COLUMN IF NOT EXISTS search_rank INTEGER, ADD COLUMN IF NOT EXISTS snippet TEXT, \
+        ADD COLUMN IF NOT EXISTS source_engine VARCHAR(32), ADD COLUMN IF NOT EXISTS discovered_at TIMESTAMPTZ, \
+        ADD COLUMN IF NOT EXISTS http_status INTEGER",
+    // companies used to have a single career page, keyed by sid alone
+    "DO $$ BEGIN
+        IF NOT EXISTS (SELECT 1 FROM information_schema.columns
+                       WHERE table_name = 'companycareerpage' AND column_name = 'is_primary') THEN
+            DELETE FROM CompanyCareerPage WHERE career_page_link IS NULL;
+            ALTER TABLE CompanyCareerPage DROP CONSTRAINT IF EXISTS companycareerpage_pkey,
+                ALTER COLUMN career_page_link TYPE VARCHAR(2048),
+                ADD COLUMN label TEXT, ADD COLUMN region VARCHAR(64), ADD COLUMN ats_type VARCHAR(32),
+                ADD COLUMN http_status INTEGER, ADD COLUMN verified_at TIMESTAMPTZ,
+                ADD COLUMN is_primary BOOLEAN NOT NULL DEFAULT true,
+                ADD PRIMARY KEY (sid, career_page_link);
+            ALTER TABLE CompanyCareerPage ALTER COLUMN is_primary SET DEFAULT false;
+        END IF;
+    END $$",
+    "DO $$ BEGIN
+        IF EXISTS (SELECT 1 FROM information_schema.columns
+                   WHERE table_name = 'companycareerpage' AND column_name = 'verified_at') THEN
+            ALTER TABLE CompanyCareerPage RENAME COLUMN verified_at TO last_verified_at;
+        END IF;
+    END $$",
+    // rows from before changes were tracked have no last_changed_at
+    "ALTER TABLE CompanyCareerPage ADD COLUMN IF NOT EXISTS last_changed_at TIMESTAMPTZ",
+    "ALTER TABLE CompanyCareerPage ALTER COLUMN last_changed_at SET DEFAULT now()",
+    "ALTER TABLE CompanyWebsites ADD COLUMN IF NOT EXISTS last_verified_at TIMESTAMPTZ, \
+        ADD COLUMN IF NOT EXISTS last_changed_at TIMESTAMPTZ",
+    "ALTER TABLE CompanyWebsites ALTER COLUMN last_changed_at SET DEFAULT now()",
+    "CREATE UNIQUE INDEX IF NOT EXISTS company_career_page_primary_index ON CompanyCareerPage (sid) WHERE is_primary",
+    "CREATE UNIQUE INDEX IF NOT EXISTS company_alias_primary_index ON CompanyAliases (sid) WHERE is_primary",
+    // companies from before there were primary names get one, chosen the same way as PROMOTE_PRIMARY_ALIAS
+    "UPDATE CompanyAliases a SET is_primary = true FROM (\
+        SELECT DISTINCT ON (sid) sid, CompanyAlias FROM CompanyAliases \
+        ORDER BY sid, COALESCE(valid_from > CURRENT_DATE, false) OR COALESCE(valid_to <= CURRENT_DATE, false), \
+            CASE kind WHEN 'legal' THEN 0 WHEN 'brand' THEN 1 WHEN 'ticker' THEN 3 WHEN 'former' THEN 4 ELSE 2 END, \
+            valid_from DESC NULLS LAST, CompanyAlias COLLATE \"C\") best \
+    WHERE a.sid = best.sid AND a.CompanyAlias = best.CompanyAlias \
+        AND NOT EXISTS (SELECT 1 FROM CompanyAliases p WHERE p.sid = a.sid AND p.is_primary)",
+    // a relationship can hold over several periods, but only one starting on each day
+    "CREATE UNIQUE INDEX IF NOT EXISTS company_relationship_period_index \
+        ON CompanyRelationships (child_sid, parent_sid, kind, COALESCE(valid_from, '-infinity'::DATE))",
+    "CREATE INDEX IF NOT EXISTS company_relationship_parent_index ON CompanyRelationships (parent_sid)",
+    "CREATE INDEX IF NOT EXISTS company_identifier_sid_index ON CompanyIdentifiers (sid)",
+    // CIKs are identifiers like any other, but were recorded only in CikToSid before there were identifiers
+    "INSERT INTO CompanyIdentifiers (kind, value, sid) SELECT 'cik', cik::TEXT, sid FROM CikToSid ON CONFLICT DO NOTHING",
+    // every company gets a discovery queue entry; companies that already have websites are done
+    "INSERT INTO CompanyDiscovery (sid, status) \
+        SELECT sid, CASE WHEN sid IN (SELECT sid FROM CompanyWebsites) THEN 'discovered' ELSE 'pending' END \
+        FROM CompanyTable ON CONFLICT (sid) DO NOTHING",
+    // bot protection used to be a single has_captcha flag per website
+    "DO $$ BEGIN
+        IF EXISTS (SELECT 1 FROM information_schema.columns
+                   WHERE table_name = 'companywebsites' AND column_name = 'has_captcha') THEN
+            INSERT INTO WebsiteBotProtection (sid, url, kind, evidence)
+                SELECT sid, website_link, 'captcha', 'migrated from CompanyWebsites.has_captcha'
+                FROM CompanyWebsites WHERE has_captcha ON CONFLICT DO NOTHING;
+            ALTER TABLE CompanyWebsites DROP COLUMN has_captcha;
+        END IF;
+    END $$",
+];
+
+/// Reads DATABASE_URL from the environment (or .env)
+pub fn database_url() -> Result<String, Error> {
     dotenvy::dotenv()?;
-    let database_url = env::var("DATABASE_URL")?;
+    Ok(env::var("DATABASE_URL")?)
+}
+
+pub async fn establish_connection() ->  Result<Client, Error>{
+    let database_url = database_url()?;
     println!("Attempting to connect to {}", database_url);
     let (client, connection) = connect(
         &database_url,
@@ -88,6 +393,35 @@ pub async fn establish_connection() ->  Result<Client, Error>{
     Ok(client)
 }
 
+const INSERT_COMPANY: &str = "INSERT INTO CompanyTable DEFAULT VALUES RETURNING sid";
+
+/// Creates the company and queues it for discovery, see initialize_company
+async fn insert_company<C: GenericClient>(client: &C) -> Result<i32, Error> {
+    let results = client.query(INSERT_COMPANY, &[]).await?;
+    let sid: i32 = results[0].get(0);
+    client.execute("INSERT INTO CompanyDiscovery (sid) VALUES ($1)", &[&sid]).await?;
+    Ok(sid)
+}
+
+/// Adds a website under its canonical URL, see add_website
+async fn insert_website<C: GenericClient>(client: &C, sid: &i32, website: &Website, provenance: &Provenance) -> Result<(), Error> {
+    let url = CanonicalUrl::parse(&website.url)?.to_string();
+    let results = client.query("SELECT 1 FROM CompanyWebsites WHERE sid = $1 AND website_link = $2", &[sid, &url]).await?;
+    if !results.is_empty() {
+        println!("Company with sid {} already has website {}", sid, url);
+        return Ok(());
+    }
+    let role = website.role.as_str();
+    let search_rank = website.search_rank.map(|rank| rank as i32);
+    let http_status = website.http_status.map(|status| status as i32);
+    insert_with_provenance(client, CompanyTables::CompanyWebsites,
+                           &["sid", "website_title", "website_link", "role", "search_rank", "snippet",
+                             "source_engine", "discovered_at", "http_status"],
+                           vec![sid, &website.title, &url, &role, &search_rank, &website.snippet,
+                                &website.source_engine, &website.discovered_at, &http_status],
+                           provenance).await
+}
+
 pub struct CompanyDataStore {
     postgres_client: Client,
 }
@@ -108,7 +442,7 @@ impl CompanyDataStore {
     }
 
     pub async fn get_companies(&mut self) -> Result<Vec<ProcessedCompany>, Error> {
-        let query = "SELECT sid FROM CompanyTable".to_string();
+        let query = "SELECT sid FROM CompanyTable WHERE deleted_at IS NULL".to_string();
         let results = self.postgres_client.query(&query, &[]).await?;
         let mut companies = Vec::new();
         for row in results {
@@ -124,15 +458,7 @@ impl CompanyDataStore {
     /// @param client: the postgres client
     /// @param dry_run: if true, will print the queries instead of executing them
     pub async fn initialize_database(&mut self, dry_run: bool) -> Result<(), Error> {
-        let tables = vec![
-            CompanyTables::CompanyTable,
-            CompanyTables::CikToSid,
-            CompanyTables::CompanyAliases,
-            CompanyTables::CompanyTags,
-            CompanyTables::CompanyWebsites,
-            CompanyTables::CompanyCareerPage,
-        ];
-        for table in tables {
+        for table in CompanyTables::all() {
             let res = self.create_table(table.as_str(), table.as_sql(), dry_run).await;
             match res {
                 Ok(_) => {
@@ -143,6 +469,59 @@ impl CompanyDataStore {
                 }
             }
         }
+        self.migrate(dry_run).await?;
+        // URLs stored before they were canonicalized wouldn't match the canonical URLs looked up now
+        if !self.data_migration_applied(DATA_MIGRATION_URLS).await? {
+            self.canonicalize_stored_urls(dry_run).await?;
+            self.record_data_migration(DATA_MIGRATION_URLS, dry_run).await?;
+        }
+        self.seed_blocked_domains(dry_run).await?;
+        for statement in CHANGE_TRIGGERS.iter().chain(CHANGE_LOG_TRIGGERS).chain(FRESHNESS_TRIGGERS).chain(VERSION_TRIGGERS) {
+            if dry_run {
+                println!("{}", statement);
+                continue;
+            }
+            self.postgres_client.batch_execute(statement).await?;
+        }
+        // pg_trgm needs the contrib package and enough privileges, search_companies works without it
+        for statement in SEARCH_SETUP {
+            if dry_run {
+                println!("{}", statement);
+                continue;
+            }
+            if let Err(e) = self.postgres_client.batch_execute(statement).await {
+                println!("Couldn't set up trigram search, search_companies will fall back to ILIKE: {}", e);
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Brings tables created by older versions up to date, see MIGRATIONS
+    pub async fn migrate(&mut self, dry_run: bool) -> Result<(), Error> {
+        for migration in MIGRATIONS {
+            if dry_run {
+                println!("{}", migration);
+                continue;
+            }
+            self.postgres_client.execute(*migration, &[]).await?;
+        }
+        Ok(())
+    }
+
+    /// Whether the named data migration already ran on this database
+    async fn data_migration_applied(&self, name: &str) -> Result<bool, Error> {
+        let results = self.postgres_client.query("SELECT 1 FROM DataMigrations WHERE name = $1", &[&name]).await?;
+        Ok(!results.is_empty())
+    }
+
+    async fn record_data_migration(&mut self, name: &str, dry_run: bool) -> Result<(), Error> {
+        let query = "INSERT INTO DataMigrations (name) VALUES ($1) ON CONFLICT DO NOTHING".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(());
+        }
+        self.postgres_client.execute(&query, &[&name]).await?;
         Ok(())
     }
 
@@ -154,6 +533,7 @@ impl CompanyDataStore {
     }
 
     /// Perform a delete operation on the company with the given sid.
+    /// This permanently removes everything we know about the company; prefer soft_delete_company.
     pub async fn delete_company(&mut self, sid: &i32, dry_run: bool) -> Result<(), Error> {
         let query = "DELETE FROM CompanyTable WHERE sid = $1".to_string();
         if dry_run {
@@ -165,21 +545,14 @@ impl CompanyDataStore {
     }
 
 
-    /// Creates an entry into the CompanyTable, which is a serial value.
+    /// Creates an entry into the CompanyTable, which is a serial value, and queues it for discovery.
     /// Returns the sid of the newly created company
-    pub async fn initialize_company(&mut self, dry_run: bool) -> Result<i32, Error> {
-        let query = "INSERT INTO CompanyTable DEFAULT VALUES".to_string();
+    pub async fn initialize_company(&self, dry_run: bool) -> Result<i32, Error> {
         if dry_run {
-            println!("{}", query);
+            println!("{}", INSERT_COMPANY);
             return Ok(0);
         }
-        self.postgres_client.execute(&query, &[]).await?;
-
-        // get the highest serial
-        let query = "SELECT MAX(sid) FROM CompanyTable".to_string();
-        let results = self.postgres_client.query(&query, &[]).await?;
-        let sid: i32 = results[0].get(0);
-        Ok(sid)
+        insert_company(&self.postgres_client).await
     }
 
     /// Create a table in the database with the given name and attributes,
@@ -201,135 +574,185 @@ impl CompanyDataStore {
     }
 
     /// Given some ProcessedCompany, we want to insert the information into the database, with
-    /// its various tables (CompanyTable, CikToSid, CompanyAliases, CompanyTags, CompanyWebsites,
-    /// CompanyCareerPage.
+    /// its various tables (CompanyTable, CompanyIdentifiers, CikToSid, CompanyAliases, CompanyTags,
+    /// CompanyWebsites, CompanyCareerPage.
+    ///
+    /// A company with any of the same identifiers (CIK, LEI, ticker, ...) is the same company, so adding
+    /// it again fails; change the existing one with update_company instead. Companies without any
+    /// identifiers are always added as new companies.
+    ///
+    /// Everything is added in one transaction, so a company that fails part way isn't left half added.
+    /// A dry run adds it and then rolls back.
     ///
     /// Many of these are nullable, seeing as we haven't established the company's tags and
     /// websites yet. (Actually, we're not even going to add rows to their respective tables.
     /// A query on a company's websites will return 0 rows if the company has no websites, ideally.
     pub async fn add_company(&mut self, company: ProcessedCompany, dry_run: bool) -> Result<(), Error> {
-        // check if the company already exists
-        // TODO 1: Figure out some way to do this if there are different identifiers (CIK, ticker, etc.)
-        // TODO 2: Make this a transaction
-        let sid = match company.cik {
-            Some(cik) => {
-                self.cik_exists(&cik).await?
-            }
-            None => {
-                None
-            }
-        };
-
-        let sid = match sid {
-            Some(sid) => {
-                if company.cik.is_some() {
-                    println!("Company with CIK {} already exists.", company.cik.unwrap());
-                }
-                sid
-            },
-            None => {
-                // if we can't initialize the company, then add_company fails.
-                self.initialize_company(dry_run).await?
-            }
-        };
-
-        match company.cik {
-            Some(cik) => {
-               self.insert_into_table(CompanyTables::CikToSid, vec![&cik, &sid], dry_run).await?;
-            },
-            None => {
-                println!("No CIK found for company");
-                return Ok(());
+        let identifiers = company.get_identifiers();
+        // tags come in as plain strings, so malformed ones are turned away before anything is added
+        let tags = company.tags.iter().flatten().map(|tag| Tag::from_str(tag)).collect::<Result<Vec<Tag>, Error>>()?;
+        if let Some(sid) = self.find_sid_by_identifiers(&identifiers).await? {
+            if let Some(tombstone) = self.get_tombstone(&sid).await? {
+                bail!("Company with sid {} was deleted at {} ({}), restore it instead",
+                    sid, tombstone.deleted_at, tombstone.reason);
             }
+            bail!("Company with sid {} already exists, update it instead", sid);
         }
-        // println!("CIK added for company with sid {}", sid);
-
-        for alias in &company.company_aliases {
-            self.add_alias(&sid, alias, dry_run).await?;
+        let company_name = company.get_company_name();
+
+        // the company is added whole or not at all; if another company got one of the identifiers
+        // since the check, inserting it fails and everything rolls back
+        let transaction = self.postgres_client.transaction().await?;
+        let sid = insert_company(&transaction).await?;
+        for identifier in &identifiers {
+            identifiers::insert_identifier(&transaction, &sid, identifier).await?;
         }
-
-        // println!("Alias added for company with sid {}", sid);
-
-        if company.tags.is_some() {
-            for tag in company.tags.unwrap() {
-                self.add_tag(&sid, tag, dry_run).await?;
+        for alias in &company.company_aliases {
+            aliases::insert_alias(&transaction, &sid, alias, &company.get_alias_provenance(alias)).await?;
+            if let Some(metadata) = company.alias_metadata.get(alias) {
+                aliases::update_alias_metadata(&transaction, &sid, alias, metadata).await?;
             }
         }
-        if company.websites.is_some() {
-            for (title, website_link) in company.websites.unwrap() {
-                self.add_website(&sid, &title, &website_link, false, dry_run).await?;
-            }
+        if let Ok(name) = &company_name {
+            aliases::make_primary_alias(&transaction, &sid, name).await?;
         }
-
-        if company.career_page.is_some() {
-            self.add_career_page(&sid, &company.career_page.clone().unwrap(), dry_run).await?;
+        for tag in &tags {
+            transaction.execute("INSERT INTO CompanyTags (sid, tag) VALUES ($1, $2)", &[&sid, &tag.to_string()]).await?;
         }
-
-        if company.has_captcha.is_some() {
-            self.update_captcha_status(&sid, company.career_page.unwrap(), company.has_captcha.unwrap()).await?;
+        for website in company.websites.iter().flatten() {
+            insert_website(&transaction, &sid, website, &company.get_website_provenance(&website.url)).await?;
         }
-        let first_company_name = match company.company_aliases.iter().next() {
-            Some(name) => name,
-            None => {
-                println!("No company name found");
-                return Ok(());
+        for career_page in company.career_pages.iter().flatten() {
+            let provenance = company.get_career_page_provenance(&career_page.url);
+            career_pages::insert_career_page(&transaction, &sid, career_page, &provenance).await?;
+        }
+        for (url, protections) in &company.bot_protection {
+            for protection in protections {
+                bot_protection::insert_bot_protection(&transaction, &sid, url, protection).await?;
             }
-        };
-        println!("Company with sid {} and name {} added", sid, first_company_name);
+        }
+        let company_name = company_name.unwrap_or_else(|_| "<no name>".to_string());
+        if dry_run {
+            transaction.rollback().await?;
+            println!("Would add company {}", company_name);
+            return Ok(());
+        }
+        transaction.commit().await?;
+        println!("Company with sid {} and name {} added", sid, company_name);
         Ok(())
     }
 
     pub async fn add_cik(&mut self, cik: i32, sid: i32, dry_run: bool) -> Result<(), Error> {
-        self.insert_into_table(CompanyTables::CikToSid, vec![&cik, &sid], dry_run).await
+        self.add_identifier(&sid, &ExternalIdentifier::cik(cik), dry_run).await
     }
 
-    pub async fn add_tag(&mut self, sid: &i32, tag: String, dry_run: bool) -> Result<(), Error> {
-        self.insert_into_table(CompanyTables::CompanyTags, vec![sid, &tag], dry_run).await
+    pub async fn add_tag(&mut self, sid: &i32, tag: &Tag, dry_run: bool) -> Result<(), Error> {
+        self.insert_into_table(CompanyTables::CompanyTags, vec![sid, &tag.to_string()], dry_run).await
     }
 
-    pub async fn add_alias(&mut self, sid: &i32, alias: &String, dry_run: bool) -> Result<(), Error>{
-        self.insert_into_table(CompanyTables::CompanyAliases, vec![&alias, sid], dry_run).await
+    /// Adds an alias. A company's first alias becomes its primary name.
+    pub async fn add_alias(&mut self, sid: &i32, alias: &String, provenance: &Provenance, dry_run: bool) -> Result<(), Error>{
+        if dry_run {
+            println!("Would add alias {} to company {}", alias, sid);
+            return Ok(());
+        }
+        aliases::insert_alias(&self.postgres_client, sid, alias, provenance).await
     }
 
-    pub async fn add_website(&mut self, sid: &i32, title: &String, website: &String, has_captcha: bool, dry_run: bool) -> Result<(), Error> {
-        self.insert_into_table(CompanyTables::CompanyWebsites, vec![sid, title, website, &has_captcha], dry_run).await
+    /// Adds a website under its canonical URL. A website the company already has under the same
+    /// canonical URL is left as it is.
+    pub async fn add_website(&mut self, sid: &i32, website: &Website, provenance: &Provenance, dry_run: bool) -> Result<(), Error> {
+        if dry_run {
+            println!("Would add website {} to company {}", website.url, sid);
+            return Ok(());
+        }
+        insert_website(&self.postgres_client, sid, website, provenance).await
     }
 
-    pub async fn update_captcha_status(&mut self, sid: &i32, website: String, has_captcha: bool) -> Result<(), Error> {
-        let query = "UPDATE CompanyWebsites SET has_captcha = $1 WHERE sid = $2 AND website_link = $3".to_string();
-        self.postgres_client.execute(&query, &[&has_captcha, &sid, &website]).await?;
+    /// Records the status code a website returned when it was fetched just now
+    pub async fn set_website_http_status(&mut self, sid: &i32, url: &str, http_status: u16, dry_run: bool) -> Result<(), Error> {
+        let query = "UPDATE CompanyWebsites SET http_status = $3, last_verified_at = now() \
+            WHERE sid = $1 AND website_link = $2".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(());
+        }
+        self.postgres_client.execute(&query, &[sid, &canonicalize_url(url), &(http_status as i32)]).await?;
         Ok(())
     }
 
-    pub async fn add_career_page(&mut self, sid: &i32, career_page: &String, dry_run: bool) -> Result<(), Error> {
-        self.insert_into_table(CompanyTables::CompanyCareerPage, vec![sid, &career_page], dry_run).await
+    /// Removes an alias. If it was the primary name, the best remaining alias becomes primary.
+    pub async fn remove_alias(&mut self, sid: &i32, alias: &str, dry_run: bool) -> Result<(), Error> {
+        let query = "DELETE FROM CompanyAliases WHERE sid = $1 AND CompanyAlias = $2".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(());
+        }
+        let transaction = self.postgres_client.transaction().await?;
+        transaction.execute(&query, &[sid, &alias]).await?;
+        transaction.execute(aliases::PROMOTE_PRIMARY_ALIAS, &[sid]).await?;
+        transaction.commit().await?;
+        Ok(())
     }
 
-    /// Deletes all companies with aliases that DON'T contain any of the strings in the filter
-    pub async fn filter_companies_alias(&mut self, filter: Vec<&str>) -> Result<(), Error> {
-        let query = "SELECT * FROM CompanyAliases WHERE {}".to_string();
-        // create regex pattern that matches on any of the strings using ors
-        let filters = filter.iter().map(|x| format!("CompanyAlias NOT ILIKE '%{}%'", x)).collect::<Vec<String>>().join(" AND ");
-        let query = query.replace("{}", &filters);
-        println!("{}", query);
-        let sids = self.postgres_client.query(&query, &[]).await?;
-        for row in sids {
-            let company_name: String = row.get(0);
-            let sid: i32 = row.get(1);
-            println!("Deleting company {} with sid {}", company_name, sid);
-            // self.delete_company(&sid, false)?;
+    pub async fn remove_website(&mut self, sid: &i32, url: &str, dry_run: bool) -> Result<(), Error> {
+        let query = "DELETE FROM CompanyWebsites WHERE sid = $1 AND website_link = $2".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(());
         }
+        self.postgres_client.execute(&query, &[sid, &canonicalize_url(url)]).await?;
         Ok(())
     }
 
+    /// Deletes all companies with aliases that DON'T contain any of the strings in the filter.
+    /// The deleted companies are archived first; see prune_companies.
+    pub async fn filter_companies_alias(&mut self, filter: Vec<&str>, dry_run: bool) -> Result<PruneReport, Error> {
+        let query = CompanyQuery::new().alias_contains_none(filter);
+        self.prune_companies(&query, "alias filter", dry_run).await
+    }
+
+    /// Returns the sids of all companies matching the query
+    pub async fn query_sids(&self, query: &CompanyQuery) -> Result<Vec<i32>, Error> {
+        let (sql, params) = query.to_sql();
+        let results = self.postgres_client.query(&sql, &as_sql_params(&params)).await?;
+        Ok(results.iter().map(|row| row.get(0)).collect())
+    }
+
+    /// Returns all companies matching the query
+    pub async fn query_companies(&self, query: &CompanyQuery) -> Result<Vec<ProcessedCompany>, Error> {
+        let mut companies = Vec::new();
+        for sid in self.query_sids(query).await? {
+            companies.push(self.construct_processed_company_from_sid(&sid).await?);
+        }
+        Ok(companies)
+    }
+
+    /// Returns the number of companies matching the query
+    pub async fn count_companies(&self, query: &CompanyQuery) -> Result<i64, Error> {
+        let (sql, params) = query.to_sql();
+        let sql = format!("SELECT COUNT(*) FROM ({}) matched", sql);
+        let results = self.postgres_client.query(&sql, &as_sql_params(&params)).await?;
+        Ok(results[0].get(0))
+    }
+
     pub async fn construct_processed_company_from_sid(&self, sid: &i32) -> Result<ProcessedCompany, Error> {
         let cik = self.get_cik_from_sid(sid).await?;
         let aliases = self.get_aliases_from_sid(sid).await?;
         let tags = self.get_tags_from_sid(sid).await?;
         let websites = self.get_websites_from_sid(sid).await?;
-        let career_page = self.get_career_page_from_sid(sid).await?;
-        let has_captcha = self.get_captcha_status_from_sid(sid).await?;
-        Ok(ProcessedCompany::new(cik, aliases, websites, career_page, tags, has_captcha))
+        let career_pages = self.get_career_pages_from_sid(sid).await?;
+        let mut company = ProcessedCompany::new(cik, aliases, websites, career_pages, tags);
+        company.identifiers = self.get_identifiers_from_sid(sid).await?
+            .into_iter().filter(|identifier| identifier.kind != IdentifierKind::Cik).collect();
+        company.bot_protection = self.get_bot_protection_from_sid(sid).await?;
+        company.alias_provenance = self.get_alias_provenance_from_sid(sid).await?;
+        company.alias_metadata = self.get_alias_metadata_from_sid(sid).await?;
+        company.primary_name = self.get_primary_name_from_sid(sid).await?;
+        company.website_provenance = self.get_website_provenance_from_sid(sid).await?;
+        company.career_page_provenance = self.get_career_page_provenance_from_sid(sid).await?;
+        company.relationships = self.get_relationships_from_sid(sid).await?;
+        Ok(company)
     }
 
     pub async fn get_cik_from_sid(&self, sid: &i32) -> Result<Option<i32>, Error> {
@@ -365,7 +788,7 @@ impl CompanyDataStore {
     }
 
     /// Checks if a company with the given CIK exists
-    /// Returns the sid of the company if it exists
+    /// Returns the sid of the company if it exists, even if it has been soft deleted
     pub async fn cik_exists(&mut self, cik: &i32) -> Result<Option<i32>, Error> {
         let query = "SELECT * FROM CikToSid WHERE cik = $1 LIMIT 1".to_string();
         let results = self.postgres_client.query(&query, &[&cik]).await?;
@@ -376,12 +799,29 @@ impl CompanyDataStore {
         Ok(sid)
     }
 
-    pub async fn get_websites_from_sid(&self, sid: &i32) -> Result<Option<Vec<(String, String)>>, Error> {
-        let query = "SELECT website_title, website_link FROM CompanyWebsites WHERE sid = $1".to_string();
+    /// Websites come back in search rank order, with websites that weren't found by searching last
+    pub async fn get_websites_from_sid(&self, sid: &i32) -> Result<Option<Vec<Website>>, Error> {
+        let query = "SELECT website_title, website_link, role, search_rank, snippet, source_engine, discovered_at, http_status, \
+                last_verified_at, last_changed_at \
+            FROM CompanyWebsites WHERE sid = $1 ORDER BY search_rank NULLS LAST, website_link".to_string();
         let results = self.postgres_client.query(&query, &[&sid]).await?;
         let mut websites = Vec::new();
         for row in results {
-            websites.push((row.get(0), row.get(1)));
+            let role: Option<String> = row.get(2);
+            let search_rank: Option<i32> = row.get(3);
+            let http_status: Option<i32> = row.get(7);
+            websites.push(Website {
+                title: row.get(0),
+                url: row.get(1),
+                role: role.and_then(|role| WebsiteRole::from_str(&role).ok()).unwrap_or_default(),
+                search_rank: search_rank.map(|rank| rank as u32),
+                snippet: row.get(4),
+                source_engine: row.get(5),
+                discovered_at: row.get(6),
+                http_status: http_status.map(|status| status as u16),
+                last_verified_at: row.get(8),
+                last_changed_at: row.get(9),
+            });
         }
         if websites.len() == 0 {
             return Ok(None);
@@ -389,26 +829,10 @@ impl CompanyDataStore {
         Ok(Some(websites))
     }
 
-    pub async fn get_career_page_from_sid(&self, sid: &i32) -> Result<Option<String>, Error> {
-        let query = "SELECT career_page_link FROM CompanyCareerPage WHERE sid = $1".to_string();
-        let results = self.postgres_client.query(&query, &[&sid]).await?;
-        if results.len() == 0 {
-            return Ok(None);
-        }
-        Ok(Some(results[0].get(0)))
-    }
-
-    pub async fn get_captcha_status_from_sid(&self, sid: &i32) -> Result<Option<bool>, Error> {
-        let query = "SELECT has_captcha FROM CompanyWebsites WHERE sid = $1".to_string();
-        let results = self.postgres_client.query(&query, &[&sid]).await?;
-        if results.len() == 0 {
-            return Ok(None);
-        }
-        Ok(Some(results[0].get(0)))
-    }
 
     pub async fn get_sid_from_cik(&mut self, cik: &i32) -> Result<Option<i32>, Error> {
-        let query = "SELECT sid FROM CikToSid WHERE cik = $1".to_string();
+        let query = "SELECT k.sid FROM CikToSid k JOIN CompanyTable c ON c.sid = k.sid \
+            WHERE k.cik = $1 AND c.deleted_at IS NULL".to_string();
         let results = self.postgres_client.query(&query, &[&cik]).await?;
         if results.len() == 0 {
             return Ok(None);
@@ -416,8 +840,10 @@ impl CompanyDataStore {
         Ok(Some(results[0].get(0)))
     }
 
+    /// Only matches the exact alias; see search_companies for fuzzy matching
     pub async fn get_sid_from_alias(&mut self, alias: &str) -> Result<Option<i32>, Error> {
-        let query = "SELECT sid FROM CompanyAliases WHERE CompanyAlias = $1".to_string();
+        let query = "SELECT a.sid FROM CompanyAliases a JOIN CompanyTable c ON c.sid = a.sid \
+            WHERE a.CompanyAlias = $1 AND c.deleted_at IS NULL".to_string();
         let results = self.postgres_client.query(&query, &[&alias]).await?;
         if results.len() == 0 {
             return Ok(None);
@@ -447,36 +873,24 @@ impl CompanyDataStore {
         }
     }
 
+    /// The next company due for discovery, without claiming it; workers should use claim_next_company
     pub async fn get_next_undiscovered_company(&self) -> Result<(i32, ProcessedCompany), Error> {
-        let query = "SELECT sid FROM CompanyTable WHERE sid NOT IN (SELECT sid FROM CompanyWebsites) LIMIT 1".to_string();
-        let results = self.postgres_client.query(&query, &[]).await?;
-        if results.len() == 0 {
+        let query = format!("{} LIMIT 1", due_for_discovery("$1"));
+        let results = self.postgres_client.query(&query, &[&MAX_DISCOVERY_ATTEMPTS]).await?;
+        if results.is_empty() {
             bail!("No undiscovered companies found");
         }
         let sid: i32 = results[0].get(0);
         println!("Found company with sid {}", sid);
-        let result = self.construct_processed_company_from_sid(&sid).await;
-        match result {
-            Ok(v) => {
-                Ok((sid, v))
-            },
-            Err(e) => {
-                println!("Error: {:?}", e);
-                bail!("Error constructing processed company from sid");
-            }
-        }
+        Ok((sid, self.construct_processed_company_from_sid(&sid).await?))
     }
 
-    /// This deletes all sids from companytable that do not have any entries
-    /// in either ciktosid or companyaliases
+    /// Soft deletes every company that has no aliases, so it can still be restored once it has some
     pub async fn clean_sids(&mut self) -> Result<(), Error> {
-        let query = "DELETE FROM CompanyTable WHERE sid NOT IN (SELECT sid FROM CompanyAliases)".to_string();
-        let res = self.postgres_client.execute(&query, &[]).await?;
-        println!("Deleted {} rows from CompanyTable", res);
+        let query = "UPDATE CompanyTable SET deleted_at = now(), deleted_reason = $1 \
+            WHERE deleted_at IS NULL AND sid NOT IN (SELECT sid FROM CompanyAliases)".to_string();
+        let res = self.postgres_client.execute(&query, &[&NO_ALIASES_REASON]).await?;
+        println!("Soft deleted {} companies without aliases", res);
         Ok(())
     }
-
-    pub fn print_stats(&self) {
-
-    }
 }
\ No newline at end of file
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use anyhow::{bail, Error};
+use chrono::{DateTime, Utc};
+use company_common::{canonicalize_url, EmploymentType, JobPosting};
+use tokio_postgres::Row;
+use crate::CompanyDataStore;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PostingStatus {
+    /// Seen the last time the source was scraped
+    Open,
+    /// Missing the last time the source was scraped; reopened if it shows up again
+    Closed,
+}
+
+impl PostingStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            PostingStatus::Open => "open",
+            PostingStatus::Closed => "closed",
+        }
+    }
+}
+
+impl fmt::Display for PostingStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for PostingStatus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(PostingStatus::Open),
+            "closed" => Ok(PostingStatus::Closed),
+            _ => bail!("Unknown posting status: {}", s),
+        }
+    }
+}
+
+/// A job posting's row in JobPostings
+#[derive(Debug, Clone)]
+pub struct PostingRecord {
+    pub posting_id: i64,
+    pub sid: i32,
+    /// Where the posting was scraped from, e.g. "greenhouse"
+    pub source: String,
+    pub posting: JobPosting,
+    pub first_seen_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub status: PostingStatus,
+    pub closed_at: Option<DateTime<Utc>>,
+}
+
+/// What an upsert_postings call changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PostingUpsertReport {
+    /// Postings seen for the first time
+    pub added: u64,
+    /// Open postings that were seen again
+    pub updated: u64,
+    /// Closed postings that showed up again
+    pub reopened: u64,
+    /// Open postings that weren't seen this time
+    pub closed: u64,
+}
+
+impl fmt::Display for PostingUpsertReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} added, {} updated, {} reopened, {} closed", self.added, self.updated, self.reopened, self.closed)
+    }
+}
+
+const POSTING_COLUMNS: &str = "posting_id, sid, source, external_id, url, title, raw_description, description, location, \
+    department, employment_type, posted_date, first_seen_at, last_seen_at, status, closed_at";
+
+fn posting_from_row(row: &Row) -> Result<PostingRecord, Error> {
+    let employment_type: Option<String> = row.get(10);
+    let status: String = row.get(14);
+    Ok(PostingRecord {
+        posting_id: row.get(0),
+        sid: row.get(1),
+        source: row.get(2),
+        posting: JobPosting {
+            external_id: row.get(3),
+            url: row.get::<_, Option<String>>(4).unwrap_or_default(),
+            title: row.get::<_, Option<String>>(5).unwrap_or_default(),
+            raw_description: row.get(6),
+            description: row.get(7),
+            location: row.get(8),
+            department: row.get(9),
+            employment_type: employment_type.and_then(|employment_type| EmploymentType::from_str(&employment_type).ok()),
+            posted_date: row.get(11),
+        },
+        first_seen_at: row.get(12),
+        last_seen_at: row.get(13),
+        status: PostingStatus::from_str(&status)?,
+        closed_at: row.get(15),
+    })
+}
+
+impl CompanyDataStore {
+    /// Stores everything scraped from one of a company's sources in a single pass.
+    /// Postings are matched on their external id: new ones are added, known ones are updated and marked
+    /// as seen now (reopening them if they were closed), and open postings from the source that
+    /// weren't passed in are closed. Calling this again with the same postings changes nothing but last_seen_at.
+    /// @param source: where the postings were scraped from, e.g. "greenhouse"; other sources' postings are untouched
+    pub async fn upsert_postings(&mut self, sid: &i32, source: &str, postings: Vec<JobPosting>) -> Result<PostingUpsertReport, Error> {
+        let mut report = PostingUpsertReport::default();
+        let transaction = self.postgres_client.transaction().await?;
+
+        let known: HashMap<String, String> = transaction.query(
+            "SELECT external_id, status FROM JobPostings WHERE sid = $1 AND source = $2 FOR UPDATE", &[sid, &source],
+        ).await?.iter().map(|row| (row.get(0), row.get(1))).collect();
+
+        let query = "INSERT INTO JobPostings (sid, source, external_id, url, title, raw_description, description, \
+                location, department, employment_type, posted_date) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) \
+            ON CONFLICT (sid, source, external_id) DO UPDATE SET \
+                url = EXCLUDED.url, title = EXCLUDED.title, raw_description = EXCLUDED.raw_description, \
+                description = EXCLUDED.description, location = EXCLUDED.location, department = EXCLUDED.department, \
+                employment_type = EXCLUDED.employment_type, posted_date = EXCLUDED.posted_date, \
+                last_seen_at = now(), status = 'open', closed_at = NULL";
+        let mut seen: Vec<String> = Vec::new();
+        for posting in &postings {
+            if seen.contains(&posting.external_id) {
+                continue;
+            }
+            let employment_type = posting.employment_type.map(|employment_type| employment_type.as_str().to_string());
+            // postings are kept even if their link is malformed
+            let url = canonicalize_url(&posting.url);
+            transaction.execute(query, &[sid, &source, &posting.external_id, &url, &posting.title,
+                &posting.raw_description, &posting.description, &posting.location, &posting.department,
+                &employment_type, &posting.posted_date]).await?;
+            match known.get(&posting.external_id).map(|status| status.as_str()) {
+                None => report.added += 1,
+                Some("closed") => report.reopened += 1,
+                Some(_) => report.updated += 1,
+            }
+            seen.push(posting.external_id.clone());
+        }
+
+        report.closed = transaction.execute(
+            "UPDATE JobPostings SET status = 'closed', closed_at = now() \
+             WHERE sid = $1 AND source = $2 AND status = 'open' AND NOT (external_id = ANY($3))",
+            &[sid, &source, &seen],
+        ).await?;
+        transaction.commit().await?;
+        println!("Upserted postings for sid {} from {}: {}", sid, source, report);
+        Ok(report)
+    }
+
+    /// The company's postings from every source, most recently seen first
+    /// @param include_closed: whether to also return postings that have closed
+    pub async fn get_postings_from_sid(&self, sid: &i32, include_closed: bool) -> Result<Vec<PostingRecord>, Error> {
+        let query = format!("SELECT {} FROM JobPostings WHERE sid = $1 AND ($2 OR status = 'open') \
+            ORDER BY last_seen_at DESC, posting_id", POSTING_COLUMNS);
+        let results = self.postgres_client.query(&query, &[sid, &include_closed]).await?;
+        results.iter().map(posting_from_row).collect()
+    }
+
+    pub async fn get_posting(&self, posting_id: &i64) -> Result<Option<PostingRecord>, Error> {
+        let query = format!("SELECT {} FROM JobPostings WHERE posting_id = $1", POSTING_COLUMNS);
+        let results = self.postgres_client.query(&query, &[posting_id]).await?;
+        match results.first() {
+            Some(row) => Ok(Some(posting_from_row(row)?)),
+            None => Ok(None),
+        }
+    }
+}
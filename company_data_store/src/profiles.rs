@@ -0,0 +1,177 @@
+use std::str::FromStr;
+use anyhow::{bail, Error};
+use chrono::{DateTime, Utc};
+use company_common::{CandidateProfile, RemotePreference};
+use serde::Serialize;
+use tokio_postgres::Row;
+use crate::{escape_like, CompanyDataStore, TagCount};
+
+/// A resume stored with a profile. Only where the resume is kept is stored, not the resume itself.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileResume {
+    pub resume_id: i32,
+    pub name: String,
+    /// e.g. a file path or URL
+    pub location: String,
+    pub added_at: DateTime<Utc>,
+}
+
+/// A company on a profile's shortlist
+#[derive(Debug, Clone, Serialize)]
+pub struct ShortlistEntry {
+    pub sid: i32,
+    pub note: Option<String>,
+    pub added_at: DateTime<Utc>,
+}
+
+const PROFILE_COLUMNS: &str = "profile_id, handle, name, email, phone, target_titles, locations, remote_preference, \
+    salary_floor, salary_currency, work_authorization, excluded_sids";
+
+fn profile_from_row(row: &Row) -> Result<(i32, CandidateProfile), Error> {
+    let remote_preference: String = row.get(7);
+    Ok((row.get(0), CandidateProfile {
+        handle: row.get(1),
+        name: row.get(2),
+        email: row.get(3),
+        phone: row.get(4),
+        target_titles: row.get(5),
+        locations: row.get(6),
+        remote_preference: RemotePreference::from_str(&remote_preference)?,
+        salary_floor: row.get(8),
+        salary_currency: row.get(9),
+        work_authorization: row.get(10),
+        excluded_companies: row.get(11),
+    }))
+}
+
+impl CompanyDataStore {
+    /// Adds a profile, returning its id. Fails if the handle is taken.
+    pub async fn create_profile(&mut self, profile: &CandidateProfile) -> Result<i32, Error> {
+        // validates the handle
+        CandidateProfile::new(&profile.handle)?;
+        let query = "INSERT INTO CandidateProfiles (handle, name, email, phone, target_titles, locations, remote_preference, \
+                salary_floor, salary_currency, work_authorization, excluded_sids) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) RETURNING profile_id".to_string();
+        let results = self.postgres_client.query(&query, &[&profile.handle, &profile.name, &profile.email, &profile.phone,
+            &profile.target_titles, &profile.locations, &profile.remote_preference.as_str(), &profile.salary_floor,
+            &profile.salary_currency, &profile.work_authorization, &profile.excluded_companies]).await?;
+        Ok(results[0].get(0))
+    }
+
+    /// Replaces everything about a profile but its handle, which would orphan its tags
+    pub async fn update_profile(&mut self, profile_id: &i32, profile: &CandidateProfile) -> Result<(), Error> {
+        let query = "UPDATE CandidateProfiles SET name = $2, email = $3, phone = $4, target_titles = $5, locations = $6, \
+                remote_preference = $7, salary_floor = $8, salary_currency = $9, work_authorization = $10, \
+                excluded_sids = $11, updated_at = now() \
+            WHERE profile_id = $1 AND handle = $12".to_string();
+        let updated = self.postgres_client.execute(&query, &[profile_id, &profile.name, &profile.email, &profile.phone,
+            &profile.target_titles, &profile.locations, &profile.remote_preference.as_str(), &profile.salary_floor,
+            &profile.salary_currency, &profile.work_authorization, &profile.excluded_companies, &profile.handle]).await?;
+        if updated == 0 {
+            bail!("No profile with id {} and handle {}", profile_id, profile.handle);
+        }
+        Ok(())
+    }
+
+    /// Deletes a profile along with its resumes, shortlist and tags
+    pub async fn delete_profile(&mut self, profile_id: &i32, dry_run: bool) -> Result<(), Error> {
+        let profile = match self.get_profile(profile_id).await? {
+            Some((_, profile)) => profile,
+            None => bail!("No profile with id {}", profile_id),
+        };
+        let tag_pattern = format!("{}:%", escape_like(&profile.tag_namespace().prefix()));
+        if dry_run {
+            println!("DELETE FROM CandidateProfiles WHERE profile_id = {}", profile_id);
+            println!("DELETE FROM CompanyTags WHERE tag LIKE '{}'", tag_pattern);
+            return Ok(());
+        }
+        let transaction = self.postgres_client.transaction().await?;
+        transaction.execute("DELETE FROM CompanyTags WHERE tag LIKE $1", &[&tag_pattern]).await?;
+        transaction.execute("DELETE FROM CandidateProfiles WHERE profile_id = $1", &[profile_id]).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    pub async fn get_profile(&self, profile_id: &i32) -> Result<Option<(i32, CandidateProfile)>, Error> {
+        let query = format!("SELECT {} FROM CandidateProfiles WHERE profile_id = $1", PROFILE_COLUMNS);
+        let results = self.postgres_client.query(&query, &[profile_id]).await?;
+        results.first().map(profile_from_row).transpose()
+    }
+
+    pub async fn get_profile_by_handle(&self, handle: &str) -> Result<Option<(i32, CandidateProfile)>, Error> {
+        let query = format!("SELECT {} FROM CandidateProfiles WHERE handle = $1", PROFILE_COLUMNS);
+        let results = self.postgres_client.query(&query, &[&handle]).await?;
+        results.first().map(profile_from_row).transpose()
+    }
+
+    pub async fn get_profiles(&self) -> Result<Vec<(i32, CandidateProfile)>, Error> {
+        let query = format!("SELECT {} FROM CandidateProfiles ORDER BY handle", PROFILE_COLUMNS);
+        let results = self.postgres_client.query(&query, &[]).await?;
+        results.iter().map(profile_from_row).collect()
+    }
+
+    pub async fn add_resume(&mut self, profile_id: &i32, name: &str, location: &str) -> Result<i32, Error> {
+        let results = self.postgres_client.query(
+            "INSERT INTO ProfileResumes (profile_id, name, location) VALUES ($1, $2, $3) RETURNING resume_id",
+            &[profile_id, &name, &location],
+        ).await?;
+        Ok(results[0].get(0))
+    }
+
+    pub async fn remove_resume(&mut self, resume_id: &i32) -> Result<(), Error> {
+        self.postgres_client.execute("DELETE FROM ProfileResumes WHERE resume_id = $1", &[resume_id]).await?;
+        Ok(())
+    }
+
+    /// The profile's resumes, most recently added first
+    pub async fn get_resumes(&self, profile_id: &i32) -> Result<Vec<ProfileResume>, Error> {
+        let query = "SELECT resume_id, name, location, added_at FROM ProfileResumes WHERE profile_id = $1 \
+            ORDER BY added_at DESC, resume_id DESC".to_string();
+        let results = self.postgres_client.query(&query, &[profile_id]).await?;
+        Ok(results.iter().map(|row| ProfileResume {
+            resume_id: row.get(0),
+            name: row.get(1),
+            location: row.get(2),
+            added_at: row.get(3),
+        }).collect())
+    }
+
+    /// Adds a company to the profile's shortlist, updating the note if it's already there.
+    /// Fails if the profile excludes the company.
+    pub async fn shortlist_company(&mut self, profile_id: &i32, sid: &i32, note: Option<&str>) -> Result<(), Error> {
+        let profile = match self.get_profile(profile_id).await? {
+            Some((_, profile)) => profile,
+            None => bail!("No profile with id {}", profile_id),
+        };
+        if profile.is_excluded(sid) {
+            bail!("Profile {} excludes company {}", profile.handle, sid);
+        }
+        self.postgres_client.execute(
+            "INSERT INTO ProfileShortlist (profile_id, sid, note) VALUES ($1, $2, $3) \
+             ON CONFLICT (profile_id, sid) DO UPDATE SET note = COALESCE(EXCLUDED.note, ProfileShortlist.note)",
+            &[profile_id, sid, &note],
+        ).await?;
+        Ok(())
+    }
+
+    pub async fn unshortlist_company(&mut self, profile_id: &i32, sid: &i32) -> Result<(), Error> {
+        self.postgres_client.execute("DELETE FROM ProfileShortlist WHERE profile_id = $1 AND sid = $2", &[profile_id, sid]).await?;
+        Ok(())
+    }
+
+    /// The companies on the profile's shortlist that haven't been soft deleted, oldest first
+    pub async fn get_shortlist(&self, profile_id: &i32) -> Result<Vec<ShortlistEntry>, Error> {
+        let query = "SELECT s.sid, s.note, s.added_at FROM ProfileShortlist s JOIN CompanyTable c ON c.sid = s.sid \
+            WHERE s.profile_id = $1 AND c.deleted_at IS NULL ORDER BY s.added_at, s.sid".to_string();
+        let results = self.postgres_client.query(&query, &[profile_id]).await?;
+        Ok(results.iter().map(|row| ShortlistEntry { sid: row.get(0), note: row.get(1), added_at: row.get(2) }).collect())
+    }
+
+    /// How many companies have each of the profile's own tags
+    pub async fn get_profile_tag_counts(&self, profile_id: &i32) -> Result<Vec<TagCount>, Error> {
+        match self.get_profile(profile_id).await? {
+            Some((_, profile)) => self.get_tag_counts(Some(&profile.tag_namespace())).await,
+            None => bail!("No profile with id {}", profile_id),
+        }
+    }
+}
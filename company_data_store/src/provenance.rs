@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use anyhow::Error;
+use chrono::{DateTime, Utc};
+use company_common::{FactSource, Provenance};
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{GenericClient, Row};
+use crate::{aliases, career_pages, CompanyDataStore, CompanyTables, NO_ALIASES_REASON};
+
+/// The tables whose rows carry provenance columns
+const FACT_TABLES: [CompanyTables; 3] = [
+    CompanyTables::CompanyAliases,
+    CompanyTables::CompanyWebsites,
+    CompanyTables::CompanyCareerPage,
+];
+
+/// How many facts a revert removed, or would remove when run as a dry run
+#[derive(Debug, Clone, Default)]
+pub struct RevertReport {
+    pub dry_run: bool,
+    pub aliases: u64,
+    pub websites: u64,
+    pub career_pages: u64,
+    /// Companies soft deleted because they were left without any aliases
+    pub companies: u64,
+}
+
+/// Reads the source, producer, confidence and recorded_at columns, starting at the given column
+fn provenance_from_row(row: &Row, first_column: usize) -> Provenance {
+    let source: Option<String> = row.get(first_column);
+    let producer: Option<String> = row.get(first_column + 1);
+    let confidence: Option<f32> = row.get(first_column + 2);
+    let recorded_at: Option<DateTime<Utc>> = row.get(first_column + 3);
+    Provenance {
+        source: source.and_then(|s| FactSource::from_str(&s).ok()).unwrap_or(FactSource::Unknown),
+        producer: producer.unwrap_or_else(|| "unknown".to_string()),
+        confidence: confidence.unwrap_or(0.0),
+        recorded_at,
+    }
+}
+
+/// Inserts a row into one of the fact tables, along with where the fact came from.
+/// If the provenance has no recorded_at, the fact is recorded as of now.
+/// @param columns: the columns the values are for, not including the provenance columns
+pub(crate) async fn insert_with_provenance<C: GenericClient>(client: &C, table: CompanyTables, columns: &[&str],
+                                                             mut values: Vec<&(dyn ToSql + Sync)>,
+                                                             provenance: &Provenance) -> Result<(), Error> {
+    let source = provenance.source.as_str();
+    let mut columns = columns.to_vec();
+    columns.extend(["source", "producer", "confidence"]);
+    values.push(&source);
+    values.push(&provenance.producer);
+    values.push(&provenance.confidence);
+    if let Some(recorded_at) = &provenance.recorded_at {
+        columns.push("recorded_at");
+        values.push(recorded_at);
+    }
+    let query = format!("INSERT INTO {} ({}) VALUES ({})",
+                        table.as_str(),
+                        columns.join(", "),
+                        (1..=values.len()).map(|i| format!("${}", i)).collect::<Vec<String>>().join(", "));
+    client.execute(&query, &values).await?;
+    Ok(())
+}
+
+impl CompanyDataStore {
+    pub async fn get_alias_provenance_from_sid(&self, sid: &i32) -> Result<HashMap<String, Provenance>, Error> {
+        let query = "SELECT CompanyAlias, source, producer, confidence, recorded_at FROM CompanyAliases WHERE sid = $1".to_string();
+        let results = self.postgres_client.query(&query, &[&sid]).await?;
+        Ok(results.iter().map(|row| (row.get(0), provenance_from_row(row, 1))).collect())
+    }
+
+    pub async fn get_website_provenance_from_sid(&self, sid: &i32) -> Result<HashMap<String, Provenance>, Error> {
+        let query = "SELECT website_link, source, producer, confidence, recorded_at FROM CompanyWebsites WHERE sid = $1".to_string();
+        let results = self.postgres_client.query(&query, &[&sid]).await?;
+        Ok(results.iter().map(|row| (row.get(0), provenance_from_row(row, 1))).collect())
+    }
+
+    pub async fn get_career_page_provenance_from_sid(&self, sid: &i32) -> Result<HashMap<String, Provenance>, Error> {
+        let query = "SELECT career_page_link, source, producer, confidence, recorded_at FROM CompanyCareerPage WHERE sid = $1".to_string();
+        let results = self.postgres_client.query(&query, &[&sid]).await?;
+        Ok(results.iter().map(|row| (row.get(0), provenance_from_row(row, 1))).collect())
+    }
+
+    /// Deletes every alias, website and career page that came from the given source,
+    /// optionally only those made by a specific producer (e.g. a buggy version of a component).
+    /// Facts recorded before provenance was tracked count as FactSource::Unknown.
+    /// Companies that lose their primary name or career page get another one, and companies left without
+    /// any aliases are soft deleted.
+    /// @param dry_run: if true, only counts what would be deleted
+    pub async fn revert_facts_from_source(&mut self, source: FactSource, producer: Option<&str>, dry_run: bool) -> Result<RevertReport, Error> {
+        let condition = "COALESCE(source, 'unknown') = $1 AND ($2::TEXT IS NULL OR producer = $2)";
+        let source = source.as_str();
+        let mut deleted: Vec<Vec<i32>> = Vec::new();
+        // a dry run makes the same changes, and rolls them back
+        let transaction = self.postgres_client.transaction().await?;
+        for table in FACT_TABLES.iter() {
+            let query = format!("DELETE FROM {} WHERE {} RETURNING sid", table.as_str(), condition);
+            let results = transaction.query(&query, &[&source, &producer]).await?;
+            deleted.push(results.iter().map(|row| row.get(0)).collect());
+        }
+
+        let mut alias_sids = deleted[0].clone();
+        alias_sids.sort();
+        alias_sids.dedup();
+        for sid in &alias_sids {
+            transaction.execute(aliases::PROMOTE_PRIMARY_ALIAS, &[sid]).await?;
+        }
+        let mut career_page_sids = deleted[2].clone();
+        career_page_sids.sort();
+        career_page_sids.dedup();
+        for sid in &career_page_sids {
+            transaction.execute(career_pages::PROMOTE_PRIMARY_CAREER_PAGE, &[sid]).await?;
+        }
+        let companies = transaction.execute(
+            "UPDATE CompanyTable c SET deleted_at = now(), deleted_reason = $2 \
+            WHERE c.sid = ANY($1) AND c.deleted_at IS NULL AND NOT EXISTS (SELECT 1 FROM CompanyAliases a WHERE a.sid = c.sid)",
+            &[&alias_sids, &NO_ALIASES_REASON],
+        ).await?;
+
+        if dry_run {
+            transaction.rollback().await?;
+        } else {
+            transaction.commit().await?;
+        }
+        Ok(RevertReport {
+            dry_run,
+            aliases: deleted[0].len() as u64,
+            websites: deleted[1].len() as u64,
+            career_pages: deleted[2].len() as u64,
+            companies,
+        })
+    }
+}
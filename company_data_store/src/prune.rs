@@ -0,0 +1,166 @@
+use std::fmt;
+use anyhow::{bail, Error};
+use company_common::ProcessedCompany;
+use tokio_postgres::GenericClient;
+use crate::{as_sql_params, CompanyDataStore, CompanyQuery};
+
+/// How many matched companies a prune preview shows
+pub const PRUNE_SAMPLE_SIZE: usize = 10;
+/// How many times prune_companies reads the companies again when they change while being archived
+const PRUNE_ATTEMPTS: usize = 3;
+
+/// What a prune removed, or would remove when run as a dry run
+#[derive(Debug, Clone, Default)]
+pub struct PruneReport {
+    pub dry_run: bool,
+    pub companies: i64,
+    pub aliases: i64,
+    pub tags: i64,
+    pub websites: i64,
+    pub career_pages: i64,
+    /// Archived along with the companies
+    pub postings: i64,
+    /// Applications to the companies' postings; companies that have any aren't pruned
+    pub applications: i64,
+    /// Profiles that shortlisted the companies; companies on a shortlist aren't pruned
+    pub shortlisted: i64,
+    /// A few of the matched companies, by sid
+    pub sample: Vec<(i32, ProcessedCompany)>,
+}
+
+impl PruneReport {
+    /// Whether pruning would destroy candidates' own data, which isn't archived
+    pub fn has_user_data(&self) -> bool {
+        self.applications > 0 || self.shortlisted > 0
+    }
+}
+
+impl fmt::Display for PruneReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = if self.dry_run { "Would prune" } else { "Pruned" };
+        writeln!(f, "{} {} companies ({} aliases, {} tags, {} websites, {} career pages, {} job postings)",
+                 verb, self.companies, self.aliases, self.tags, self.websites, self.career_pages, self.postings)?;
+        if self.has_user_data() {
+            writeln!(f, "  refused: {} applications and {} shortlist entries would be lost", self.applications, self.shortlisted)?;
+        }
+        for (sid, company) in &self.sample {
+            let name = company.get_company_name().unwrap_or_else(|_| "<no name>".to_string());
+            writeln!(f, "  sid {}: {}", sid, name)?;
+        }
+        if (self.sample.len() as i64) < self.companies {
+            writeln!(f, "  ... and {} more", self.companies - self.sample.len() as i64)?;
+        }
+        Ok(())
+    }
+}
+
+/// table is always one of our own table names, never user input
+async fn count_rows_for_sids<C: GenericClient>(client: &C, table: &str, sids: &Vec<i32>) -> Result<i64, Error> {
+    let query = format!("SELECT COUNT(*) FROM {} WHERE sid = ANY($1)", table);
+    let results = client.query(&query, &[sids]).await?;
+    Ok(results[0].get(0))
+}
+
+/// Counts the companies' postings, and the applications and shortlist entries that refer to them.
+/// Applications are job_applier's table, so there are none if it hasn't created it.
+async fn count_dependents<C: GenericClient>(client: &C, report: &mut PruneReport, sids: &Vec<i32>) -> Result<(), Error> {
+    report.postings = count_rows_for_sids(client, "JobPostings", sids).await?;
+    report.shortlisted = count_rows_for_sids(client, "ProfileShortlist", sids).await?;
+    let results = client.query("SELECT to_regclass('applications') IS NOT NULL", &[]).await?;
+    report.applications = if results[0].get(0) {
+        let results = client.query(
+            "SELECT COUNT(*) FROM Applications a JOIN JobPostings p ON p.posting_id = a.posting_id WHERE p.sid = ANY($1)",
+            &[sids],
+        ).await?;
+        results[0].get(0)
+    } else {
+        0
+    };
+    Ok(())
+}
+
+impl CompanyDataStore {
+    /// Reports what pruning the companies matching the query would remove, without removing anything
+    pub async fn prune_preview(&self, query: &CompanyQuery) -> Result<PruneReport, Error> {
+        let sids = self.query_sids(query).await?;
+        self.prune_report(&sids, true).await
+    }
+
+    /// Deletes every company matching the query, along with its aliases, tags, websites, postings etc.
+    /// Each company graph is copied to the CompanyArchive table (as JSON), with its job postings, before it is deleted.
+    /// Companies with applications or on a profile's shortlist aren't archived, so the prune fails if any match.
+    /// The companies are locked, archived and deleted in a single transaction. Their graphs are read beforehand,
+    /// and read again if any of them changed in between, so the archive always holds what was deleted.
+    /// @param reason: recorded alongside the archived companies
+    /// @param dry_run: if true, only reports what would be pruned
+    pub async fn prune_companies(&mut self, query: &CompanyQuery, reason: &str, dry_run: bool) -> Result<PruneReport, Error> {
+        if dry_run {
+            return self.prune_preview(query).await;
+        }
+        for _ in 0..PRUNE_ATTEMPTS {
+            let sids = self.query_sids(query).await?;
+            let mut report = self.prune_report(&sids, false).await?;
+            if sids.is_empty() {
+                return Ok(report);
+            }
+            // the version is read first, so a change made while the graph is read shows up as a new version
+            let mut archives = Vec::new();
+            for sid in &sids {
+                let version = self.get_company_version(sid).await?;
+                let company = serde_json::to_value(self.construct_processed_company_from_sid(sid).await?)?;
+                archives.push((*sid, version, company));
+            }
+
+            let transaction = self.postgres_client.transaction().await?;
+            let (sql, params) = query.to_sql();
+            // changes to a company's aliases, websites etc. also update its row (to bump its version), so they wait too
+            let sql = format!("SELECT sid, version FROM CompanyTable WHERE sid IN ({} FOR UPDATE OF c) ORDER BY sid", sql);
+            let locked: Vec<(i32, i64)> = transaction.query(&sql, &as_sql_params(&params)).await?
+                .iter().map(|row| (row.get(0), row.get(1))).collect();
+            let mut read: Vec<(i32, i64)> = archives.iter().map(|(sid, version, _)| (*sid, *version)).collect();
+            read.sort();
+            if locked != read {
+                println!("Companies changed while being pruned, reading them again");
+                continue;
+            }
+            // new applications on the postings would wait for the lock, and be deleted with them
+            transaction.execute("SELECT 1 FROM JobPostings WHERE sid = ANY($1) FOR UPDATE", &[&sids]).await?;
+            count_dependents(&transaction, &mut report, &sids).await?;
+            if report.has_user_data() {
+                bail!("Not pruning {} companies, {} applications and {} shortlist entries would be lost",
+                    report.companies, report.applications, report.shortlisted);
+            }
+
+            for (sid, _, company) in &archives {
+                transaction.execute(
+                    "INSERT INTO CompanyArchive (sid, reason, company, postings) VALUES ($1, $2, $3, \
+                        (SELECT COALESCE(jsonb_agg(to_jsonb(p) ORDER BY p.posting_id), '[]') FROM JobPostings p WHERE p.sid = $1))",
+                    &[sid, &reason, company],
+                ).await?;
+            }
+            let deleted = transaction.execute("DELETE FROM CompanyTable WHERE sid = ANY($1)", &[&sids]).await?;
+            transaction.commit().await?;
+            report.companies = deleted as i64;
+            println!("{}", report);
+            return Ok(report);
+        }
+        bail!("Companies kept changing while being pruned, try again later")
+    }
+
+    async fn prune_report(&self, sids: &Vec<i32>, dry_run: bool) -> Result<PruneReport, Error> {
+        let mut report = PruneReport {
+            dry_run,
+            companies: sids.len() as i64,
+            ..Default::default()
+        };
+        report.aliases = count_rows_for_sids(&self.postgres_client, "CompanyAliases", sids).await?;
+        report.tags = count_rows_for_sids(&self.postgres_client, "CompanyTags", sids).await?;
+        report.websites = count_rows_for_sids(&self.postgres_client, "CompanyWebsites", sids).await?;
+        report.career_pages = count_rows_for_sids(&self.postgres_client, "CompanyCareerPage", sids).await?;
+        count_dependents(&self.postgres_client, &mut report, sids).await?;
+        for sid in sids.iter().take(PRUNE_SAMPLE_SIZE) {
+            report.sample.push((*sid, self.construct_processed_company_from_sid(sid).await?));
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,312 @@
+use tokio_postgres::types::ToSql;
+use company_common::{BotProtectionKind, TagNamespace};
+use crate::{hierarchy_cte, DiscoveryStatus};
+
+/// What to order the results of a CompanyQuery by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompanySort {
+    Sid,
+    /// Each company's primary name
+    Alias,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+/// Parameters collected while compiling a CompanyQuery, in placeholder order ($1, $2, ...)
+pub type QueryParams = Vec<Box<dyn ToSql + Sync + Send>>;
+
+/// A filter over the companies in the data store, compiled to parameterized SQL.
+/// Every filter is optional; an empty query matches every company that hasn't been soft deleted.
+///
+/// ```ignore
+/// let query = CompanyQuery::new()
+///     .alias_contains_any(vec!["inc", "corp"])
+///     .has_website(false)
+///     .sort_by(CompanySort::Sid, SortDirection::Ascending)
+///     .limit(10);
+/// let companies = data_store.query_companies(&query).await?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CompanyQuery {
+    alias_patterns: Vec<String>,
+    excluded_alias_patterns: Vec<String>,
+    tags: Vec<String>,
+    tag_patterns: Vec<String>,
+    has_cik: Option<bool>,
+    has_website: Option<bool>,
+    has_career_page: Option<bool>,
+    inherit_career_pages: bool,
+    has_bot_protection: Option<bool>,
+    bot_protection_kinds: Vec<BotProtectionKind>,
+    discovery_statuses: Vec<DiscoveryStatus>,
+    shortlisted_by: Option<i32>,
+    not_excluded_by: Option<i32>,
+    include_deleted: bool,
+    sort: Option<(CompanySort, SortDirection)>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+impl CompanyQuery {
+    pub fn new() -> CompanyQuery {
+        CompanyQuery::default()
+    }
+
+    /// Matches companies with an alias containing the given text, ignoring case.
+    /// Calling this more than once matches companies with an alias containing any of them.
+    pub fn alias_contains(mut self, text: &str) -> Self {
+        self.alias_patterns.push(format!("%{}%", escape_like(text)));
+        self
+    }
+
+    pub fn alias_contains_any(mut self, texts: Vec<&str>) -> Self {
+        for text in texts {
+            self = self.alias_contains(text);
+        }
+        self
+    }
+
+    /// Matches companies with an alias matching the given ILIKE pattern (% and _ are wildcards)
+    pub fn alias_like(mut self, pattern: &str) -> Self {
+        self.alias_patterns.push(pattern.to_string());
+        self
+    }
+
+    /// Matches companies where none of the aliases contain any of the given strings, ignoring case
+    pub fn alias_contains_none(mut self, texts: Vec<&str>) -> Self {
+        for text in texts {
+            self.excluded_alias_patterns.push(format!("%{}%", escape_like(text)));
+        }
+        self
+    }
+
+    /// Matches companies that have the given tag. Calling this more than once requires all of the tags.
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// Matches companies with any tag in the given namespace.
+    /// Calling this more than once requires a tag in each of the namespaces.
+    pub fn with_tag_in_namespace(mut self, namespace: &TagNamespace) -> Self {
+        self.tag_patterns.push(format!("{}:%", escape_like(&namespace.prefix())));
+        self
+    }
+
+    pub fn has_cik(mut self, has_cik: bool) -> Self {
+        self.has_cik = Some(has_cik);
+        self
+    }
+
+    pub fn has_website(mut self, has_website: bool) -> Self {
+        self.has_website = Some(has_website);
+        self
+    }
+
+    pub fn has_career_page(mut self, has_career_page: bool) -> Self {
+        self.has_career_page = Some(has_career_page);
+        self
+    }
+
+    /// Makes has_career_page also count the career pages of the companies above each company in the
+    /// hierarchy today, for subsidiaries and brands whose careers live on a parent's site
+    pub fn inherit_career_pages(mut self, inherit_career_pages: bool) -> Self {
+        self.inherit_career_pages = inherit_career_pages;
+        self
+    }
+
+    /// Matches companies with (or without) any bot protection on any of their URLs
+    pub fn has_bot_protection(mut self, has_bot_protection: bool) -> Self {
+        self.has_bot_protection = Some(has_bot_protection);
+        self
+    }
+
+    /// Matches companies with the given kind of bot protection on any of their URLs.
+    /// Calling this more than once matches companies with any of the kinds.
+    pub fn bot_protection_kind(mut self, kind: BotProtectionKind) -> Self {
+        self.bot_protection_kinds.push(kind);
+        self
+    }
+
+    /// Matches companies with the given discovery status.
+    /// Calling this more than once matches companies with any of the statuses.
+    pub fn discovery_status(mut self, status: DiscoveryStatus) -> Self {
+        self.discovery_statuses.push(status);
+        self
+    }
+
+    /// Matches companies on the given profile's shortlist
+    pub fn shortlisted_by(mut self, profile_id: i32) -> Self {
+        self.shortlisted_by = Some(profile_id);
+        self
+    }
+
+    /// Leaves out the companies the given profile excludes
+    pub fn not_excluded_by(mut self, profile_id: i32) -> Self {
+        self.not_excluded_by = Some(profile_id);
+        self
+    }
+
+    /// Also match companies that have been soft deleted
+    pub fn include_deleted(mut self, include_deleted: bool) -> Self {
+        self.include_deleted = include_deleted;
+        self
+    }
+
+    pub fn sort_by(mut self, sort: CompanySort, direction: SortDirection) -> Self {
+        self.sort = Some((sort, direction));
+        self
+    }
+
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: i64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Compiles the query into SQL selecting the matching sids, along with its parameters.
+    /// User supplied values only ever appear in the parameters, never in the SQL string.
+    pub fn to_sql(&self) -> (String, QueryParams) {
+        let mut params: QueryParams = Vec::new();
+        let mut conditions: Vec<String> = Vec::new();
+
+        if !self.include_deleted {
+            conditions.push("c.deleted_at IS NULL".to_string());
+        }
+
+        if !self.alias_patterns.is_empty() {
+            params.push(Box::new(self.alias_patterns.clone()));
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM CompanyAliases a WHERE a.sid = c.sid AND a.CompanyAlias ILIKE ANY(${}))",
+                params.len()
+            ));
+        }
+        if !self.excluded_alias_patterns.is_empty() {
+            params.push(Box::new(self.excluded_alias_patterns.clone()));
+            conditions.push(format!(
+                "NOT EXISTS (SELECT 1 FROM CompanyAliases a WHERE a.sid = c.sid AND a.CompanyAlias ILIKE ANY(${}))",
+                params.len()
+            ));
+        }
+        for tag in &self.tags {
+            params.push(Box::new(tag.clone()));
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM CompanyTags t WHERE t.sid = c.sid AND t.tag = ${})",
+                params.len()
+            ));
+        }
+        for pattern in &self.tag_patterns {
+            params.push(Box::new(pattern.clone()));
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM CompanyTags t WHERE t.sid = c.sid AND t.tag LIKE ${})",
+                params.len()
+            ));
+        }
+        if let Some(has_cik) = self.has_cik {
+            conditions.push(exists_condition(has_cik, "SELECT 1 FROM CikToSid k WHERE k.sid = c.sid"));
+        }
+        if let Some(has_website) = self.has_website {
+            conditions.push(exists_condition(has_website, "SELECT 1 FROM CompanyWebsites w WHERE w.sid = c.sid"));
+        }
+        if let Some(has_career_page) = self.has_career_page {
+            if self.inherit_career_pages {
+                conditions.push(exists_condition(has_career_page, &format!(
+                    "SELECT 1 FROM CompanyCareerPage p WHERE p.sid = c.sid \
+                     OR p.sid IN ({} SELECT sid FROM hierarchy)",
+                    hierarchy_cte("c.sid", Some("CURRENT_DATE"), true)
+                )));
+            } else {
+                conditions.push(exists_condition(has_career_page, "SELECT 1 FROM CompanyCareerPage p WHERE p.sid = c.sid"));
+            }
+        }
+        if let Some(has_bot_protection) = self.has_bot_protection {
+            conditions.push(exists_condition(has_bot_protection, "SELECT 1 FROM WebsiteBotProtection b WHERE b.sid = c.sid"));
+        }
+        if !self.bot_protection_kinds.is_empty() {
+            let kinds: Vec<String> = self.bot_protection_kinds.iter().map(|kind| kind.as_str().to_string()).collect();
+            params.push(Box::new(kinds));
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM WebsiteBotProtection b WHERE b.sid = c.sid AND b.kind = ANY(${}))",
+                params.len()
+            ));
+        }
+        if !self.discovery_statuses.is_empty() {
+            let statuses: Vec<String> = self.discovery_statuses.iter().map(|status| status.as_str().to_string()).collect();
+            params.push(Box::new(statuses));
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM CompanyDiscovery d WHERE d.sid = c.sid AND d.status = ANY(${}))",
+                params.len()
+            ));
+        }
+
+        if let Some(profile_id) = self.shortlisted_by {
+            params.push(Box::new(profile_id));
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM ProfileShortlist s WHERE s.sid = c.sid AND s.profile_id = ${})",
+                params.len()
+            ));
+        }
+        if let Some(profile_id) = self.not_excluded_by {
+            params.push(Box::new(profile_id));
+            conditions.push(format!(
+                "NOT EXISTS (SELECT 1 FROM CandidateProfiles cp WHERE cp.profile_id = ${} AND c.sid = ANY(cp.excluded_sids))",
+                params.len()
+            ));
+        }
+
+        let mut query = "SELECT c.sid FROM CompanyTable c".to_string();
+        if !conditions.is_empty() {
+            query.push_str(" WHERE ");
+            query.push_str(&conditions.join(" AND "));
+        }
+
+        let (sort, direction) = self.sort.unwrap_or((CompanySort::Sid, SortDirection::Ascending));
+        let sort_column = match sort {
+            CompanySort::Sid => "c.sid",
+            CompanySort::Alias => "(SELECT a.CompanyAlias FROM CompanyAliases a WHERE a.sid = c.sid AND a.is_primary)",
+        };
+        let direction = match direction {
+            SortDirection::Ascending => "ASC",
+            SortDirection::Descending => "DESC",
+        };
+        query.push_str(&format!(" ORDER BY {} {}, c.sid", sort_column, direction));
+
+        if let Some(limit) = self.limit {
+            params.push(Box::new(limit));
+            query.push_str(&format!(" LIMIT ${}", params.len()));
+        }
+        if let Some(offset) = self.offset {
+            params.push(Box::new(offset));
+            query.push_str(&format!(" OFFSET ${}", params.len()));
+        }
+        (query, params)
+    }
+}
+
+fn exists_condition(should_exist: bool, subquery: &str) -> String {
+    if should_exist {
+        format!("EXISTS ({})", subquery)
+    } else {
+        format!("NOT EXISTS ({})", subquery)
+    }
+}
+
+/// Escapes the LIKE wildcards in some text so that it is matched literally
+pub fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Borrows the boxed parameters in the form tokio_postgres expects
+pub(crate) fn as_sql_params(params: &QueryParams) -> Vec<&(dyn ToSql + Sync)> {
+    params.iter().map(|p| p.as_ref() as &(dyn ToSql + Sync)).collect()
+}
@@ -0,0 +1,155 @@
+use std::str::FromStr;
+use anyhow::{bail, Error};
+use chrono::{NaiveDate, Utc};
+use company_common::{CareerPage, CompanyRelationship, RelationshipKind};
+use crate::CompanyDataStore;
+
+/// A company above or below another in the hierarchy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HierarchyEntry {
+    pub sid: i32,
+    /// How the nearer of the two companies on the path's last step belongs to the other
+    pub kind: RelationshipKind,
+    /// 1 for direct parents and children, 2 for grandparents and grandchildren, ...
+    pub depth: i32,
+}
+
+/// SQL for a recursive CTE named `hierarchy` holding every company above (or below) `start` in the hierarchy,
+/// through relationships valid on `on`, or through relationships from any period if `on` is None.
+/// Each company comes once per path to it, with the path's depth.
+/// `start` and `on` are SQL expressions, so the CTE can be correlated with an outer query.
+pub(crate) fn hierarchy_cte(start: &str, on: Option<&str>, upwards: bool) -> String {
+    let (from, to) = if upwards { ("child_sid", "parent_sid") } else { ("parent_sid", "child_sid") };
+    let valid_on = match on {
+        Some(on) => format!("(r.valid_from IS NULL OR r.valid_from <= {on}) AND (r.valid_to IS NULL OR r.valid_to > {on})", on = on),
+        None => "true".to_string(),
+    };
+    format!(
+        "WITH RECURSIVE hierarchy (sid, kind, depth, path) AS (\
+            SELECT r.{to}, r.kind, 1, ARRAY[r.{from}, r.{to}] FROM CompanyRelationships r \
+            WHERE r.{from} = {start} AND {valid_on} \
+            UNION ALL \
+            SELECT r.{to}, r.kind, h.depth + 1, h.path || r.{to} FROM CompanyRelationships r \
+            JOIN hierarchy h ON r.{from} = h.sid \
+            WHERE NOT r.{to} = ANY(h.path) AND {valid_on})",
+        from = from, to = to, start = start, valid_on = valid_on,
+    )
+}
+
+impl CompanyDataStore {
+    /// Adds a relationship, or updates the end of its validity period if it's already stored with the same start.
+    /// Fails if it would make a company its own ancestor.
+    pub async fn add_relationship(&mut self, relationship: &CompanyRelationship, dry_run: bool) -> Result<(), Error> {
+        if relationship.child_sid == relationship.parent_sid {
+            bail!("Company {} can't be related to itself", relationship.child_sid);
+        }
+        if let (Some(from), Some(to)) = (relationship.valid_from, relationship.valid_to) {
+            if to <= from {
+                bail!("Relationship ends ({}) before it starts ({})", to, from);
+            }
+        }
+        let query = "INSERT INTO CompanyRelationships (child_sid, parent_sid, kind, valid_from, valid_to) \
+            VALUES ($1, $2, $3, $4, $5) \
+            ON CONFLICT (child_sid, parent_sid, kind, COALESCE(valid_from, '-infinity'::DATE)) \
+            DO UPDATE SET valid_to = EXCLUDED.valid_to".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(());
+        }
+        // cycles are checked across every period, since relationships can be backdated
+        let cycle = format!("{} SELECT 1 FROM hierarchy WHERE sid = $2", hierarchy_cte("$1", None, true));
+        let results = self.postgres_client.query(&cycle, &[&relationship.parent_sid, &relationship.child_sid]).await?;
+        if !results.is_empty() {
+            bail!("Company {} is already above company {} in the hierarchy", relationship.child_sid, relationship.parent_sid);
+        }
+        self.postgres_client.execute(&query, &[&relationship.child_sid, &relationship.parent_sid,
+            &relationship.kind.as_str(), &relationship.valid_from, &relationship.valid_to]).await?;
+        println!("Company {} is now a {} of company {}", relationship.child_sid, relationship.kind, relationship.parent_sid);
+        Ok(())
+    }
+
+    /// Ends the open-ended relationships of the given kind between two companies, on the given day.
+    /// Returns how many were ended.
+    pub async fn end_relationship(&mut self, child_sid: &i32, parent_sid: &i32, kind: RelationshipKind,
+                                  valid_to: NaiveDate, dry_run: bool) -> Result<u64, Error> {
+        let query = "UPDATE CompanyRelationships SET valid_to = $4 \
+            WHERE child_sid = $1 AND parent_sid = $2 AND kind = $3 AND valid_to IS NULL \
+            AND (valid_from IS NULL OR valid_from < $4)".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(0);
+        }
+        Ok(self.postgres_client.execute(&query, &[child_sid, parent_sid, &kind.as_str(), &valid_to]).await?)
+    }
+
+    /// Removes every period of the given relationship, as if it never existed
+    pub async fn remove_relationship(&mut self, child_sid: &i32, parent_sid: &i32, kind: RelationshipKind, dry_run: bool) -> Result<(), Error> {
+        let query = "DELETE FROM CompanyRelationships WHERE child_sid = $1 AND parent_sid = $2 AND kind = $3".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(());
+        }
+        self.postgres_client.execute(&query, &[child_sid, parent_sid, &kind.as_str()]).await?;
+        Ok(())
+    }
+
+    /// Every relationship the company is on either side of, whenever it held
+    pub async fn get_relationships_from_sid(&self, sid: &i32) -> Result<Vec<CompanyRelationship>, Error> {
+        let query = "SELECT child_sid, parent_sid, kind, valid_from, valid_to FROM CompanyRelationships \
+            WHERE child_sid = $1 OR parent_sid = $1 ORDER BY valid_from NULLS FIRST, child_sid, parent_sid, kind".to_string();
+        let results = self.postgres_client.query(&query, &[sid]).await?;
+        let mut relationships = Vec::new();
+        for row in results {
+            let kind: String = row.get(2);
+            relationships.push(CompanyRelationship {
+                child_sid: row.get(0),
+                parent_sid: row.get(1),
+                kind: RelationshipKind::from_str(&kind)?,
+                valid_from: row.get(3),
+                valid_to: row.get(4),
+            });
+        }
+        Ok(relationships)
+    }
+
+    /// Every company above the given one on the given day (parents, their parents, ...), nearest first
+    pub async fn get_ancestors(&self, sid: &i32, on: NaiveDate) -> Result<Vec<HierarchyEntry>, Error> {
+        self.walk_hierarchy(sid, on, true).await
+    }
+
+    /// Every company below the given one on the given day (subsidiaries, brands, acquisitions and theirs), nearest first
+    pub async fn get_descendants(&self, sid: &i32, on: NaiveDate) -> Result<Vec<HierarchyEntry>, Error> {
+        self.walk_hierarchy(sid, on, false).await
+    }
+
+    /// The company at the top of the given one's hierarchy today, or the company itself if it has no parent.
+    /// If there is more than one, the furthest up wins.
+    pub async fn get_ultimate_parent(&self, sid: &i32) -> Result<i32, Error> {
+        let ancestors = self.get_ancestors(sid, Utc::now().date_naive()).await?;
+        Ok(ancestors.iter().max_by_key(|ancestor| (ancestor.depth, -ancestor.sid)).map_or(*sid, |ancestor| ancestor.sid))
+    }
+
+    async fn walk_hierarchy(&self, sid: &i32, on: NaiveDate, upwards: bool) -> Result<Vec<HierarchyEntry>, Error> {
+        let query = format!("{} SELECT sid, kind, depth FROM (\
+                SELECT DISTINCT ON (sid) sid, kind, depth FROM hierarchy ORDER BY sid, depth, kind) nearest \
+            ORDER BY depth, sid", hierarchy_cte("$1", Some("$2::DATE"), upwards));
+        let results = self.postgres_client.query(&query, &[sid, &on]).await?;
+        let mut entries = Vec::new();
+        for row in results {
+            let kind: String = row.get(1);
+            entries.push(HierarchyEntry { sid: row.get(0), kind: RelationshipKind::from_str(&kind)?, depth: row.get(2) });
+        }
+        Ok(entries)
+    }
+
+    /// The career pages of the nearest company above the given one today that has any, along with its sid.
+    /// For companies whose careers live on a parent's site.
+    pub async fn get_inherited_career_pages(&self, sid: &i32) -> Result<Option<(i32, Vec<CareerPage>)>, Error> {
+        for ancestor in self.get_ancestors(sid, Utc::now().date_naive()).await? {
+            if let Some(career_pages) = self.get_career_pages_from_sid(&ancestor.sid).await? {
+                return Ok(Some((ancestor.sid, career_pages)));
+            }
+        }
+        Ok(None)
+    }
+}
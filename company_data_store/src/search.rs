@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+use anyhow::Error;
+use company_common::{normalize_alias, trigram_similarity, ProcessedCompany};
+use serde::Serialize;
+use crate::CompanyDataStore;
+
+/// How alike an alias has to be to the searched text to match, the same as pg_trgm's default
+pub const SEARCH_SIMILARITY_THRESHOLD: f32 = 0.3;
+
+/// Installs pg_trgm and the trigram index search_companies uses.
+/// normalize_company_alias must match company_common::normalize_alias.
+/// Only the function is needed without pg_trgm, so it is created first.
+pub(crate) const SEARCH_SETUP: &[&str] = &[
+    "CREATE OR REPLACE FUNCTION normalize_company_alias(alias TEXT) RETURNS TEXT AS $$
+        SELECT trim(regexp_replace(lower(alias), '[^[:alnum:]]+', ' ', 'g'))
+    $$ LANGUAGE SQL IMMUTABLE STRICT",
+    "CREATE EXTENSION IF NOT EXISTS pg_trgm",
+    "CREATE INDEX IF NOT EXISTS company_aliases_trigram_index ON CompanyAliases \
+        USING gin (normalize_company_alias(CompanyAlias) gin_trgm_ops)",
+];
+
+/// A company matched by search_companies, through its most similar alias
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CompanySearchResult {
+    pub sid: i32,
+    /// The alias that matched
+    pub alias: String,
+    /// How alike the alias is to the searched text, from 0 to 1
+    pub similarity: f32,
+}
+
+/// Searches companies that aren't in the data store, ranking them the same way search_companies does.
+/// Companies are given with their sids; a company matches through its most similar alias.
+pub fn search_companies_in<'a, I>(companies: I, text: &str, limit: usize) -> Vec<CompanySearchResult>
+where
+    I: IntoIterator<Item = (i32, &'a ProcessedCompany)>,
+{
+    let text = normalize_alias(text);
+    let results = companies.into_iter()
+        .filter_map(|(sid, company)| best_alias(sid, company.company_aliases.iter(), &text, SEARCH_SIMILARITY_THRESHOLD))
+        .collect();
+    rank_search_results(results, limit)
+}
+
+/// The company's alias most similar to the (normalized) text, if it is at least `threshold` alike.
+/// Ties go to the alphabetically first alias.
+fn best_alias<'a, I>(sid: i32, aliases: I, text: &str, threshold: f32) -> Option<CompanySearchResult>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    let mut aliases: Vec<&String> = aliases.into_iter().collect();
+    aliases.sort();
+    let mut best: Option<CompanySearchResult> = None;
+    for alias in aliases {
+        let similarity = trigram_similarity(&normalize_alias(alias), text);
+        if similarity < threshold {
+            continue;
+        }
+        if best.as_ref().is_none_or(|best| similarity > best.similarity) {
+            best = Some(CompanySearchResult { sid, alias: alias.clone(), similarity });
+        }
+    }
+    best
+}
+
+fn rank_search_results(mut results: Vec<CompanySearchResult>, limit: usize) -> Vec<CompanySearchResult> {
+    results.sort_by(|a, b| b.similarity.total_cmp(&a.similarity).then(a.sid.cmp(&b.sid)));
+    results.truncate(limit);
+    results
+}
+
+impl CompanyDataStore {
+    /// Finds the companies with an alias like the given text, ignoring case and punctuation,
+    /// most similar first. Each company appears once, with its most similar alias.
+    /// Companies that have been soft deleted aren't searched.
+    /// If pg_trgm couldn't be installed, only aliases containing the text match (still ranked by similarity).
+    pub async fn search_companies(&self, text: &str, limit: i64) -> Result<Vec<CompanySearchResult>, Error> {
+        if !self.has_trigram_search().await? {
+            return self.search_companies_by_substring(text, limit).await;
+        }
+        let query = "SELECT sid, alias, similarity FROM ( \
+                SELECT DISTINCT ON (a.sid) a.sid, a.CompanyAlias AS alias, \
+                    similarity(normalize_company_alias(a.CompanyAlias), $1) AS similarity \
+                FROM CompanyAliases a JOIN CompanyTable c ON c.sid = a.sid \
+                WHERE c.deleted_at IS NULL AND normalize_company_alias(a.CompanyAlias) % $1 \
+                ORDER BY a.sid, similarity DESC, a.CompanyAlias \
+            ) best ORDER BY similarity DESC, sid LIMIT $2".to_string();
+        let text = normalize_alias(text);
+        let results = self.postgres_client.query(&query, &[&text, &limit]).await?;
+        Ok(results.iter().map(|row| CompanySearchResult {
+            sid: row.get(0),
+            alias: row.get(1),
+            similarity: row.get(2),
+        }).collect())
+    }
+
+    /// Whether pg_trgm is installed, see SEARCH_SETUP
+    async fn has_trigram_search(&self) -> Result<bool, Error> {
+        let results = self.postgres_client.query("SELECT 1 FROM pg_extension WHERE extname = 'pg_trgm'", &[]).await?;
+        Ok(!results.is_empty())
+    }
+
+    /// search_companies without pg_trgm: finds the aliases containing the text with ILIKE and ranks them in Rust
+    async fn search_companies_by_substring(&self, text: &str, limit: i64) -> Result<Vec<CompanySearchResult>, Error> {
+        let query = "SELECT a.sid, a.CompanyAlias FROM CompanyAliases a JOIN CompanyTable c ON c.sid = a.sid \
+            WHERE c.deleted_at IS NULL AND normalize_company_alias(a.CompanyAlias) ILIKE '%' || $1 || '%'".to_string();
+        let text = normalize_alias(text);
+        let rows = self.postgres_client.query(&query, &[&text]).await?;
+        let mut aliases: BTreeMap<i32, Vec<String>> = BTreeMap::new();
+        for row in rows {
+            aliases.entry(row.get(0)).or_default().push(row.get(1));
+        }
+        let results = aliases.iter()
+            .filter_map(|(sid, aliases)| best_alias(*sid, aliases, &text, 0.0))
+            .collect();
+        Ok(rank_search_results(results, limit.max(0) as usize))
+    }
+}
@@ -0,0 +1,362 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use anyhow::{bail, Error};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use tokio_postgres::{IsolationLevel, Row, Transaction};
+use crate::{ChangeOperation, ChangeTable, CompanyDataStore};
+
+/// Installs the triggers that record every change to companies, websites, career pages and tags in
+/// CompanyChangeLog. Each trigger passes its ChangeTable name and the column identifying a row within
+/// a company (besides sid) as its arguments. Like the change notifications, updates that only bump
+/// a company's version aren't recorded.
+pub(crate) const CHANGE_LOG_TRIGGERS: &[&str] = &[
+    "CREATE OR REPLACE FUNCTION log_company_change() RETURNS trigger AS $$
+    DECLARE
+        old_row JSONB;
+        new_row JSONB;
+    BEGIN
+        IF TG_OP <> 'INSERT' THEN
+            old_row := to_jsonb(OLD);
+        END IF;
+        IF TG_OP <> 'DELETE' THEN
+            new_row := to_jsonb(NEW);
+        END IF;
+        INSERT INTO CompanyChangeLog (table_name, operation, sid, row_key, old_row, new_row)
+            VALUES (TG_ARGV[0], lower(TG_OP), (COALESCE(new_row, old_row) ->> 'sid')::INTEGER,
+                    COALESCE(new_row, old_row) ->> TG_ARGV[1], old_row, new_row);
+        RETURN NULL;
+    END;
+    $$ LANGUAGE plpgsql",
+    "DROP TRIGGER IF EXISTS log_company_change ON CompanyTable",
+    "CREATE TRIGGER log_company_change AFTER INSERT OR DELETE ON CompanyTable \
+        FOR EACH ROW EXECUTE FUNCTION log_company_change('company', 'sid')",
+    "DROP TRIGGER IF EXISTS log_company_update ON CompanyTable",
+    "CREATE TRIGGER log_company_update AFTER UPDATE ON CompanyTable \
+        FOR EACH ROW WHEN ((to_jsonb(OLD) - 'version') IS DISTINCT FROM (to_jsonb(NEW) - 'version')) \
+        EXECUTE FUNCTION log_company_change('company', 'sid')",
+    "DROP TRIGGER IF EXISTS log_company_change ON CompanyWebsites",
+    "CREATE TRIGGER log_company_change AFTER INSERT OR UPDATE OR DELETE ON CompanyWebsites \
+        FOR EACH ROW EXECUTE FUNCTION log_company_change('website', 'website_link')",
+    "DROP TRIGGER IF EXISTS log_company_change ON CompanyCareerPage",
+    "CREATE TRIGGER log_company_change AFTER INSERT OR UPDATE OR DELETE ON CompanyCareerPage \
+        FOR EACH ROW EXECUTE FUNCTION log_company_change('career_page', 'career_page_link')",
+    "DROP TRIGGER IF EXISTS log_company_change ON CompanyTags",
+    "CREATE TRIGGER log_company_change AFTER INSERT OR UPDATE OR DELETE ON CompanyTags \
+        FOR EACH ROW EXECUTE FUNCTION log_company_change('tag', 'tag')",
+];
+
+/// Career page columns that change on every check, and so don't count as the page changing.
+/// verified_at is what last_verified_at was called in older change log entries.
+const VOLATILE_CAREER_PAGE_COLUMNS: &[&str] = &["last_verified_at", "last_changed_at", "verified_at", "recorded_at"];
+
+/// The row before the first change and after the last, by table, sid and row key
+type RowChanges = BTreeMap<(ChangeTable, i32, String), (Option<Value>, Option<Value>)>;
+
+/// A named position in the change log
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    pub name: String,
+    /// The last change made before the snapshot was taken
+    pub change_id: i64,
+    /// The transactions whose changes are part of the snapshot, as a pg_snapshot. change_ids are handed out
+    /// when a change is made, not when it commits, so changes below change_id can still be left out.
+    /// None for snapshots taken before this was recorded, which hold every change up to change_id.
+    pub xid_snapshot: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub note: Option<String>,
+}
+
+/// A position in the change log, what a SnapshotRef resolves to
+struct LogPosition {
+    change_id: i64,
+    xid_snapshot: Option<String>,
+}
+
+/// One end of a diff
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotRef {
+    Named(String),
+    /// The state of the store at the given time
+    At(DateTime<Utc>),
+    Now,
+}
+
+/// A row of company data changing, as recorded in CompanyChangeLog
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeLogEntry {
+    pub change_id: i64,
+    pub table: ChangeTable,
+    pub operation: ChangeOperation,
+    pub sid: i32,
+    /// Identifies the row within the company, e.g. the website link; the sid for companies
+    pub key: String,
+    /// None for inserts
+    pub old_row: Option<Value>,
+    /// None for deletes
+    pub new_row: Option<Value>,
+}
+
+/// A website, career page or tag of a company
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct CompanyItem {
+    pub sid: i32,
+    /// The website or career page URL, or the tag
+    pub value: String,
+}
+
+/// What changed between two points in the change log. Rows that were added and removed again
+/// in between don't show up; soft deleting a company counts as removing it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SnapshotDiff {
+    pub from_change: i64,
+    pub to_change: i64,
+    pub companies_added: Vec<i32>,
+    pub companies_removed: Vec<i32>,
+    pub websites_added: Vec<CompanyItem>,
+    pub websites_removed: Vec<CompanyItem>,
+    pub career_pages_added: Vec<CompanyItem>,
+    pub career_pages_removed: Vec<CompanyItem>,
+    /// Career pages whose label, region, ATS, status code or primary flag changed
+    pub career_pages_changed: Vec<CompanyItem>,
+    pub tags_added: Vec<CompanyItem>,
+    pub tags_removed: Vec<CompanyItem>,
+}
+
+/// How print_diff prints a diff
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffFormat {
+    Text,
+    Json,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.companies_added.is_empty() && self.companies_removed.is_empty()
+            && self.websites_added.is_empty() && self.websites_removed.is_empty()
+            && self.career_pages_added.is_empty() && self.career_pages_removed.is_empty()
+            && self.career_pages_changed.is_empty()
+            && self.tags_added.is_empty() && self.tags_removed.is_empty()
+    }
+}
+
+impl fmt::Display for SnapshotDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Changes {} to {}:", self.from_change, self.to_change)?;
+        if self.is_empty() {
+            return writeln!(f, "  nothing changed");
+        }
+        writeln!(f, "  companies: {} added, {} removed", self.companies_added.len(), self.companies_removed.len())?;
+        for sid in &self.companies_added {
+            writeln!(f, "    + {}", sid)?;
+        }
+        for sid in &self.companies_removed {
+            writeln!(f, "    - {}", sid)?;
+        }
+        let sections = [
+            ("websites", &self.websites_added, &self.websites_removed, None),
+            ("career pages", &self.career_pages_added, &self.career_pages_removed, Some(&self.career_pages_changed)),
+            ("tags", &self.tags_added, &self.tags_removed, None),
+        ];
+        for (name, added, removed, changed) in sections {
+            write!(f, "  {}: {} added, {} removed", name, added.len(), removed.len())?;
+            match changed {
+                Some(changed) => writeln!(f, ", {} changed", changed.len())?,
+                None => writeln!(f)?,
+            }
+            for (sign, items) in [("+", added), ("-", removed), ("~", changed.unwrap_or(&Vec::new()))] {
+                for item in items {
+                    writeln!(f, "    {} {} {}", sign, item.sid, item.value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn snapshot_from_row(row: &Row) -> Snapshot {
+    Snapshot { name: row.get(0), change_id: row.get(1), xid_snapshot: row.get(2), created_at: row.get(3), note: row.get(4) }
+}
+
+/// Whether a change is part of the position bound to the given placeholders: made by a transaction that had
+/// committed when the xid snapshot was taken or, without one, at or before the change_id.
+/// Changes logged before transactions were recorded have no txid, and go by change_id too.
+fn visible_at(change_id: &str, xid_snapshot: &str) -> String {
+    format!(
+        "COALESCE(pg_visible_in_snapshot(txid, {xid_snapshot}::TEXT::pg_snapshot), change_id <= {change_id})",
+        change_id = change_id,
+        xid_snapshot = xid_snapshot,
+    )
+}
+
+/// The change log position the reference stands for, read in the given (repeatable read) transaction
+async fn resolve_snapshot(transaction: &Transaction<'_>, snapshot: &SnapshotRef) -> Result<LogPosition, Error> {
+    let results = match snapshot {
+        SnapshotRef::Named(name) => {
+            let results = transaction.query(
+                "SELECT change_id, xid_snapshot FROM CompanySnapshots WHERE name = $1", &[name],
+            ).await?;
+            if results.is_empty() {
+                bail!("No snapshot named {}", name);
+            }
+            results
+        },
+        // when a transaction committed isn't recorded, so this goes by when its changes were made
+        SnapshotRef::At(at) => transaction.query(
+            "SELECT COALESCE(MAX(change_id), 0), NULL::TEXT FROM CompanyChangeLog WHERE changed_at <= $1", &[at],
+        ).await?,
+        SnapshotRef::Now => transaction.query(
+            "SELECT COALESCE(MAX(change_id), 0), pg_current_snapshot()::TEXT FROM CompanyChangeLog", &[],
+        ).await?,
+    };
+    Ok(LogPosition { change_id: results[0].get(0), xid_snapshot: results[0].get(1) })
+}
+
+/// Whether the row counts as present; soft deleted companies don't
+fn row_exists(table: ChangeTable, row: &Option<Value>) -> bool {
+    match row {
+        Some(row) => table != ChangeTable::Company || row.get("deleted_at").is_none_or(Value::is_null),
+        None => false,
+    }
+}
+
+fn without_volatile_columns(row: &Option<Value>) -> Option<Value> {
+    let mut row = row.clone();
+    if let Some(Value::Object(columns)) = &mut row {
+        for column in VOLATILE_CAREER_PAGE_COLUMNS {
+            columns.remove(*column);
+        }
+    }
+    row
+}
+
+/// Works out the net effect of a run of changes, which must be in change_id order
+pub fn diff_changes(changes: &[ChangeLogEntry]) -> SnapshotDiff {
+    // the row before the first change and after the last, for every row that changed
+    let mut rows: RowChanges = BTreeMap::new();
+    for change in changes {
+        rows.entry((change.table, change.sid, change.key.clone()))
+            .or_insert_with(|| (change.old_row.clone(), None))
+            .1 = change.new_row.clone();
+    }
+
+    let mut diff = SnapshotDiff::default();
+    for ((table, sid, key), (before, after)) in rows {
+        let existed = row_exists(table, &before);
+        let exists = row_exists(table, &after);
+        let item = CompanyItem { sid, value: key };
+        let (added, removed, changed) = match table {
+            ChangeTable::Company => {
+                if exists && !existed {
+                    diff.companies_added.push(sid);
+                } else if existed && !exists {
+                    diff.companies_removed.push(sid);
+                }
+                continue;
+            },
+            ChangeTable::Website => (&mut diff.websites_added, &mut diff.websites_removed, None),
+            ChangeTable::CareerPage => (&mut diff.career_pages_added, &mut diff.career_pages_removed, Some(&mut diff.career_pages_changed)),
+            ChangeTable::Tag => (&mut diff.tags_added, &mut diff.tags_removed, None),
+            _ => continue,
+        };
+        if exists && !existed {
+            added.push(item);
+        } else if existed && !exists {
+            removed.push(item);
+        } else if let Some(changed) = changed {
+            if exists && without_volatile_columns(&before) != without_volatile_columns(&after) {
+                changed.push(item);
+            }
+        }
+    }
+    diff
+}
+
+impl CompanyDataStore {
+    /// Names the current position in the change log, so later changes can be diffed against it.
+    /// The position is the transaction snapshot it was taken in, so it never waits for writers.
+    pub async fn create_snapshot(&mut self, name: &str, note: Option<&str>) -> Result<Snapshot, Error> {
+        if self.get_snapshot(name).await?.is_some() {
+            bail!("There is already a snapshot named {}", name);
+        }
+        // MAX(change_id) and pg_current_snapshot() both see the transaction's snapshot
+        let query = "INSERT INTO CompanySnapshots (name, change_id, xid_snapshot, note) \
+            VALUES ($1, (SELECT COALESCE(MAX(change_id), 0) FROM CompanyChangeLog), pg_current_snapshot()::TEXT, $2) \
+            RETURNING name, change_id, xid_snapshot, created_at, note".to_string();
+        let transaction = self.postgres_client.build_transaction()
+            .isolation_level(IsolationLevel::RepeatableRead)
+            .start().await?;
+        let results = transaction.query(&query, &[&name, &note]).await?;
+        transaction.commit().await?;
+        let snapshot = snapshot_from_row(&results[0]);
+        println!("Snapshot {} taken at change {}", name, snapshot.change_id);
+        Ok(snapshot)
+    }
+
+    pub async fn get_snapshot(&self, name: &str) -> Result<Option<Snapshot>, Error> {
+        let query = "SELECT name, change_id, xid_snapshot, created_at, note FROM CompanySnapshots WHERE name = $1".to_string();
+        let results = self.postgres_client.query(&query, &[&name]).await?;
+        Ok(results.first().map(snapshot_from_row))
+    }
+
+    /// Every snapshot, oldest first
+    pub async fn get_snapshots(&self) -> Result<Vec<Snapshot>, Error> {
+        let query = "SELECT name, change_id, xid_snapshot, created_at, note FROM CompanySnapshots \
+            ORDER BY change_id, created_at".to_string();
+        let results = self.postgres_client.query(&query, &[]).await?;
+        Ok(results.iter().map(snapshot_from_row).collect())
+    }
+
+    pub async fn delete_snapshot(&mut self, name: &str) -> Result<(), Error> {
+        self.postgres_client.execute("DELETE FROM CompanySnapshots WHERE name = $1", &[&name]).await?;
+        Ok(())
+    }
+
+    /// What changed between two snapshots, times, or a snapshot and now.
+    /// Both ends are resolved and the changes read in one repeatable read transaction, so a change
+    /// committing meanwhile can't end up in the diff without being part of "now".
+    pub async fn diff_snapshots(&mut self, from: &SnapshotRef, to: &SnapshotRef) -> Result<SnapshotDiff, Error> {
+        let transaction = self.postgres_client.build_transaction()
+            .isolation_level(IsolationLevel::RepeatableRead)
+            .read_only(true)
+            .start().await?;
+        let from_position = resolve_snapshot(&transaction, from).await?;
+        let to_position = resolve_snapshot(&transaction, to).await?;
+        if to_position.change_id < from_position.change_id {
+            bail!("{:?} is before {:?}", to, from);
+        }
+        // every change visible at the later end is at or below its change_id
+        let query = format!("SELECT change_id, table_name, operation, sid, row_key, old_row, new_row FROM CompanyChangeLog \
+            WHERE change_id <= $3 AND NOT {} AND {} ORDER BY change_id", visible_at("$1", "$2"), visible_at("$3", "$4"));
+        let results = transaction.query(&query, &[
+            &from_position.change_id, &from_position.xid_snapshot, &to_position.change_id, &to_position.xid_snapshot,
+        ]).await?;
+        transaction.commit().await?;
+        let mut changes = Vec::new();
+        for row in results {
+            changes.push(ChangeLogEntry {
+                change_id: row.get(0),
+                table: serde_json::from_value(Value::String(row.get(1)))?,
+                operation: serde_json::from_value(Value::String(row.get(2)))?,
+                sid: row.get(3),
+                key: row.get(4),
+                old_row: row.get(5),
+                new_row: row.get(6),
+            });
+        }
+        let mut diff = diff_changes(&changes);
+        diff.from_change = from_position.change_id;
+        diff.to_change = to_position.change_id;
+        Ok(diff)
+    }
+
+    pub async fn print_diff(&mut self, from: &SnapshotRef, to: &SnapshotRef, format: DiffFormat) -> Result<(), Error> {
+        let diff = self.diff_snapshots(from, to).await?;
+        match format {
+            DiffFormat::Text => print!("{}", diff),
+            DiffFormat::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+use std::fmt;
+use anyhow::Error;
+use chrono::NaiveDate;
+use serde::Serialize;
+use crate::{CompanyDataStore, CompanyTables, DiscoveryStatus};
+
+/// How many days of growth the statistics report covers
+pub const STATS_GROWTH_DAYS: i32 = 30;
+
+/// How print_stats prints the statistics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsFormat {
+    /// Human readable tables
+    Table,
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TableRowCount {
+    pub table: String,
+    pub rows: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CoverageStats {
+    /// Companies that haven't been soft deleted
+    pub companies: i64,
+    pub deleted_companies: i64,
+    pub with_websites: i64,
+    pub without_websites: i64,
+    pub with_career_page: i64,
+    pub without_career_page: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveryStatusCount {
+    pub status: String,
+    pub companies: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BotProtectionCount {
+    pub kind: String,
+    pub urls: i64,
+    pub companies: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BotProtectionStats {
+    pub websites: i64,
+    pub protected_urls: i64,
+    pub protected_companies: i64,
+    pub by_kind: Vec<BotProtectionCount>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub companies: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyGrowth {
+    pub day: NaiveDate,
+    pub companies_added: i64,
+    pub websites_added: i64,
+}
+
+/// A snapshot of how much data the store holds and how far along the pipeline it is
+#[derive(Debug, Clone, Serialize)]
+pub struct DataStoreStats {
+    pub tables: Vec<TableRowCount>,
+    pub coverage: CoverageStats,
+    pub discovery: Vec<DiscoveryStatusCount>,
+    pub bot_protection: BotProtectionStats,
+    /// Most used tags first
+    pub tags: Vec<TagCount>,
+    /// The last STATS_GROWTH_DAYS days, oldest first
+    pub growth: Vec<DailyGrowth>,
+}
+
+impl fmt::Display for DataStoreStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Table rows")?;
+        for table in &self.tables {
+            writeln!(f, "  {:<24}{:>12}", table.table, table.rows)?;
+        }
+
+        let coverage = &self.coverage;
+        writeln!(f, "Coverage")?;
+        writeln!(f, "  {:<24}{:>12}", "companies", coverage.companies)?;
+        writeln!(f, "  {:<24}{:>12}", "deleted companies", coverage.deleted_companies)?;
+        writeln!(f, "  {:<24}{:>12}", "with websites", coverage.with_websites)?;
+        writeln!(f, "  {:<24}{:>12}", "without websites", coverage.without_websites)?;
+        writeln!(f, "  {:<24}{:>12}", "with career page", coverage.with_career_page)?;
+        writeln!(f, "  {:<24}{:>12}", "without career page", coverage.without_career_page)?;
+
+        writeln!(f, "Discovery status")?;
+        for status in &self.discovery {
+            writeln!(f, "  {:<24}{:>12}", status.status, status.companies)?;
+        }
+
+        let bot_protection = &self.bot_protection;
+        writeln!(f, "Bot protection")?;
+        writeln!(f, "  {:<24}{:>12}", "websites", bot_protection.websites)?;
+        writeln!(f, "  {:<24}{:>12}", "protected urls", bot_protection.protected_urls)?;
+        writeln!(f, "  {:<24}{:>12}", "protected companies", bot_protection.protected_companies)?;
+        writeln!(f, "  {:<24}{:>12}{:>12}", "kind", "urls", "companies")?;
+        for kind in &bot_protection.by_kind {
+            writeln!(f, "  {:<24}{:>12}{:>12}", kind.kind, kind.urls, kind.companies)?;
+        }
+
+        writeln!(f, "Tags")?;
+        for tag in &self.tags {
+            writeln!(f, "  {:<24}{:>12}", tag.tag, tag.companies)?;
+        }
+
+        writeln!(f, "Growth (last {} days)", STATS_GROWTH_DAYS)?;
+        writeln!(f, "  {:<12}{:>12}{:>12}", "day", "companies", "websites")?;
+        for day in &self.growth {
+            writeln!(f, "  {:<12}{:>12}{:>12}", day.day.to_string(), day.companies_added, day.websites_added)?;
+        }
+        Ok(())
+    }
+}
+
+impl CompanyDataStore {
+    /// Gathers row counts, coverage, discovery progress, bot protection prevalence, tag usage and growth
+    pub async fn collect_stats(&self) -> Result<DataStoreStats, Error> {
+        let mut tables = Vec::new();
+        for table in CompanyTables::all() {
+            let rows = self.count_rows(&format!("SELECT COUNT(*) FROM {}", table.as_str())).await?;
+            tables.push(TableRowCount { table: table.as_str().to_string(), rows });
+        }
+
+        let live = "FROM CompanyTable c WHERE c.deleted_at IS NULL";
+        let companies = self.count_rows(&format!("SELECT COUNT(*) {}", live)).await?;
+        let with_websites = self.count_rows(&format!(
+            "SELECT COUNT(*) {} AND EXISTS (SELECT 1 FROM CompanyWebsites w WHERE w.sid = c.sid)", live)).await?;
+        let with_career_page = self.count_rows(&format!(
+            "SELECT COUNT(*) {} AND EXISTS (SELECT 1 FROM CompanyCareerPage p WHERE p.sid = c.sid)", live)).await?;
+        let coverage = CoverageStats {
+            companies,
+            deleted_companies: self.count_rows("SELECT COUNT(*) FROM CompanyTable WHERE deleted_at IS NOT NULL").await?,
+            with_websites,
+            without_websites: companies - with_websites,
+            with_career_page,
+            without_career_page: companies - with_career_page,
+        };
+
+        let query = "SELECT d.status, COUNT(*) FROM CompanyDiscovery d JOIN CompanyTable c ON c.sid = d.sid \
+            WHERE c.deleted_at IS NULL GROUP BY d.status".to_string();
+        let results = self.postgres_client.query(&query, &[]).await?;
+        let discovery = DiscoveryStatus::all().iter().map(|status| DiscoveryStatusCount {
+            status: status.as_str().to_string(),
+            companies: results.iter()
+                .find(|row| row.get::<_, String>(0) == status.as_str())
+                .map(|row| row.get(1))
+                .unwrap_or(0),
+        }).collect();
+
+        let query = "SELECT b.kind, COUNT(DISTINCT (b.sid, b.url)), COUNT(DISTINCT b.sid) FROM WebsiteBotProtection b \
+            JOIN CompanyTable c ON c.sid = b.sid WHERE c.deleted_at IS NULL \
+            GROUP BY b.kind ORDER BY COUNT(*) DESC, b.kind".to_string();
+        let by_kind = self.postgres_client.query(&query, &[]).await?.iter()
+            .map(|row| BotProtectionCount { kind: row.get(0), urls: row.get(1), companies: row.get(2) })
+            .collect();
+        let bot_protection = BotProtectionStats {
+            websites: self.count_rows(&format!(
+                "SELECT COUNT(*) FROM CompanyWebsites w WHERE w.sid IN (SELECT c.sid {})", live)).await?,
+            protected_urls: self.count_rows(&format!(
+                "SELECT COUNT(DISTINCT (b.sid, b.url)) FROM WebsiteBotProtection b WHERE b.sid IN (SELECT c.sid {})", live)).await?,
+            protected_companies: self.count_rows(&format!(
+                "SELECT COUNT(DISTINCT b.sid) FROM WebsiteBotProtection b WHERE b.sid IN (SELECT c.sid {})", live)).await?,
+            by_kind,
+        };
+
+        let tags = self.get_tag_counts(None).await?;
+
+        let query = "SELECT day::DATE, \
+                (SELECT COUNT(*) FROM CompanyTable c WHERE c.created_at::DATE = day::DATE), \
+                (SELECT COUNT(*) FROM CompanyWebsites w WHERE w.recorded_at::DATE = day::DATE) \
+            FROM generate_series(CURRENT_DATE - ($1 - 1), CURRENT_DATE, INTERVAL '1 day') AS day \
+            ORDER BY day".to_string();
+        let growth = self.postgres_client.query(&query, &[&STATS_GROWTH_DAYS]).await?.iter()
+            .map(|row| DailyGrowth { day: row.get(0), companies_added: row.get(1), websites_added: row.get(2) })
+            .collect();
+
+        Ok(DataStoreStats {
+            tables,
+            coverage,
+            discovery,
+            bot_protection,
+            tags,
+            growth,
+        })
+    }
+
+    pub async fn print_stats(&self, format: StatsFormat) -> Result<(), Error> {
+        let stats = self.collect_stats().await?;
+        match format {
+            StatsFormat::Table => print!("{}", stats),
+            StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+        }
+        Ok(())
+    }
+
+    /// query must select a single count, and is always our own SQL, never user input
+    async fn count_rows(&self, query: &str) -> Result<i64, Error> {
+        let results = self.postgres_client.query(query, &[]).await?;
+        Ok(results[0].get(0))
+    }
+}
@@ -0,0 +1,128 @@
+use anyhow::{bail, Error};
+use company_common::{Tag, TagNamespace};
+use crate::{escape_like, CompanyDataStore, CompanyQuery, TagCount};
+
+impl CompanyDataStore {
+    /// Tags a company, doing nothing if it already has the tag
+    pub async fn tag_company(&mut self, sid: &i32, tag: &Tag, dry_run: bool) -> Result<(), Error> {
+        let query = "INSERT INTO CompanyTags (sid, tag) VALUES ($1, $2) ON CONFLICT DO NOTHING".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(());
+        }
+        self.postgres_client.execute(&query, &[sid, &tag.to_string()]).await?;
+        Ok(())
+    }
+
+    pub async fn untag_company(&mut self, sid: &i32, tag: &Tag, dry_run: bool) -> Result<(), Error> {
+        let query = "DELETE FROM CompanyTags WHERE sid = $1 AND tag = $2".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(());
+        }
+        self.postgres_client.execute(&query, &[sid, &tag.to_string()]).await?;
+        Ok(())
+    }
+
+    /// Tags every company matched by the query.
+    /// Returns how many companies were tagged, not counting those that already had the tag.
+    /// @param dry_run: if true, only counts the companies that would be tagged
+    pub async fn tag_companies(&mut self, query: &CompanyQuery, tag: &Tag, dry_run: bool) -> Result<u64, Error> {
+        let sids = self.query_sids(query).await?;
+        let tag = tag.to_string();
+        if dry_run {
+            let results = self.postgres_client.query(
+                "SELECT COUNT(*) FROM unnest($1::INTEGER[]) AS s(sid) \
+                 WHERE NOT EXISTS (SELECT 1 FROM CompanyTags t WHERE t.sid = s.sid AND t.tag = $2)",
+                &[&sids, &tag],
+            ).await?;
+            return Ok(results[0].get::<_, i64>(0) as u64);
+        }
+        Ok(self.postgres_client.execute(
+            "INSERT INTO CompanyTags (sid, tag) SELECT unnest($1::INTEGER[]), $2 ON CONFLICT DO NOTHING",
+            &[&sids, &tag],
+        ).await?)
+    }
+
+    /// Removes the tag from every company matched by the query.
+    /// Returns how many companies lost the tag.
+    /// @param dry_run: if true, only counts the companies that would lose the tag
+    pub async fn untag_companies(&mut self, query: &CompanyQuery, tag: &Tag, dry_run: bool) -> Result<u64, Error> {
+        let sids = self.query_sids(query).await?;
+        let tag = tag.to_string();
+        if dry_run {
+            let results = self.postgres_client.query(
+                "SELECT COUNT(*) FROM CompanyTags WHERE sid = ANY($1) AND tag = $2", &[&sids, &tag],
+            ).await?;
+            return Ok(results[0].get::<_, i64>(0) as u64);
+        }
+        Ok(self.postgres_client.execute(
+            "DELETE FROM CompanyTags WHERE sid = ANY($1) AND tag = $2", &[&sids, &tag],
+        ).await?)
+    }
+
+    /// Renames a tag on every company that has it.
+    /// Fails if the new tag is already in use; merge the tags instead.
+    /// Returns how many companies were retagged.
+    pub async fn rename_tag(&mut self, from: &Tag, to: &Tag, dry_run: bool) -> Result<u64, Error> {
+        let results = self.postgres_client.query(
+            "SELECT COUNT(*) FROM CompanyTags WHERE tag = $1", &[&to.to_string()],
+        ).await?;
+        if results[0].get::<_, i64>(0) > 0 {
+            bail!("Tag {} is already in use, merge {} into it instead", to, from);
+        }
+        self.merge_tags(std::slice::from_ref(from), to, dry_run).await
+    }
+
+    /// Replaces each of the given tags with another tag, on every company that has any of them.
+    /// Companies that end up with the tag twice keep it once.
+    /// Returns how many companies had one of the merged tags.
+    /// @param dry_run: if true, only counts the companies that would be retagged
+    pub async fn merge_tags(&mut self, from: &[Tag], into: &Tag, dry_run: bool) -> Result<u64, Error> {
+        let from: Vec<String> = from.iter().map(|tag| tag.to_string()).filter(|tag| *tag != into.to_string()).collect();
+        let into = into.to_string();
+        if dry_run {
+            let results = self.postgres_client.query(
+                "SELECT COUNT(DISTINCT sid) FROM CompanyTags WHERE tag = ANY($1)", &[&from],
+            ).await?;
+            return Ok(results[0].get::<_, i64>(0) as u64);
+        }
+        let transaction = self.postgres_client.transaction().await?;
+        let sids: Vec<i32> = transaction.query(
+            "DELETE FROM CompanyTags WHERE tag = ANY($1) RETURNING sid", &[&from],
+        ).await?.iter().map(|row| row.get(0)).collect();
+        transaction.execute(
+            "INSERT INTO CompanyTags (sid, tag) SELECT DISTINCT unnest($1::INTEGER[]), $2 ON CONFLICT DO NOTHING",
+            &[&sids, &into],
+        ).await?;
+        transaction.commit().await?;
+        let mut sids = sids;
+        sids.sort();
+        sids.dedup();
+        Ok(sids.len() as u64)
+    }
+
+    /// Removes a tag from every company.
+    /// Returns how many companies lost the tag.
+    pub async fn delete_tag(&mut self, tag: &Tag, dry_run: bool) -> Result<u64, Error> {
+        let tag = tag.to_string();
+        if dry_run {
+            let results = self.postgres_client.query("SELECT COUNT(*) FROM CompanyTags WHERE tag = $1", &[&tag]).await?;
+            return Ok(results[0].get::<_, i64>(0) as u64);
+        }
+        Ok(self.postgres_client.execute("DELETE FROM CompanyTags WHERE tag = $1", &[&tag]).await?)
+    }
+
+    /// How many companies (that haven't been soft deleted) have each tag, most used first.
+    /// @param namespace: only count the tags in this namespace, or every tag if None
+    pub async fn get_tag_counts(&self, namespace: Option<&TagNamespace>) -> Result<Vec<TagCount>, Error> {
+        let pattern = match namespace {
+            Some(namespace) => format!("{}:%", escape_like(&namespace.prefix())),
+            None => "%".to_string(),
+        };
+        let query = "SELECT t.tag, COUNT(*) FROM CompanyTags t JOIN CompanyTable c ON c.sid = t.sid \
+            WHERE c.deleted_at IS NULL AND t.tag LIKE $1 GROUP BY t.tag ORDER BY COUNT(*) DESC, t.tag".to_string();
+        let results = self.postgres_client.query(&query, &[&pattern]).await?;
+        Ok(results.iter().map(|row| TagCount { tag: row.get(0), companies: row.get(1) }).collect())
+    }
+}
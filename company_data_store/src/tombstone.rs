@@ -0,0 +1,73 @@
+use anyhow::{bail, Error};
+use chrono::{DateTime, Utc};
+use crate::CompanyDataStore;
+
+/// Why clean_sids soft deletes a company
+pub const NO_ALIASES_REASON: &str = "no aliases";
+
+/// Left behind when a company is soft deleted. The company's rows are kept, but it is excluded
+/// from queries, and re-ingesting its CIK won't bring it back.
+#[derive(Debug, Clone)]
+pub struct Tombstone {
+    pub deleted_at: DateTime<Utc>,
+    pub reason: String,
+}
+
+impl CompanyDataStore {
+    /// Marks the company with the given sid as deleted, keeping all of its data so it can be restored.
+    pub async fn soft_delete_company(&mut self, sid: &i32, reason: &str, dry_run: bool) -> Result<(), Error> {
+        let query = "UPDATE CompanyTable SET deleted_at = now(), deleted_reason = $2 \
+            WHERE sid = $1 AND deleted_at IS NULL".to_string();
+        if dry_run {
+            println!("{}", query);
+            return Ok(());
+        }
+        let updated = self.postgres_client.execute(&query, &[&sid, &reason]).await?;
+        if updated == 0 {
+            bail!("No live company with sid {}", sid);
+        }
+        println!("Soft deleted company with sid {}: {}", sid, reason);
+        Ok(())
+    }
+
+    /// Undoes soft_delete_company
+    pub async fn restore_company(&mut self, sid: &i32) -> Result<(), Error> {
+        let query = "UPDATE CompanyTable SET deleted_at = NULL, deleted_reason = NULL \
+            WHERE sid = $1 AND deleted_at IS NOT NULL".to_string();
+        let updated = self.postgres_client.execute(&query, &[&sid]).await?;
+        if updated == 0 {
+            bail!("No deleted company with sid {}", sid);
+        }
+        println!("Restored company with sid {}", sid);
+        Ok(())
+    }
+
+    /// Returns the tombstone of the company with the given sid, if it has been soft deleted
+    pub async fn get_tombstone(&self, sid: &i32) -> Result<Option<Tombstone>, Error> {
+        let query = "SELECT deleted_at, deleted_reason FROM CompanyTable \
+            WHERE sid = $1 AND deleted_at IS NOT NULL".to_string();
+        let results = self.postgres_client.query(&query, &[&sid]).await?;
+        if results.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Tombstone {
+            deleted_at: results[0].get(0),
+            reason: results[0].get::<_, Option<String>>(1).unwrap_or_default(),
+        }))
+    }
+
+    pub async fn is_tombstoned(&self, sid: &i32) -> Result<bool, Error> {
+        Ok(self.get_tombstone(sid).await?.is_some())
+    }
+
+    /// Returns every soft deleted company's sid and tombstone, most recently deleted first
+    pub async fn get_tombstones(&self) -> Result<Vec<(i32, Tombstone)>, Error> {
+        let query = "SELECT sid, deleted_at, deleted_reason FROM CompanyTable \
+            WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC".to_string();
+        let results = self.postgres_client.query(&query, &[]).await?;
+        Ok(results.iter().map(|row| (row.get(0), Tombstone {
+            deleted_at: row.get(1),
+            reason: row.get::<_, Option<String>>(2).unwrap_or_default(),
+        })).collect())
+    }
+}
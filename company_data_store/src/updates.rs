@@ -0,0 +1,185 @@
+use std::fmt;
+use anyhow::{bail, Error};
+use company_common::{canonicalize_url, CanonicalUrl, CareerPageUpdate, ProcessedCompanyPatch, Provenance};
+use crate::{aliases, CompanyDataStore};
+
+/// Installs the triggers that keep CompanyTable.version counting changes to a company:
+/// any change to the company's own row, its identifiers, aliases, tags, websites or career pages bumps it.
+/// Each child table trigger passes the columns whose changes don't count, like when a page was last checked.
+pub(crate) const VERSION_TRIGGERS: &[&str] = &[
+    "CREATE OR REPLACE FUNCTION bump_own_company_version() RETURNS trigger AS $$
+    BEGIN
+        IF (to_jsonb(NEW) - 'version') IS DISTINCT FROM (to_jsonb(OLD) - 'version') THEN
+            NEW.version := OLD.version + 1;
+        END IF;
+        RETURN NEW;
+    END;
+    $$ LANGUAGE plpgsql",
+    "DROP TRIGGER IF EXISTS bump_company_version ON CompanyTable",
+    "CREATE TRIGGER bump_company_version BEFORE UPDATE ON CompanyTable \
+        FOR EACH ROW EXECUTE FUNCTION bump_own_company_version()",
+    "CREATE OR REPLACE FUNCTION bump_company_version() RETURNS trigger AS $$
+    BEGIN
+        IF TG_OP = 'UPDATE' AND (to_jsonb(NEW) - TG_ARGV) = (to_jsonb(OLD) - TG_ARGV) THEN
+            RETURN NULL;
+        END IF;
+        IF TG_OP <> 'INSERT' THEN
+            UPDATE CompanyTable SET version = version + 1 WHERE sid = OLD.sid;
+        END IF;
+        IF TG_OP <> 'DELETE' AND (TG_OP = 'INSERT' OR NEW.sid IS DISTINCT FROM OLD.sid) THEN
+            UPDATE CompanyTable SET version = version + 1 WHERE sid = NEW.sid;
+        END IF;
+        RETURN NULL;
+    END;
+    $$ LANGUAGE plpgsql",
+    "DROP TRIGGER IF EXISTS bump_company_version ON CikToSid",
+    "CREATE TRIGGER bump_company_version AFTER INSERT OR UPDATE OR DELETE ON CikToSid \
+        FOR EACH ROW EXECUTE FUNCTION bump_company_version()",
+    "DROP TRIGGER IF EXISTS bump_company_version ON CompanyIdentifiers",
+    "CREATE TRIGGER bump_company_version AFTER INSERT OR UPDATE OR DELETE ON CompanyIdentifiers \
+        FOR EACH ROW EXECUTE FUNCTION bump_company_version()",
+    "DROP TRIGGER IF EXISTS bump_company_version ON CompanyAliases",
+    "CREATE TRIGGER bump_company_version AFTER INSERT OR UPDATE OR DELETE ON CompanyAliases \
+        FOR EACH ROW EXECUTE FUNCTION bump_company_version()",
+    "DROP TRIGGER IF EXISTS bump_company_version ON CompanyTags",
+    "CREATE TRIGGER bump_company_version AFTER INSERT OR UPDATE OR DELETE ON CompanyTags \
+        FOR EACH ROW EXECUTE FUNCTION bump_company_version()",
+    "DROP TRIGGER IF EXISTS bump_company_version ON CompanyWebsites",
+    "CREATE TRIGGER bump_company_version AFTER INSERT OR UPDATE OR DELETE ON CompanyWebsites \
+        FOR EACH ROW EXECUTE FUNCTION bump_company_version('last_verified_at')",
+    "DROP TRIGGER IF EXISTS bump_company_version ON CompanyCareerPage",
+    "CREATE TRIGGER bump_company_version AFTER INSERT OR UPDATE OR DELETE ON CompanyCareerPage \
+        FOR EACH ROW EXECUTE FUNCTION bump_company_version('last_verified_at')",
+];
+
+/// The company was changed by someone else since its version was read.
+/// Returned (wrapped in an anyhow::Error) by update_company; check for it with `error.downcast_ref::<VersionConflict>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionConflict {
+    pub sid: i32,
+    pub expected_version: i64,
+    pub actual_version: i64,
+}
+
+impl fmt::Display for VersionConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Company {} is at version {}, not {}; it was changed since it was read",
+               self.sid, self.actual_version, self.expected_version)
+    }
+}
+
+impl std::error::Error for VersionConflict {}
+
+impl CompanyDataStore {
+    /// The company's version, which goes up whenever the company or anything attached to it changes.
+    /// Read it before reading the company, and pass it to update_company.
+    pub async fn get_company_version(&self, sid: &i32) -> Result<i64, Error> {
+        let results = self.postgres_client.query("SELECT version FROM CompanyTable WHERE sid = $1", &[sid]).await?;
+        match results.first() {
+            Some(row) => Ok(row.get(0)),
+            None => bail!("No company with sid {}", sid),
+        }
+    }
+
+    /// Applies the patch to the company in a single transaction, if the company is still at `expected_version`.
+    /// Fails with a VersionConflict if it isn't, in which case nothing is changed, and the caller should
+    /// read the company again and redo its changes. Websites on blocked domains are left out.
+    /// Returns the company's new version.
+    pub async fn update_company(&mut self, sid: &i32, patch: &ProcessedCompanyPatch, expected_version: i64, dry_run: bool) -> Result<i64, Error> {
+        if dry_run {
+            println!("Would update company {} at version {}: {:?}", sid, expected_version, patch);
+            return Ok(expected_version);
+        }
+        let provenance = patch.provenance.clone().unwrap_or_else(Provenance::unknown);
+        let source = provenance.source.as_str();
+        let blocklist = self.get_domain_blocklist().await?;
+        let transaction = self.postgres_client.transaction().await?;
+
+        // locks the company until the patch is applied, so nobody changes it in between
+        let results = transaction.query("SELECT version, deleted_at IS NOT NULL FROM CompanyTable WHERE sid = $1 FOR UPDATE", &[sid]).await?;
+        let row = match results.first() {
+            Some(row) => row,
+            None => bail!("No company with sid {}", sid),
+        };
+        let actual_version: i64 = row.get(0);
+        if row.get::<_, bool>(1) {
+            bail!("Company with sid {} was deleted, restore it before updating it", sid);
+        }
+        if actual_version != expected_version {
+            return Err(VersionConflict { sid: *sid, expected_version, actual_version }.into());
+        }
+
+        for alias in &patch.remove_aliases {
+            transaction.execute("DELETE FROM CompanyAliases WHERE sid = $1 AND CompanyAlias = $2", &[sid, alias]).await?;
+        }
+        for alias in patch.add_aliases.iter().chain(&patch.primary_name) {
+            transaction.execute(
+                "INSERT INTO CompanyAliases (CompanyAlias, sid, source, producer, confidence, recorded_at) \
+                VALUES ($1, $2, $3, $4, $5, COALESCE($6, now())) ON CONFLICT DO NOTHING",
+                &[alias, sid, &source, &provenance.producer, &provenance.confidence, &provenance.recorded_at],
+            ).await?;
+        }
+        if let Some(name) = &patch.primary_name {
+            transaction.execute("UPDATE CompanyAliases SET is_primary = false WHERE sid = $1 AND is_primary AND CompanyAlias <> $2",
+                                &[sid, name]).await?;
+            transaction.execute("UPDATE CompanyAliases SET is_primary = true WHERE sid = $1 AND CompanyAlias = $2", &[sid, name]).await?;
+        }
+        transaction.execute(aliases::PROMOTE_PRIMARY_ALIAS, &[sid]).await?;
+        for tag in &patch.remove_tags {
+            transaction.execute("DELETE FROM CompanyTags WHERE sid = $1 AND tag = $2", &[sid, &tag.to_string()]).await?;
+        }
+        for tag in &patch.add_tags {
+            transaction.execute("INSERT INTO CompanyTags (sid, tag) VALUES ($1, $2) ON CONFLICT DO NOTHING", &[sid, &tag.to_string()]).await?;
+        }
+        for url in &patch.remove_websites {
+            transaction.execute("DELETE FROM CompanyWebsites WHERE sid = $1 AND website_link = $2", &[sid, &canonicalize_url(url)]).await?;
+        }
+        // like website discovery, directories and social networks are never a company's own site
+        for website in &patch.add_websites {
+            if blocklist.is_blocked(&website.url) {
+                println!("Not adding website {} to company {}, its domain is blocked", website.url, sid);
+                continue;
+            }
+            let url = CanonicalUrl::parse(&website.url)?.to_string();
+            let search_rank = website.search_rank.map(|rank| rank as i32);
+            let http_status = website.http_status.map(|status| status as i32);
+            transaction.execute(
+                "INSERT INTO CompanyWebsites (sid, website_title, website_link, role, search_rank, snippet, source_engine, \
+                    discovered_at, http_status, source, producer, confidence, recorded_at) \
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, COALESCE($13, now())) ON CONFLICT DO NOTHING",
+                &[sid, &website.title, &url, &website.role.as_str(), &search_rank, &website.snippet, &website.source_engine,
+                  &website.discovered_at, &http_status, &source, &provenance.producer, &provenance.confidence, &provenance.recorded_at],
+            ).await?;
+        }
+        match &patch.career_page {
+            Some(CareerPageUpdate::Set(career_page)) => {
+                let url = CanonicalUrl::parse(&career_page.url)?.to_string();
+                let ats_type = career_page.ats_type.map(|ats| ats.as_str().to_string());
+                let http_status = career_page.http_status.map(|status| status as i32);
+                transaction.execute("DELETE FROM CompanyCareerPage WHERE sid = $1 AND career_page_link <> $2", &[sid, &url]).await?;
+                transaction.execute(
+                    "INSERT INTO CompanyCareerPage (sid, career_page_link, label, region, ats_type, http_status, \
+                        last_verified_at, is_primary, source, producer, confidence, recorded_at) \
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, true, $8, $9, $10, COALESCE($11, now())) \
+                    ON CONFLICT (sid, career_page_link) DO UPDATE SET label = EXCLUDED.label, region = EXCLUDED.region, \
+                        ats_type = EXCLUDED.ats_type, http_status = COALESCE(EXCLUDED.http_status, CompanyCareerPage.http_status), \
+                        last_verified_at = GREATEST(EXCLUDED.last_verified_at, CompanyCareerPage.last_verified_at), \
+                        is_primary = true, source = EXCLUDED.source, producer = EXCLUDED.producer, \
+                        confidence = EXCLUDED.confidence, recorded_at = EXCLUDED.recorded_at",
+                    &[sid, &url, &career_page.label, &career_page.region, &ats_type, &http_status, &career_page.last_verified_at,
+                      &source, &provenance.producer, &provenance.confidence, &provenance.recorded_at],
+                ).await?;
+            },
+            Some(CareerPageUpdate::Clear) => {
+                transaction.execute("DELETE FROM CompanyCareerPage WHERE sid = $1", &[sid]).await?;
+            },
+            None => {},
+        }
+
+        let results = transaction.query("SELECT version FROM CompanyTable WHERE sid = $1", &[sid]).await?;
+        let version: i64 = results[0].get(0);
+        transaction.commit().await?;
+        println!("Updated company with sid {} to version {}", sid, version);
+        Ok(version)
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt;
+use anyhow::Error;
+use company_common::CanonicalUrl;
+use tokio_postgres::Transaction;
+use crate::CompanyDataStore;
+
+/// What canonicalize_stored_urls changed in one table
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UrlCanonicalizationCount {
+    /// Rows whose URL was rewritten to its canonical form
+    pub rewritten: u64,
+    /// Rows dropped because the company already had the canonical URL
+    pub merged: u64,
+    /// Rows left alone because their URL couldn't be parsed
+    pub invalid: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UrlCanonicalizationReport {
+    pub websites: UrlCanonicalizationCount,
+    pub career_pages: UrlCanonicalizationCount,
+    pub bot_protection: UrlCanonicalizationCount,
+    pub postings: UrlCanonicalizationCount,
+}
+
+impl fmt::Display for UrlCanonicalizationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tables = [("websites", &self.websites), ("career pages", &self.career_pages),
+            ("bot protection", &self.bot_protection), ("postings", &self.postings)];
+        for (name, count) in tables {
+            writeln!(f, "{}: {} rewritten, {} merged, {} invalid", name, count.rewritten, count.merged, count.invalid)?;
+        }
+        Ok(())
+    }
+}
+
+/// The stored URLs of a table that aren't in canonical form, as (sid, stored, canonical),
+/// along with how many couldn't be parsed
+async fn non_canonical_urls(transaction: &Transaction<'_>, query: &str) -> Result<(Vec<(i32, String, String)>, u64), Error> {
+    let mut urls = Vec::new();
+    let mut invalid = 0;
+    for row in transaction.query(query, &[]).await? {
+        let url: String = row.get(1);
+        match CanonicalUrl::parse(&url) {
+            Ok(canonical) if canonical.as_str() != url => urls.push((row.get(0), url, canonical.to_string())),
+            Ok(_) => {},
+            Err(_) => invalid += 1,
+        }
+    }
+    Ok((urls, invalid))
+}
+
+impl CompanyDataStore {
+    /// Rewrites the URLs stored before URLs were canonicalized into their canonical form.
+    /// Where a company ends up with the same URL twice, the row already in canonical form is kept
+    /// (a career page stays primary if either was), and the other is dropped.
+    /// Safe to run again; rows that are already canonical are left alone.
+    pub async fn canonicalize_stored_urls(&mut self, dry_run: bool) -> Result<UrlCanonicalizationReport, Error> {
+        let mut report = UrlCanonicalizationReport::default();
+        let transaction = self.postgres_client.transaction().await?;
+
+        let (urls, invalid) = non_canonical_urls(&transaction, "SELECT sid, website_link FROM CompanyWebsites").await?;
+        report.websites.invalid = invalid;
+        for (sid, url, canonical) in urls {
+            let rewritten = transaction.execute(
+                "UPDATE CompanyWebsites SET website_link = $3 WHERE sid = $1 AND website_link = $2 \
+                 AND NOT EXISTS (SELECT 1 FROM CompanyWebsites w WHERE w.sid = $1 AND w.website_link = $3)",
+                &[&sid, &url, &canonical],
+            ).await?;
+            if rewritten == 0 {
+                transaction.execute("DELETE FROM CompanyWebsites WHERE sid = $1 AND website_link = $2", &[&sid, &url]).await?;
+                report.websites.merged += 1;
+            } else {
+                report.websites.rewritten += 1;
+            }
+        }
+
+        let (urls, invalid) = non_canonical_urls(&transaction, "SELECT sid, career_page_link FROM CompanyCareerPage").await?;
+        report.career_pages.invalid = invalid;
+        for (sid, url, canonical) in urls {
+            let deleted = transaction.query(
+                "DELETE FROM CompanyCareerPage WHERE sid = $1 AND career_page_link = $2 \
+                 AND EXISTS (SELECT 1 FROM CompanyCareerPage p WHERE p.sid = $1 AND p.career_page_link = $3) \
+                 RETURNING is_primary",
+                &[&sid, &url, &canonical],
+            ).await?;
+            match deleted.first() {
+                Some(row) => {
+                    if row.get::<_, bool>(0) {
+                        transaction.execute(
+                            "UPDATE CompanyCareerPage SET is_primary = true WHERE sid = $1 AND career_page_link = $2",
+                            &[&sid, &canonical],
+                        ).await?;
+                    }
+                    report.career_pages.merged += 1;
+                },
+                None => {
+                    transaction.execute(
+                        "UPDATE CompanyCareerPage SET career_page_link = $3 WHERE sid = $1 AND career_page_link = $2",
+                        &[&sid, &url, &canonical],
+                    ).await?;
+                    report.career_pages.rewritten += 1;
+                },
+            }
+        }
+
+        let (urls, invalid) = non_canonical_urls(&transaction, "SELECT DISTINCT sid, url FROM WebsiteBotProtection").await?;
+        report.bot_protection.invalid = invalid;
+        for (sid, url, canonical) in urls {
+            // the same kind of protection may already be recorded on the canonical URL
+            let moved = transaction.execute(
+                "UPDATE WebsiteBotProtection b SET url = $3 WHERE sid = $1 AND url = $2 \
+                 AND NOT EXISTS (SELECT 1 FROM WebsiteBotProtection o WHERE o.sid = $1 AND o.url = $3 AND o.kind = b.kind)",
+                &[&sid, &url, &canonical],
+            ).await?;
+            let merged = transaction.execute("DELETE FROM WebsiteBotProtection WHERE sid = $1 AND url = $2", &[&sid, &url]).await?;
+            report.bot_protection.rewritten += moved;
+            report.bot_protection.merged += merged;
+        }
+
+        // postings are keyed by their external id, so their links never collide
+        let (urls, invalid) = non_canonical_urls(&transaction, "SELECT sid, url FROM JobPostings WHERE url <> ''").await?;
+        report.postings.invalid = invalid;
+        for (sid, url, canonical) in urls {
+            report.postings.rewritten += transaction.execute(
+                "UPDATE JobPostings SET url = $3 WHERE sid = $1 AND url = $2", &[&sid, &url, &canonical],
+            ).await?;
+        }
+
+        if dry_run {
+            println!("Would canonicalize stored URLs:\n{}", report);
+            transaction.rollback().await?;
+        } else {
+            println!("Canonicalized stored URLs:\n{}", report);
+            transaction.commit().await?;
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use anyhow::Error;
+use company_common::{CanonicalUrl, DomainBlocklist, ProcessedCompany, WebsiteRole};
+use serde::Serialize;
+use crate::{CompanyDataStore, CompanyQuery};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Worth a look, but may well be right
+    Info,
+    /// Probably wrong
+    Warning,
+    /// Certainly wrong
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A correction that can be applied to a company without a human looking at it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Fix {
+    RemoveAlias(String),
+    RemoveWebsite(String),
+    RemoveCareerPage(String),
+}
+
+/// Something a rule found wrong with a company
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Violation {
+    pub rule: String,
+    pub severity: Severity,
+    pub sid: i32,
+    /// The alias or URL at fault
+    pub subject: String,
+    pub message: String,
+    /// None if the violation has to be fixed by hand
+    pub fix: Option<Fix>,
+}
+
+/// A check run against every company by CompanyDataStore::validate.
+/// Rules only look at the company they're given, so they can be tested without a database.
+pub trait ValidationRule {
+    /// Identifies the rule in reports, e.g. "blank_alias"
+    fn name(&self) -> &str;
+
+    fn check(&self, sid: i32, company: &ProcessedCompany) -> Vec<Violation>;
+}
+
+/// Aliases that are empty or only whitespace
+pub struct BlankAliasRule;
+
+impl ValidationRule for BlankAliasRule {
+    fn name(&self) -> &str {
+        "blank_alias"
+    }
+
+    fn check(&self, sid: i32, company: &ProcessedCompany) -> Vec<Violation> {
+        company.company_aliases.iter().filter(|alias| alias.trim().is_empty()).map(|alias| Violation {
+            rule: self.name().to_string(),
+            severity: Severity::Error,
+            sid,
+            subject: alias.clone(),
+            message: "alias is blank".to_string(),
+            fix: Some(Fix::RemoveAlias(alias.clone())),
+        }).collect()
+    }
+}
+
+/// Website links that can't be the company's own site: links on a blocked domain (social networks, job boards
+/// and directories, see DomainBlocklist), and documents
+pub struct NonCompanyWebsiteRule {
+    pub blocklist: DomainBlocklist,
+}
+
+impl NonCompanyWebsiteRule {
+    pub fn new(blocklist: DomainBlocklist) -> NonCompanyWebsiteRule {
+        NonCompanyWebsiteRule { blocklist }
+    }
+}
+
+impl ValidationRule for NonCompanyWebsiteRule {
+    fn name(&self) -> &str {
+        "non_company_website"
+    }
+
+    fn check(&self, sid: i32, company: &ProcessedCompany) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        for website in company.websites.iter().flatten() {
+            let message = match self.blocklist.blocked_role(&website.url) {
+                Some(WebsiteRole::Social) => "links to a social media profile",
+                Some(_) => "links to a job board or directory",
+                None if is_document(&website.url) => "links to a PDF",
+                None => continue,
+            };
+            violations.push(Violation {
+                rule: self.name().to_string(),
+                severity: Severity::Warning,
+                sid,
+                subject: website.url.clone(),
+                message: message.to_string(),
+                fix: Some(Fix::RemoveWebsite(website.url.clone())),
+            });
+        }
+        violations
+    }
+}
+
+fn is_document(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.to_lowercase().ends_with(".pdf")
+}
+
+/// Career pages on a registrable domain none of the company's websites are on.
+/// Pages on an applicant tracking system are on the ATS's domain, so they're left alone,
+/// as are companies without websites to compare against.
+pub struct CareerPageDomainRule;
+
+impl ValidationRule for CareerPageDomainRule {
+    fn name(&self) -> &str {
+        "career_page_domain"
+    }
+
+    fn check(&self, sid: i32, company: &ProcessedCompany) -> Vec<Violation> {
+        let sites: Vec<CanonicalUrl> = company.websites.iter().flatten()
+            .filter(|website| matches!(WebsiteRole::guess_from_url(&website.url), WebsiteRole::Unknown
+                | WebsiteRole::OfficialHomepage | WebsiteRole::Careers))
+            .filter_map(|website| CanonicalUrl::parse(&website.url).ok())
+            .collect();
+        if sites.is_empty() {
+            return Vec::new();
+        }
+        company.career_pages.iter().flatten()
+            .filter(|career_page| career_page.ats_type.is_none())
+            .filter(|career_page| match CanonicalUrl::parse(&career_page.url) {
+                Ok(url) => !sites.iter().any(|site| site.same_site(&url)),
+                Err(_) => true,
+            })
+            .map(|career_page| Violation {
+                rule: self.name().to_string(),
+                severity: Severity::Warning,
+                sid,
+                subject: career_page.url.clone(),
+                message: "career page isn't on any of the company's websites' domains".to_string(),
+                fix: None,
+            })
+            .collect()
+    }
+}
+
+/// The usual rules, checking websites against the built in blocklist.
+/// Use rules_with_blocklist to check against the maintained one (CompanyDataStore::get_domain_blocklist).
+pub fn default_rules() -> Vec<Box<dyn ValidationRule>> {
+    rules_with_blocklist(DomainBlocklist::default())
+}
+
+pub fn rules_with_blocklist(blocklist: DomainBlocklist) -> Vec<Box<dyn ValidationRule>> {
+    vec![Box::new(BlankAliasRule), Box::new(NonCompanyWebsiteRule::new(blocklist)), Box::new(CareerPageDomainRule)]
+}
+
+/// Runs the rules against a single company
+pub fn validate_company(rules: &[Box<dyn ValidationRule>], sid: i32, company: &ProcessedCompany) -> Vec<Violation> {
+    rules.iter().flat_map(|rule| rule.check(sid, company)).collect()
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ValidationReport {
+    pub companies_checked: u64,
+    pub violations: Vec<Violation>,
+    /// How many of the violations were fixed; always 0 unless fixing was asked for
+    pub fixed: u64,
+}
+
+impl ValidationReport {
+    /// The violations grouped by rule, then by severity
+    pub fn by_rule_and_severity(&self) -> BTreeMap<(&str, Severity), Vec<&Violation>> {
+        let mut groups: BTreeMap<(&str, Severity), Vec<&Violation>> = BTreeMap::new();
+        for violation in &self.violations {
+            groups.entry((violation.rule.as_str(), violation.severity)).or_default().push(violation);
+        }
+        groups
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Checked {} companies: {} violations, {} fixed",
+                 self.companies_checked, self.violations.len(), self.fixed)?;
+        for ((rule, severity), violations) in self.by_rule_and_severity() {
+            writeln!(f, "  {} ({}): {}", rule, severity, violations.len())?;
+            for violation in violations {
+                let fixable = if violation.fix.is_some() { " [fixable]" } else { "" };
+                writeln!(f, "    {} {:?}: {}{}", violation.sid, violation.subject, violation.message, fixable)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CompanyDataStore {
+    /// Runs the rules against every company matching the query, and reports what they find.
+    /// @param fix: if true, applies the fixes of the violations that have one; the rest are only reported
+    pub async fn validate(&mut self, query: &CompanyQuery, rules: &[Box<dyn ValidationRule>], fix: bool) -> Result<ValidationReport, Error> {
+        let mut report = ValidationReport::default();
+        for sid in self.query_sids(query).await? {
+            let company = self.construct_processed_company_from_sid(&sid).await?;
+            report.companies_checked += 1;
+            report.violations.extend(validate_company(rules, sid, &company));
+        }
+        if fix {
+            for violation in &report.violations {
+                match &violation.fix {
+                    Some(Fix::RemoveAlias(alias)) => self.remove_alias(&violation.sid, alias, false).await?,
+                    Some(Fix::RemoveWebsite(url)) => self.remove_website(&violation.sid, url, false).await?,
+                    Some(Fix::RemoveCareerPage(url)) => self.remove_career_page(&violation.sid, url, false).await?,
+                    None => continue,
+                }
+                report.fixed += 1;
+            }
+        }
+        println!("Validated {} companies: {} violations, {} fixed", report.companies_checked, report.violations.len(), report.fixed);
+        Ok(report)
+    }
+}
@@ -0,0 +1,454 @@
+#[cfg(test)]
+mod company_query_tests {
+    use company_data_store::*;
+
+    #[test]
+    fn empty_query_test() {
+        let (sql, params) = CompanyQuery::new().to_sql();
+        assert_eq!(sql, "SELECT c.sid FROM CompanyTable c WHERE c.deleted_at IS NULL ORDER BY c.sid ASC, c.sid");
+        assert!(params.is_empty());
+    }
+
+    #[test]
+    fn include_deleted_test() {
+        let (sql, _) = CompanyQuery::new().include_deleted(true).to_sql();
+        assert!(!sql.contains("deleted_at"));
+    }
+
+    #[test]
+    fn user_input_is_parameterized_test() {
+        let malicious = "'; DROP TABLE CompanyTable; --";
+        let (sql, params) = CompanyQuery::new()
+            .alias_contains(malicious)
+            .alias_contains_none(vec![malicious])
+            .with_tag(malicious)
+            .to_sql();
+        assert!(!sql.contains(malicious));
+        assert!(!sql.contains("DROP"));
+        assert_eq!(params.len(), 3);
+    }
+
+    #[test]
+    fn filters_limit_and_offset_test() {
+        let (sql, params) = CompanyQuery::new()
+            .has_cik(true)
+            .has_website(false)
+            .discovery_status(DiscoveryStatus::Pending)
+            .sort_by(CompanySort::Alias, SortDirection::Descending)
+            .limit(10)
+            .offset(20)
+            .to_sql();
+        assert!(sql.contains("EXISTS (SELECT 1 FROM CikToSid"));
+        assert!(sql.contains("NOT EXISTS (SELECT 1 FROM CompanyWebsites"));
+        assert!(sql.contains("DESC"));
+        assert!(sql.contains("EXISTS (SELECT 1 FROM CompanyDiscovery"));
+        assert!(sql.ends_with("LIMIT $2 OFFSET $3"));
+        assert_eq!(params.len(), 3);
+    }
+
+    #[test]
+    fn tag_namespace_test() {
+        let (sql, params) = CompanyQuery::new()
+            .with_tag_in_namespace(&company_common::TagNamespace::User("50%_off".to_string()))
+            .to_sql();
+        assert!(sql.contains("t.tag LIKE $1"));
+        assert!(!sql.contains("50%"));
+        assert_eq!(params.len(), 1);
+    }
+
+    #[test]
+    fn profile_scope_test() {
+        let (sql, params) = CompanyQuery::new().shortlisted_by(1).not_excluded_by(1).to_sql();
+        assert!(sql.contains("s.profile_id = $1"));
+        assert!(sql.contains("cp.profile_id = $2 AND c.sid = ANY(cp.excluded_sids)"));
+        assert_eq!(params.len(), 2);
+    }
+
+    #[test]
+    fn inherit_career_pages_test() {
+        let (sql, _) = CompanyQuery::new().has_career_page(false).to_sql();
+        assert!(!sql.contains("hierarchy"));
+        let (sql, params) = CompanyQuery::new().has_career_page(false).inherit_career_pages(true).to_sql();
+        assert!(sql.contains("NOT EXISTS (SELECT 1 FROM CompanyCareerPage p WHERE p.sid = c.sid OR p.sid IN (WITH RECURSIVE hierarchy"));
+        assert!(sql.contains("r.child_sid = c.sid"));
+        assert!(params.is_empty());
+    }
+
+    #[test]
+    fn escape_like_test() {
+        assert_eq!(escape_like("100%_\\"), "100\\%\\_\\\\");
+    }
+}
+
+#[cfg(test)]
+mod discovery_status_tests {
+    use std::str::FromStr;
+    use company_data_store::*;
+
+    #[test]
+    fn status_round_trip_test() {
+        for status in DiscoveryStatus::all() {
+            assert_eq!(DiscoveryStatus::from_str(status.as_str()).unwrap(), status);
+        }
+        assert!(DiscoveryStatus::from_str("bogus").is_err());
+    }
+}
+
+#[cfg(test)]
+mod posting_tests {
+    use std::str::FromStr;
+    use company_data_store::*;
+
+    #[test]
+    fn posting_status_round_trip_test() {
+        for status in [PostingStatus::Open, PostingStatus::Closed] {
+            assert_eq!(PostingStatus::from_str(status.as_str()).unwrap(), status);
+        }
+        assert!(PostingStatus::from_str("bogus").is_err());
+    }
+}
+
+#[cfg(test)]
+mod change_event_tests {
+    use company_data_store::*;
+
+    #[test]
+    fn parse_notification_payload_test() {
+        let payload = r#"{"table" : "career_page", "operation" : "delete", "sid" : 42}"#;
+        let event: ChangeEvent = serde_json::from_str(payload).unwrap();
+        assert_eq!(event, ChangeEvent {
+            table: ChangeTable::CareerPage,
+            operation: ChangeOperation::Delete,
+            sid: 42,
+        });
+
+        let payload = r#"{"table" : "posting", "operation" : "update", "sid" : 7}"#;
+        let event: ChangeEvent = serde_json::from_str(payload).unwrap();
+        assert_eq!(event.table, ChangeTable::Posting);
+    }
+}
+
+#[cfg(test)]
+mod snapshot_tests {
+    use serde_json::{json, Value};
+    use company_data_store::*;
+
+    fn change(change_id: i64, table: ChangeTable, sid: i32, key: &str, old_row: Option<Value>, new_row: Option<Value>) -> ChangeLogEntry {
+        let operation = match (&old_row, &new_row) {
+            (None, _) => ChangeOperation::Insert,
+            (_, None) => ChangeOperation::Delete,
+            _ => ChangeOperation::Update,
+        };
+        ChangeLogEntry { change_id, table, operation, sid, key: key.to_string(), old_row, new_row }
+    }
+
+    #[test]
+    fn diff_changes_test() {
+        let page = |status: i32, verified_at: &str| json!({"sid": 1, "career_page_link": "https://a.com/jobs", "http_status": status, "verified_at": verified_at});
+        let changes = vec![
+            change(1, ChangeTable::Company, 1, "1", None, Some(json!({"sid": 1, "deleted_at": null}))),
+            change(2, ChangeTable::Website, 1, "https://a.com", None, Some(json!({"sid": 1}))),
+            // added and removed again in between
+            change(3, ChangeTable::Website, 1, "https://b.com", None, Some(json!({"sid": 1}))),
+            change(4, ChangeTable::Website, 1, "https://b.com", Some(json!({"sid": 1})), None),
+            // only re-verified
+            change(5, ChangeTable::CareerPage, 2, "https://a.com/jobs", Some(page(200, "a")), Some(page(200, "b"))),
+            change(6, ChangeTable::CareerPage, 3, "https://a.com/jobs", Some(page(200, "a")), Some(page(404, "b"))),
+            change(7, ChangeTable::Tag, 2, "industry:fintech", Some(json!({"sid": 2})), None),
+            change(8, ChangeTable::Company, 2, "2", Some(json!({"sid": 2, "deleted_at": null})), Some(json!({"sid": 2, "deleted_at": "2024-01-01"}))),
+        ];
+        let diff = diff_changes(&changes);
+        assert_eq!(diff.companies_added, vec![1]);
+        assert_eq!(diff.companies_removed, vec![2]);
+        assert_eq!(diff.websites_added, vec![CompanyItem { sid: 1, value: "https://a.com".to_string() }]);
+        assert!(diff.websites_removed.is_empty());
+        assert_eq!(diff.career_pages_changed, vec![CompanyItem { sid: 3, value: "https://a.com/jobs".to_string() }]);
+        assert_eq!(diff.tags_removed, vec![CompanyItem { sid: 2, value: "industry:fintech".to_string() }]);
+        assert!(diff.to_string().contains("career pages: 0 added, 0 removed, 1 changed"));
+        assert!(diff_changes(&[]).is_empty());
+    }
+}
+
+#[cfg(test)]
+mod export_tests {
+    use std::collections::HashSet;
+    use chrono::{TimeZone, Utc};
+    use company_common::{AliasKind, AliasMetadata, BotProtection, BotProtectionKind, CareerPage, FactSource, ProcessedCompany, Provenance, Website, WebsiteRole};
+    use company_data_store::*;
+
+    fn sample_companies() -> Vec<ProcessedCompany> {
+        let mut provenance = Provenance::new(FactSource::Sec, "company_scraper/0.0.0", 1.0);
+        provenance.recorded_at = Some(Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap());
+        let mut apple = ProcessedCompany::new(
+            Some(320193),
+            HashSet::new(),
+            Some(vec![Website {
+                title: "Apple".to_string(),
+                url: "https://www.apple.com".to_string(),
+                role: WebsiteRole::OfficialHomepage,
+                search_rank: Some(1),
+                snippet: Some("Discover the innovative world of Apple".to_string()),
+                source_engine: Some("google".to_string()),
+                discovered_at: Some(Utc.with_ymd_and_hms(2024, 6, 2, 8, 0, 0).unwrap()),
+                http_status: Some(200),
+                last_verified_at: Some(Utc.with_ymd_and_hms(2024, 6, 3, 9, 0, 0).unwrap()),
+                last_changed_at: None,
+            }]),
+            Some(vec![
+                CareerPage {
+                    url: "https://jobs.apple.com".to_string(),
+                    label: None,
+                    region: Some("US".to_string()),
+                    ats_type: None,
+                    http_status: Some(200),
+                    last_verified_at: Some(Utc.with_ymd_and_hms(2024, 6, 3, 9, 0, 0).unwrap()),
+                    last_changed_at: None,
+                    is_primary: true,
+                },
+                CareerPage::new("https://boards.greenhouse.io/beats"),
+            ]),
+            Some(vec!["tech".to_string()]),
+        );
+        let mut recaptcha = BotProtection::new(BotProtectionKind::Recaptcha, Some("g-recaptcha div".to_string()));
+        recaptcha.detected_at = Some(Utc.with_ymd_and_hms(2024, 6, 2, 8, 30, 0).unwrap());
+        apple.add_bot_protection("https://jobs.apple.com", recaptcha);
+        apple.add_alias("APPLE INC".to_string(), provenance);
+        apple.set_alias_metadata("APPLE COMPUTER INC", AliasMetadata::former(None, chrono::NaiveDate::from_ymd_opt(2007, 1, 9).unwrap()));
+        apple.set_alias_metadata("APPLE INC", AliasMetadata::new(AliasKind::Legal));
+        apple.set_primary_name("APPLE INC");
+        apple.career_page_provenance.insert("https://boards.greenhouse.io/beats".to_string(),
+                                            Provenance::new(FactSource::Manual, "user", 0.9));
+        apple.website_provenance.insert("https://www.apple.com".to_string(),
+                                        Provenance::new(FactSource::Serp, "website_discovery/0.1.0", 0.5));
+        let mut no_cik = ProcessedCompany::new(None, HashSet::new(), None, None, None);
+        no_cik.company_aliases.insert("Some Startup".to_string());
+        vec![apple, no_cik]
+    }
+
+    #[test]
+    fn round_trip_test() {
+        let companies = sample_companies();
+        for (format, extension) in [(ExportFormat::JsonLines, "jsonl"), (ExportFormat::Csv, "csv"), (ExportFormat::Parquet, "parquet")] {
+            let path = std::env::temp_dir().join(format!("company_export_round_trip_test.{}", extension));
+            assert_eq!(ExportFormat::from_path(&path).unwrap(), format);
+            write_companies(&path, format, &companies).unwrap();
+            let read = read_companies(&path, format).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(read.len(), companies.len());
+            for (expected, actual) in companies.iter().zip(read.iter()) {
+                assert_eq!(expected.cik, actual.cik);
+                assert_eq!(expected.company_aliases, actual.company_aliases);
+                assert_eq!(expected.websites, actual.websites);
+                assert_eq!(expected.career_pages, actual.career_pages);
+                assert_eq!(expected.career_page_provenance, actual.career_page_provenance);
+                assert_eq!(expected.tags, actual.tags);
+                assert_eq!(expected.bot_protection, actual.bot_protection);
+                assert_eq!(expected.alias_provenance, actual.alias_provenance);
+                assert_eq!(expected.alias_metadata, actual.alias_metadata);
+                assert_eq!(expected.primary_name, actual.primary_name);
+                assert_eq!(expected.website_provenance, actual.website_provenance);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod search_tests {
+    use std::collections::HashSet;
+    use company_common::ProcessedCompany;
+    use company_data_store::*;
+
+    fn company(aliases: &[&str]) -> ProcessedCompany {
+        let aliases: HashSet<String> = aliases.iter().map(|alias| alias.to_string()).collect();
+        ProcessedCompany::new(None, aliases, None, None, None)
+    }
+
+    #[test]
+    fn search_companies_in_test() {
+        let companies = [
+            (1, company(&["APPLE INC", "Apple Computer, Inc."])),
+            (2, company(&["Applied Materials Inc"])),
+            (3, company(&["Microsoft Corp"])),
+        ];
+        let results = search_companies_in(companies.iter().map(|(sid, company)| (*sid, company)), "apple", 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0], CompanySearchResult { sid: 1, alias: "APPLE INC".to_string(), similarity: 0.6 });
+
+        let results = search_companies_in(companies.iter().map(|(sid, company)| (*sid, company)), "applied materials", 10);
+        assert_eq!(results[0].sid, 2);
+        assert!(search_companies_in(companies.iter().map(|(sid, company)| (*sid, company)), "apple", 0).is_empty());
+    }
+}
+
+#[cfg(test)]
+mod validation_tests {
+    use std::collections::HashSet;
+    use company_common::{CareerPage, DomainBlocklist, ProcessedCompany, Website, WebsiteRole};
+    use company_data_store::*;
+
+    fn company(aliases: &[&str], websites: &[&str], career_pages: &[&str]) -> ProcessedCompany {
+        let aliases: HashSet<String> = aliases.iter().map(|alias| alias.to_string()).collect();
+        let websites = websites.iter().map(|url| Website::new("", url)).collect();
+        let career_pages = career_pages.iter().map(|url| CareerPage::new(url)).collect();
+        ProcessedCompany::new(None, aliases, Some(websites), Some(career_pages), None)
+    }
+
+    #[test]
+    fn default_rules_test() {
+        let rules = default_rules();
+        let clean = company(&["Acme Inc"], &["https://www.acme.com"], &["https://careers.acme.com", "https://boards.greenhouse.io/acme"]);
+        assert!(validate_company(&rules, 1, &clean).is_empty());
+
+        let dirty = company(&["Acme Inc", "  "], &["https://acme.com", "https://www.linkedin.com/company/acme", "https://acme.com/10-K.PDF?x=1"],
+                            &["https://jobs.example.org"]);
+        let violations = validate_company(&rules, 2, &dirty);
+        let found: Vec<(&str, Severity, &str)> = violations.iter()
+            .map(|violation| (violation.rule.as_str(), violation.severity, violation.subject.as_str())).collect();
+        assert_eq!(found.len(), 4);
+        assert!(found.contains(&("blank_alias", Severity::Error, "  ")));
+        assert!(found.contains(&("non_company_website", Severity::Warning, "https://www.linkedin.com/company/acme")));
+        assert!(found.contains(&("non_company_website", Severity::Warning, "https://acme.com/10-K.PDF?x=1")));
+        assert!(found.contains(&("career_page_domain", Severity::Warning, "https://jobs.example.org")));
+
+        let report = ValidationReport { companies_checked: 2, violations, fixed: 0 };
+        let groups = report.by_rule_and_severity();
+        assert_eq!(groups[&("non_company_website", Severity::Warning)].len(), 2);
+        assert!(groups[&("career_page_domain", Severity::Warning)][0].fix.is_none());
+    }
+
+    #[test]
+    fn maintained_blocklist_test() {
+        let mut blocklist = DomainBlocklist::empty();
+        blocklist.add("acme-directory.com", WebsiteRole::Aggregator).unwrap();
+        let rules = rules_with_blocklist(blocklist);
+        let listed = company(&["Acme Inc"], &["https://acme.com", "https://www.linkedin.com/company/acme", "https://acme-directory.com/acme"], &[]);
+        let violations = validate_company(&rules, 1, &listed);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].subject, "https://acme-directory.com/acme");
+        assert_eq!(violations[0].message, "links to a job board or directory");
+    }
+
+    #[test]
+    fn related_groups_test() {
+        // 2 is a subsidiary of 1, which was acquired by 5; 3 and 4 are on their own
+        let relationships = [(2, 1), (1, 5), (6, 7)];
+        let groups = related_groups(&[1, 2, 3, 4, 2], &relationships);
+        assert_eq!(groups, vec![vec![1, 2], vec![3], vec![4]]);
+        assert_eq!(related_groups(&[2, 5], &relationships).len(), 1);
+        assert!(related_groups(&[], &relationships).is_empty());
+    }
+}
+
+#[cfg(test)]
+mod freshness_tests {
+    use std::time::Duration;
+    use chrono::{TimeZone, Utc};
+    use company_data_store::*;
+
+    #[test]
+    fn refresh_policy_test() {
+        let policies = RefreshPolicies::default();
+        let career_pages = policies.for_target(VerificationTarget::CareerPage);
+        let checked = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        assert_eq!(career_pages.next_check(Some(checked), Some(200)), Some(Utc.with_ymd_and_hms(2024, 6, 15, 12, 0, 0).unwrap()));
+        assert_eq!(career_pages.next_check(Some(checked), Some(404)), Some(Utc.with_ymd_and_hms(2024, 6, 2, 12, 0, 0).unwrap()));
+        // never checked, so due straight away
+        assert_eq!(career_pages.next_check(None, None), None);
+
+        let hourly = RefreshPolicy::new(Duration::from_secs(60 * 60), Duration::from_secs(60));
+        assert_eq!(hourly.next_check(Some(checked), None), Some(Utc.with_ymd_and_hms(2024, 6, 1, 13, 0, 0).unwrap()));
+        assert_eq!(policies.for_target(VerificationTarget::Website).interval, Duration::from_secs(30 * 24 * 60 * 60));
+    }
+}
+
+#[cfg(test)]
+mod prune_tests {
+    use std::collections::HashSet;
+    use company_common::ProcessedCompany;
+    use company_data_store::*;
+
+    fn company(name: &str) -> ProcessedCompany {
+        let aliases: HashSet<String> = [name.to_string()].into_iter().collect();
+        ProcessedCompany::new(None, aliases, None, None, None)
+    }
+
+    #[test]
+    fn prune_report_test() {
+        let report = PruneReport {
+            dry_run: true,
+            companies: 3,
+            aliases: 4,
+            tags: 1,
+            websites: 2,
+            career_pages: 0,
+            postings: 5,
+            applications: 0,
+            shortlisted: 0,
+            sample: vec![(7, company("Acme")), (9, ProcessedCompany::new(None, HashSet::new(), None, None, None))],
+        };
+        assert!(!report.has_user_data());
+        assert_eq!(report.to_string(), "Would prune 3 companies (4 aliases, 1 tags, 2 websites, 0 career pages, 5 job postings)\n  \
+            sid 7: Acme\n  sid 9: <no name>\n  ... and 1 more\n");
+
+        let report = PruneReport { dry_run: false, companies: 1, sample: vec![(7, company("Acme"))], ..Default::default() };
+        assert_eq!(report.to_string(), "Pruned 1 companies (0 aliases, 0 tags, 0 websites, 0 career pages, 0 job postings)\n  sid 7: Acme\n");
+
+        let report = PruneReport { dry_run: true, companies: 1, applications: 2, shortlisted: 1, ..Default::default() };
+        assert!(report.has_user_data());
+        assert_eq!(report.to_string(), "Would prune 1 companies (0 aliases, 0 tags, 0 websites, 0 career pages, 0 job postings)\n  \
+            refused: 2 applications and 1 shortlist entries would be lost\n  ... and 1 more\n");
+    }
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use chrono::NaiveDate;
+    use company_data_store::*;
+
+    fn stats() -> DataStoreStats {
+        DataStoreStats {
+            tables: vec![TableRowCount { table: "CompanyTable".to_string(), rows: 3 }],
+            coverage: CoverageStats {
+                companies: 2,
+                deleted_companies: 1,
+                with_websites: 1,
+                without_websites: 1,
+                with_career_page: 0,
+                without_career_page: 2,
+            },
+            discovery: vec![DiscoveryStatusCount { status: "pending".to_string(), companies: 2 }],
+            bot_protection: BotProtectionStats {
+                websites: 4,
+                protected_urls: 1,
+                protected_companies: 1,
+                by_kind: vec![BotProtectionCount { kind: "recaptcha".to_string(), urls: 1, companies: 1 }],
+            },
+            tags: vec![TagCount { tag: "industry:tech".to_string(), companies: 2 }],
+            growth: vec![DailyGrowth { day: NaiveDate::from_ymd_opt(2024, 6, 1).unwrap(), companies_added: 2, websites_added: 4 }],
+        }
+    }
+
+    #[test]
+    fn stats_display_test() {
+        let printed = stats().to_string();
+        let lines: Vec<&str> = printed.lines().collect();
+        assert_eq!(lines[0], "Table rows");
+        assert_eq!(lines[1], format!("  {:<24}{:>12}", "CompanyTable", 3));
+        assert!(lines.contains(&"Coverage"));
+        assert!(lines.contains(&format!("  {:<24}{:>12}", "deleted companies", 1).as_str()));
+        assert!(lines.contains(&format!("  {:<24}{:>12}{:>12}", "recaptcha", 1, 1).as_str()));
+        assert!(lines.contains(&format!("  {:<24}{:>12}", "industry:tech", 2).as_str()));
+        assert!(lines.contains(&format!("Growth (last {} days)", STATS_GROWTH_DAYS).as_str()));
+        assert_eq!(*lines.last().unwrap(), format!("  {:<12}{:>12}{:>12}", "2024-06-01", 2, 4));
+    }
+
+    #[test]
+    fn stats_json_test() {
+        let json = serde_json::to_value(stats()).unwrap();
+        assert_eq!(json["coverage"]["without_career_page"], 2);
+        assert_eq!(json["discovery"][0]["status"], "pending");
+        assert_eq!(json["bot_protection"]["by_kind"][0]["kind"], "recaptcha");
+        assert_eq!(json["growth"][0]["day"], "2024-06-01");
+    }
+}
@@ -7,8 +7,8 @@ use reqwest::header;
 use chrono;
 use chrono::{Datelike, Utc};
 use serde_json;
-use company_data_store::CompanyDataStore;
-use company_common::{Company, ProcessedCompany};
+use company_data_store::{CompanyDataStore, CompanyQuery};
+use company_common::{AliasKind, AliasMetadata, Company, FactSource, ProcessedCompany, Provenance};
 
 /// This function gets the date of the company.idx file
 /// The relevant part of the header for this file is 2lines long, and contains:
@@ -77,6 +77,11 @@ pub fn get_companies_from_idx() -> Result<Vec<Company>, Box<dyn Error>> {
     Ok(all_companies)
 }
 
+/// Provenance of the facts taken from the SEC's company.idx
+fn sec_provenance() -> Provenance {
+    Provenance::new(FactSource::Sec, concat!("company_scraper/", env!("CARGO_PKG_VERSION")), 1.0)
+}
+
 pub async fn process_raw_sec_data(companies: Vec<Company>, dry_run: bool) -> Result<CompanyDataStore, Box<dyn Error>> {
     let mut data_store = CompanyDataStore::new().await?;
     for company in companies {
@@ -90,11 +95,19 @@ pub async fn process_raw_sec_data(companies: Vec<Company>, dry_run: bool) -> Res
         println!("Processing company: {}", company.name);
         // check if cik already exists, if so, add the alias
         if let Some(sid) = data_store.cik_exists(&cik).await? {
+            // deleted companies stay deleted until someone restores them
+            if data_store.is_tombstoned(&sid).await? {
+                println!("Company with CIK {} was deleted, skipping", cik);
+                continue;
+            }
             println!("Company with CIK {} already exists, adding alias", company.cik.unwrap());
-            let result = data_store.add_alias(&sid, &company.name, dry_run).await;
+            let result = data_store.add_alias(&sid, &company.name, &sec_provenance(), dry_run).await;
             match result {
                 Ok(_) => {
                     println!("Successfully added alias");
+                    if let Err(e) = record_sec_name(&mut data_store, &sid, &company, dry_run).await {
+                        println!("Error: {:?}", e);
+                    }
                 },
                 Err(e) => {
                     println!("Error: {:?}", e);
@@ -109,29 +122,50 @@ pub async fn process_raw_sec_data(companies: Vec<Company>, dry_run: bool) -> Res
             None,
             None,
             None,
-            None,
         );
-        processed_company.company_aliases.insert(company.name.clone());
+        processed_company.add_alias(company.name.clone(), sec_provenance());
+        processed_company.set_alias_metadata(&company.name, AliasMetadata {
+            kind: AliasKind::Legal,
+            valid_from: company.filing_date(),
+            valid_to: None,
+        });
+        processed_company.set_primary_name(&company.name);
         data_store.add_company(processed_company, dry_run).await?;
     }
     Ok(data_store)
 }
 
+/// Records when a company filed under a name it didn't have before. Companies file under their legal name
+/// at the time, so a name from a newer filing than the primary name's is a rename, and one from an older filing a former name.
+async fn record_sec_name(data_store: &mut CompanyDataStore, sid: &i32, company: &Company, dry_run: bool) -> Result<(), Box<dyn Error>> {
+    let filed = match company.filing_date() {
+        Some(filed) => filed,
+        None => return Ok(()),
+    };
+    let metadata = data_store.get_alias_metadata_from_sid(sid).await?;
+    let primary_since = data_store.get_primary_name_from_sid(sid).await?
+        .and_then(|name| metadata.get(&name).and_then(|metadata| metadata.valid_from));
+    match primary_since {
+        Some(since) if filed < since => {
+            data_store.set_alias_metadata(sid, &company.name, &AliasMetadata::former(Some(filed), since), dry_run).await?;
+        },
+        _ => {
+            println!("Company with sid {} renamed to {} as of {}", sid, company.name, filed);
+            data_store.rename_company(sid, &company.name, Some(filed), dry_run).await?;
+        },
+    }
+    Ok(())
+}
+
 /// This function filters the data based on the filter strings.
 /// Returns a vector of ProcessedCompany structs that contain the filter strings.
-/// Could have used a regex here, but the filter strings are simple enough that it's not necessary.
+/// Matching is case insensitive and done by the database, so only the matches are loaded.
 pub async fn filter_data(data_store: &mut CompanyDataStore, filter: Vec<&str>) -> Result<Vec<ProcessedCompany>, Box<dyn Error>> {
-    let mut filtered_data = vec![];
-    // convert all filter strings to lowercase
-    let filter: Vec<String> = filter.iter().map(|&x| x.to_lowercase()).collect();
-    for company in data_store.get_companies().await? {
-        if filter.iter().any(|filter|
-            company.company_aliases.iter().any(|alias|
-                alias.to_lowercase().contains(filter.as_str()))) {
-            filtered_data.push(company.clone());
-        }
+    if filter.is_empty() {
+        return Ok(vec![]);
     }
-    Ok(filtered_data)
+    let query = CompanyQuery::new().alias_contains_any(filter);
+    Ok(data_store.query_companies(&query).await?)
 }
 
 // This function downloads the master list of companies from the SEC
@@ -70,7 +70,17 @@ async fn main() {
         "company",
         "bank"
     ];
-    let res = good_data_store.filter_companies_alias(filter).await;
+    // preview what the filter would remove before actually removing it
+    match good_data_store.filter_companies_alias(filter.clone(), true).await {
+        Ok(report) => {
+            println!("{}", report);
+        },
+        Err(e) => {
+            println!("Error: {:?}", e);
+            return;
+        }
+    }
+    let res = good_data_store.filter_companies_alias(filter, dry_run).await;
     match res {
         Ok(_) => {
             println!("Successfully filtered data");
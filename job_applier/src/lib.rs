@@ -1,14 +1,378 @@
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
+extern crate company_data_store;
+extern crate tokio_postgres;
+extern crate anyhow;
+
+mod status;
+
+pub use status::*;
+
+use std::fmt;
+use std::str::FromStr;
+use anyhow::{bail, Error};
+use chrono::{DateTime, Utc};
+use company_data_store::establish_connection;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::{Client, Row};
+
+pub enum ApplicationTables {
+    Applications,
+    ApplicationTransitions,
+    ApplicationNotes,
+    ApplicationDocuments,
+}
+
+impl ApplicationTables {
+    /// Every table, in the order they need to be created in
+    pub fn all() -> Vec<ApplicationTables> {
+        vec![
+            ApplicationTables::Applications,
+            ApplicationTables::ApplicationTransitions,
+            ApplicationTables::ApplicationNotes,
+            ApplicationTables::ApplicationDocuments,
+        ]
+    }
+
+    fn as_sql(&self) -> &str {
+        match self {
+            ApplicationTables::Applications => {
+                "application_id BIGSERIAL PRIMARY KEY, \
+                profile_id INTEGER NOT NULL REFERENCES CandidateProfiles(profile_id) ON DELETE CASCADE, \
+                posting_id BIGINT NOT NULL REFERENCES JobPostings(posting_id) ON DELETE CASCADE, \
+                status VARCHAR(32) NOT NULL DEFAULT 'discovered', \
+                created_at TIMESTAMPTZ NOT NULL DEFAULT now(), updated_at TIMESTAMPTZ NOT NULL DEFAULT now(), \
+                UNIQUE (profile_id, posting_id)"
+            },
+            ApplicationTables::ApplicationTransitions => {
+                "transition_id BIGSERIAL PRIMARY KEY, \
+                application_id BIGINT NOT NULL REFERENCES Applications(application_id) ON DELETE CASCADE, \
+                from_status VARCHAR(32), to_status VARCHAR(32) NOT NULL, \
+                transitioned_at TIMESTAMPTZ NOT NULL DEFAULT now(), note TEXT"
+            },
+            ApplicationTables::ApplicationNotes => {
+                "note_id BIGSERIAL PRIMARY KEY, \
+                application_id BIGINT NOT NULL REFERENCES Applications(application_id) ON DELETE CASCADE, \
+                body TEXT NOT NULL, created_at TIMESTAMPTZ NOT NULL DEFAULT now()"
+            },
+            ApplicationTables::ApplicationDocuments => {
+                "document_id BIGSERIAL PRIMARY KEY, \
+                application_id BIGINT NOT NULL REFERENCES Applications(application_id) ON DELETE CASCADE, \
+                kind VARCHAR(32) NOT NULL, name TEXT NOT NULL, location TEXT NOT NULL, \
+                attached_at TIMESTAMPTZ NOT NULL DEFAULT now()"
+            },
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        match self {
+            ApplicationTables::Applications => "Applications",
+            ApplicationTables::ApplicationTransitions => "ApplicationTransitions",
+            ApplicationTables::ApplicationNotes => "ApplicationNotes",
+            ApplicationTables::ApplicationDocuments => "ApplicationDocuments",
+        }
+    }
+}
+
+/// Brings tables created by earlier versions up to date. Each statement has to be safe to run more than once.
+const MIGRATIONS: &[&str] = &[
+    // applications used to be shared by everyone; they now belong to a profile, and any from before
+    // go to a "default" one
+    "DO $$ BEGIN
+        IF NOT EXISTS (SELECT 1 FROM information_schema.columns
+                WHERE table_name = 'applications' AND column_name = 'profile_id') THEN
+            ALTER TABLE Applications ADD COLUMN profile_id INTEGER REFERENCES CandidateProfiles(profile_id) ON DELETE CASCADE;
+            IF EXISTS (SELECT 1 FROM Applications) THEN
+                INSERT INTO CandidateProfiles (handle) VALUES ('default') ON CONFLICT (handle) DO NOTHING;
+                UPDATE Applications SET profile_id = (SELECT profile_id FROM CandidateProfiles WHERE handle = 'default');
+            END IF;
+            ALTER TABLE Applications ALTER COLUMN profile_id SET NOT NULL;
+            ALTER TABLE Applications DROP CONSTRAINT IF EXISTS applications_posting_id_key;
+            ALTER TABLE Applications ADD CONSTRAINT applications_profile_id_posting_id_key UNIQUE (profile_id, posting_id);
+        END IF;
+    END $$",
+];
+
+/// What kind of document is attached to an application
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DocumentKind {
+    Resume,
+    CoverLetter,
+    Other,
+}
+
+impl DocumentKind {
+    pub fn as_str(&self) -> &str {
+        match self {
+            DocumentKind::Resume => "resume",
+            DocumentKind::CoverLetter => "cover_letter",
+            DocumentKind::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for DocumentKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for DocumentKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "resume" => Ok(DocumentKind::Resume),
+            "cover_letter" => Ok(DocumentKind::CoverLetter),
+            "other" => Ok(DocumentKind::Other),
+            _ => bail!("Unknown document kind: {}", s),
+        }
+    }
+}
+
+/// An application, along with the posting it's for
+#[derive(Debug, Clone, Serialize)]
+pub struct Application {
+    pub application_id: i64,
+    /// The profile applying
+    pub profile_id: i32,
+    pub posting_id: i64,
+    /// The company the posting is at
+    pub sid: i32,
+    pub posting_title: String,
+    pub posting_url: String,
+    pub status: ApplicationStatus,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// An application moving from one status to another
+#[derive(Debug, Clone, Serialize)]
+pub struct Transition {
+    /// None for the application being created
+    pub from: Option<ApplicationStatus>,
+    pub to: ApplicationStatus,
+    pub transitioned_at: DateTime<Utc>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Note {
+    pub note_id: i64,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Document {
+    pub document_id: i64,
+    pub kind: DocumentKind,
+    pub name: String,
+    /// Where the document is kept, e.g. a file path or URL
+    pub location: String,
+    pub attached_at: DateTime<Utc>,
+}
+
+const APPLICATION_COLUMNS: &str = "a.application_id, a.profile_id, a.posting_id, p.sid, p.title, p.url, a.status, \
+    a.created_at, a.updated_at";
+
+fn application_from_row(row: &Row) -> Result<Application, Error> {
+    let status: String = row.get(6);
+    Ok(Application {
+        application_id: row.get(0),
+        profile_id: row.get(1),
+        posting_id: row.get(2),
+        sid: row.get(3),
+        posting_title: row.get::<_, Option<String>>(4).unwrap_or_default(),
+        posting_url: row.get::<_, Option<String>>(5).unwrap_or_default(),
+        status: ApplicationStatus::from_str(&status)?,
+        created_at: row.get(7),
+        updated_at: row.get(8),
+    })
+}
+
+/// Tracks applications in the same database as the company data store.
+/// The data store has to be initialized first, since applications reference its JobPostings and CandidateProfiles.
+pub struct ApplicationTracker {
+    postgres_client: Client,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl ApplicationTracker {
+    pub async fn new() -> Result<ApplicationTracker, Error> {
+        let postgres_client = establish_connection().await?;
+        Ok(ApplicationTracker { postgres_client })
+    }
+
+    pub async fn initialize_database(&mut self, dry_run: bool) -> Result<(), Error> {
+        for table in ApplicationTables::all() {
+            let query = format!("CREATE TABLE IF NOT EXISTS {} ({})", table.as_str(), table.as_sql());
+            if dry_run {
+                println!("{}", query);
+                continue;
+            }
+            self.postgres_client.execute(&query, &[]).await?;
+            println!("Table {} created", table.as_str());
+        }
+        for migration in MIGRATIONS {
+            if dry_run {
+                println!("{}", migration);
+                continue;
+            }
+            self.postgres_client.batch_execute(migration).await?;
+        }
+        Ok(())
+    }
+
+    /// Starts tracking a profile's application to a posting, as Discovered.
+    /// Returns the profile's existing application instead if it already has one.
+    /// Fails if the profile excludes the posting's company.
+    pub async fn create_application(&mut self, profile_id: &i32, posting_id: &i64) -> Result<i64, Error> {
+        let transaction = self.postgres_client.transaction().await?;
+        let excluded = transaction.query(
+            "SELECT 1 FROM JobPostings p JOIN CandidateProfiles cp ON p.sid = ANY(cp.excluded_sids) \
+             WHERE p.posting_id = $1 AND cp.profile_id = $2",
+            &[posting_id, profile_id],
+        ).await?;
+        if !excluded.is_empty() {
+            bail!("Profile {} excludes the company posting {}", profile_id, posting_id);
+        }
+        let results = transaction.query(
+            "INSERT INTO Applications (profile_id, posting_id) VALUES ($1, $2) \
+             ON CONFLICT (profile_id, posting_id) DO NOTHING RETURNING application_id",
+            &[profile_id, posting_id],
+        ).await?;
+        let application_id: i64 = match results.first() {
+            Some(row) => row.get(0),
+            None => {
+                let results = transaction.query(
+                    "SELECT application_id FROM Applications WHERE profile_id = $1 AND posting_id = $2",
+                    &[profile_id, posting_id],
+                ).await?;
+                return Ok(results[0].get(0));
+            },
+        };
+        transaction.execute(
+            "INSERT INTO ApplicationTransitions (application_id, from_status, to_status) VALUES ($1, NULL, $2)",
+            &[&application_id, &ApplicationStatus::Discovered.as_str()],
+        ).await?;
+        transaction.commit().await?;
+        Ok(application_id)
+    }
+
+    /// Moves an application to another status, recording the transition.
+    /// Fails if the move isn't one of the current status's next_statuses.
+    pub async fn transition(&mut self, application_id: &i64, to: ApplicationStatus, note: Option<&str>) -> Result<(), Error> {
+        let transaction = self.postgres_client.transaction().await?;
+        let results = transaction.query(
+            "SELECT status FROM Applications WHERE application_id = $1 FOR UPDATE", &[application_id],
+        ).await?;
+        let from = match results.first() {
+            Some(row) => ApplicationStatus::from_str(row.get(0))?,
+            None => bail!("No application with id {}", application_id),
+        };
+        if !from.can_transition_to(to) {
+            bail!("Application {} can't move from {} to {}", application_id, from, to);
+        }
+        transaction.execute(
+            "UPDATE Applications SET status = $2, updated_at = now() WHERE application_id = $1",
+            &[application_id, &to.as_str()],
+        ).await?;
+        transaction.execute(
+            "INSERT INTO ApplicationTransitions (application_id, from_status, to_status, note) VALUES ($1, $2, $3, $4)",
+            &[application_id, &from.as_str(), &to.as_str(), &note],
+        ).await?;
+        transaction.commit().await?;
+        println!("Application {} moved from {} to {}", application_id, from, to);
+        Ok(())
+    }
+
+    pub async fn get_application(&self, application_id: &i64) -> Result<Option<Application>, Error> {
+        let query = format!("SELECT {} FROM Applications a JOIN JobPostings p ON p.posting_id = a.posting_id \
+            WHERE a.application_id = $1", APPLICATION_COLUMNS);
+        let results = self.postgres_client.query(&query, &[application_id]).await?;
+        match results.first() {
+            Some(row) => Ok(Some(application_from_row(row)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The profile's applications with any of the given statuses (or all of them if none are given),
+    /// least recently updated first
+    pub async fn get_applications(&self, profile_id: &i32, statuses: Vec<ApplicationStatus>) -> Result<Vec<Application>, Error> {
+        let statuses: Vec<String> = statuses.iter().map(|status| status.as_str().to_string()).collect();
+        let query = format!("SELECT {} FROM Applications a JOIN JobPostings p ON p.posting_id = a.posting_id \
+            WHERE a.profile_id = $1 AND (cardinality($2::TEXT[]) = 0 OR a.status = ANY($2)) \
+            ORDER BY a.updated_at, a.application_id", APPLICATION_COLUMNS);
+        let results = self.postgres_client.query(&query, &[profile_id, &statuses]).await?;
+        results.iter().map(application_from_row).collect()
+    }
+
+    /// Everything of the profile's that can't move forward until they act, longest waiting first
+    pub async fn get_waiting_on_me(&self, profile_id: &i32) -> Result<Vec<Application>, Error> {
+        let statuses = ApplicationStatus::all().into_iter().filter(|status| status.is_waiting_on_user()).collect();
+        self.get_applications(profile_id, statuses).await
+    }
+
+    /// The application's history, oldest first
+    pub async fn get_transitions(&self, application_id: &i64) -> Result<Vec<Transition>, Error> {
+        let query = "SELECT from_status, to_status, transitioned_at, note FROM ApplicationTransitions \
+            WHERE application_id = $1 ORDER BY transitioned_at, transition_id".to_string();
+        let results = self.postgres_client.query(&query, &[application_id]).await?;
+        let mut transitions = Vec::new();
+        for row in results {
+            let from: Option<String> = row.get(0);
+            let to: String = row.get(1);
+            transitions.push(Transition {
+                from: match from {
+                    Some(from) => Some(ApplicationStatus::from_str(&from)?),
+                    None => None,
+                },
+                to: ApplicationStatus::from_str(&to)?,
+                transitioned_at: row.get(2),
+                note: row.get(3),
+            });
+        }
+        Ok(transitions)
+    }
+
+    pub async fn add_note(&mut self, application_id: &i64, body: &str) -> Result<i64, Error> {
+        let results = self.postgres_client.query(
+            "INSERT INTO ApplicationNotes (application_id, body) VALUES ($1, $2) RETURNING note_id",
+            &[application_id, &body],
+        ).await?;
+        Ok(results[0].get(0))
+    }
+
+    /// The application's notes, oldest first
+    pub async fn get_notes(&self, application_id: &i64) -> Result<Vec<Note>, Error> {
+        let query = "SELECT note_id, body, created_at FROM ApplicationNotes WHERE application_id = $1 \
+            ORDER BY created_at, note_id".to_string();
+        let results = self.postgres_client.query(&query, &[application_id]).await?;
+        Ok(results.iter().map(|row| Note { note_id: row.get(0), body: row.get(1), created_at: row.get(2) }).collect())
+    }
+
+    /// Attaches a document to an application. Only where the document is kept is stored, not the document itself.
+    pub async fn attach_document(&mut self, application_id: &i64, kind: DocumentKind, name: &str, location: &str) -> Result<i64, Error> {
+        let results = self.postgres_client.query(
+            "INSERT INTO ApplicationDocuments (application_id, kind, name, location) VALUES ($1, $2, $3, $4) RETURNING document_id",
+            &[application_id, &kind.as_str(), &name, &location],
+        ).await?;
+        Ok(results[0].get(0))
+    }
 
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    pub async fn get_documents(&self, application_id: &i64) -> Result<Vec<Document>, Error> {
+        let query = "SELECT document_id, kind, name, location, attached_at FROM ApplicationDocuments \
+            WHERE application_id = $1 ORDER BY attached_at, document_id".to_string();
+        let results = self.postgres_client.query(&query, &[application_id]).await?;
+        let mut documents = Vec::new();
+        for row in results {
+            let kind: String = row.get(1);
+            documents.push(Document {
+                document_id: row.get(0),
+                kind: DocumentKind::from_str(&kind)?,
+                name: row.get(2),
+                location: row.get(3),
+                attached_at: row.get(4),
+            });
+        }
+        Ok(documents)
     }
 }
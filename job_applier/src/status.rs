@@ -0,0 +1,107 @@
+use std::fmt;
+use std::str::FromStr;
+use anyhow::{bail, Error};
+use serde::{Deserialize, Serialize};
+
+/// Where an application stands. Applications only move along the transitions in next_statuses.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ApplicationStatus {
+    /// We know about the posting, nothing more
+    Discovered,
+    /// Picked as worth applying to
+    Shortlisted,
+    /// The application has been written, but not yet reviewed
+    Drafted,
+    /// Waiting for the user to approve sending the draft
+    AwaitingApproval,
+    Submitted,
+    /// The company confirmed they received it
+    Acknowledged,
+    Interviewing,
+    Offer,
+    Rejected,
+    /// We pulled out, or decided not to apply
+    Withdrawn,
+}
+
+impl ApplicationStatus {
+    pub fn all() -> Vec<ApplicationStatus> {
+        vec![
+            ApplicationStatus::Discovered,
+            ApplicationStatus::Shortlisted,
+            ApplicationStatus::Drafted,
+            ApplicationStatus::AwaitingApproval,
+            ApplicationStatus::Submitted,
+            ApplicationStatus::Acknowledged,
+            ApplicationStatus::Interviewing,
+            ApplicationStatus::Offer,
+            ApplicationStatus::Rejected,
+            ApplicationStatus::Withdrawn,
+        ]
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            ApplicationStatus::Discovered => "discovered",
+            ApplicationStatus::Shortlisted => "shortlisted",
+            ApplicationStatus::Drafted => "drafted",
+            ApplicationStatus::AwaitingApproval => "awaiting_approval",
+            ApplicationStatus::Submitted => "submitted",
+            ApplicationStatus::Acknowledged => "acknowledged",
+            ApplicationStatus::Interviewing => "interviewing",
+            ApplicationStatus::Offer => "offer",
+            ApplicationStatus::Rejected => "rejected",
+            ApplicationStatus::Withdrawn => "withdrawn",
+        }
+    }
+
+    /// The statuses an application can move to from this one
+    pub fn next_statuses(&self) -> Vec<ApplicationStatus> {
+        use ApplicationStatus::*;
+        match self {
+            Discovered => vec![Shortlisted, Withdrawn],
+            Shortlisted => vec![Drafted, Withdrawn],
+            Drafted => vec![AwaitingApproval, Withdrawn],
+            // the user can send a draft back for changes
+            AwaitingApproval => vec![Submitted, Drafted, Withdrawn],
+            // companies often skip acknowledging and go straight to an interview or rejection
+            Submitted => vec![Acknowledged, Interviewing, Rejected, Withdrawn],
+            Acknowledged => vec![Interviewing, Rejected, Withdrawn],
+            Interviewing => vec![Offer, Rejected, Withdrawn],
+            // declining an offer is withdrawing; a rescinded offer is a rejection
+            Offer => vec![Rejected, Withdrawn],
+            Rejected | Withdrawn => vec![],
+        }
+    }
+
+    pub fn can_transition_to(&self, to: ApplicationStatus) -> bool {
+        self.next_statuses().contains(&to)
+    }
+
+    /// Whether the application is over
+    pub fn is_terminal(&self) -> bool {
+        self.next_statuses().is_empty()
+    }
+
+    /// Whether the application can't move forward until the user does something
+    pub fn is_waiting_on_user(&self) -> bool {
+        matches!(self, ApplicationStatus::AwaitingApproval | ApplicationStatus::Offer)
+    }
+}
+
+impl fmt::Display for ApplicationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for ApplicationStatus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match ApplicationStatus::all().into_iter().find(|status| status.as_str() == s) {
+            Some(status) => Ok(status),
+            None => bail!("Unknown application status: {}", s),
+        }
+    }
+}
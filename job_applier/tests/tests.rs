@@ -0,0 +1,37 @@
+#[cfg(test)]
+mod application_status_tests {
+    use std::str::FromStr;
+    use job_applier::*;
+
+    #[test]
+    fn status_round_trip_test() {
+        for status in ApplicationStatus::all() {
+            assert_eq!(ApplicationStatus::from_str(status.as_str()).unwrap(), status);
+        }
+        for kind in [DocumentKind::Resume, DocumentKind::CoverLetter, DocumentKind::Other] {
+            assert_eq!(DocumentKind::from_str(kind.as_str()).unwrap(), kind);
+        }
+        assert!(ApplicationStatus::from_str("bogus").is_err());
+    }
+
+    #[test]
+    fn transitions_test() {
+        use ApplicationStatus::*;
+        let happy_path = [Discovered, Shortlisted, Drafted, AwaitingApproval, Submitted, Acknowledged, Interviewing, Offer];
+        for pair in happy_path.windows(2) {
+            assert!(pair[0].can_transition_to(pair[1]), "{} -> {}", pair[0], pair[1]);
+        }
+        // a draft can be sent back for changes, but nothing else moves backwards
+        assert!(AwaitingApproval.can_transition_to(Drafted));
+        assert!(!Discovered.can_transition_to(Submitted));
+        assert!(!Rejected.can_transition_to(Shortlisted));
+        for status in ApplicationStatus::all() {
+            assert!(!status.can_transition_to(status));
+            assert_eq!(status.is_terminal(), status == Rejected || status == Withdrawn);
+            // you can always walk away from an application that isn't over
+            assert_eq!(status.can_transition_to(Withdrawn), !status.is_terminal());
+        }
+        let waiting: Vec<ApplicationStatus> = ApplicationStatus::all().into_iter().filter(|s| s.is_waiting_on_user()).collect();
+        assert_eq!(waiting, vec![AwaitingApproval, Offer]);
+    }
+}
\ No newline at end of file
@@ -3,6 +3,7 @@ use serde_json::Value;
 use yup_oauth2 as oauth2;
 use yup_oauth2::{InstalledFlowAuthenticator, InstalledFlowReturnMethod};
 use company_data_store::{CompanyDataStore};
+use company_common::{Website, WebsiteRole};
 use anyhow::{Error, bail, Result};
 use futures::executor::block_on;
 use thiserror::Error;
@@ -33,8 +34,9 @@ pub struct GoogleSerpService {
 }
 
 impl GoogleSerpService {
-    async fn get_serp(&self, query: &str) -> Result<Vec<(String, String)>, SerpServiceError> {
-        let mut result_vec: Vec<(String, String)> = vec![]; // Tuples containing titles and links
+    pub async fn get_serp(&self, query: &str) -> Result<Vec<Website>, SerpServiceError> {
+        let mut result_vec: Vec<Website> = vec![];
+        let discovered_at = chrono::Utc::now();
         // deserialize the applicationsecret from serde_json
         let secret_file_path = &self.secret_file_path; // the JSON obtained from Google Cloud Console
         let secret = oauth2::read_application_secret(secret_file_path).await;
@@ -162,7 +164,7 @@ impl GoogleSerpService {
         };
 
 
-        jsoned_response.iter().for_each(|item| {
+        jsoned_response.iter().enumerate().for_each(|(rank, item)| {
             let title = item.get("title");
             let title = match title {
                 Some(title) => {
@@ -195,7 +197,19 @@ impl GoogleSerpService {
             let link = link.as_str().unwrap();
             println!("Title: {}", title);
             println!("Link: {}", link);
-            result_vec.push((title.to_string(), link.to_string()));
+            let snippet = item.get("snippet").and_then(|snippet| snippet.as_str());
+            result_vec.push(Website {
+                title: title.to_string(),
+                url: link.to_string(),
+                role: WebsiteRole::guess_from_url(link),
+                search_rank: Some(rank as u32 + 1),
+                snippet: snippet.map(|snippet| snippet.to_string()),
+                source_engine: Some("google".to_string()),
+                discovered_at: Some(discovered_at),
+                http_status: None,
+                last_verified_at: None,
+                last_changed_at: None,
+            });
         });
         Ok(result_vec)
     }
@@ -218,7 +232,7 @@ impl GoogleSerpService {
         }
     }
 
-    pub async fn search_query(&self, query: &str) -> Result<Vec<(String, String)>, SerpServiceError> {
+    pub async fn search_query(&self, query: &str) -> Result<Vec<Website>, SerpServiceError> {
         println!("Searching query: {}", query);
         let maximum_backoff = 64;
         let mut backoff = 1;
@@ -261,7 +275,7 @@ pub struct DuckDuckGoSerpService {
 }
 
 impl DuckDuckGoSerpService {
-    fn get_serp(&self, query: &str) -> Result<Vec<(String, String)>, Error> {
+    fn get_serp(&self, query: &str) -> Result<Vec<Website>, Error> {
         unimplemented!()
     }
 }
@@ -1,4 +1,4 @@
-use serp_service::{GoogleSerpService, SerpService};
+use serp_service::GoogleSerpService;
 
 #[tokio::main]
 async fn main() {
@@ -6,10 +6,10 @@ async fn main() {
     let cwd = std::env::current_dir().unwrap();
     println!("Current working directory: {:?}", cwd);
     let serp_service = GoogleSerpService::new(None);
-    match serp_service.get_serp("test") {
+    match serp_service.search_query("test").await {
         Ok(v) => {
-            for (title, url) in v {
-                println!("Title: {}, URL: {}", title, url);
+            for website in v {
+                println!("Title: {}, URL: {}, Role: {}", website.title, website.url, website.role);
             }
         },
         Err(e) => {
@@ -1,12 +1,19 @@
-use company_data_store::{CompanyDataStore};
+use std::time::Duration;
+use company_data_store::{CompanyDataStore, DiscoveryStatus};
 use anyhow::{bail, Error, Result};
 use serp_service::{GoogleSerpService};
+use company_common::{CanonicalUrl, FactSource, Provenance};
 
-
+/// How long a worker may hold a company before another worker can claim it
+const DISCOVERY_LEASE: Duration = Duration::from_secs(10 * 60);
+/// How long to wait when there are no companies due for discovery
+const IDLE_WAIT: Duration = Duration::from_secs(60);
 
 pub struct WebsiteDiscoverer {
     pub company_data_store: CompanyDataStore,
     pub serp_service: GoogleSerpService,
+    /// Identifies this discoverer's leases in the discovery queue
+    pub worker_id: String,
 }
 
 impl WebsiteDiscoverer {
@@ -16,101 +23,133 @@ impl WebsiteDiscoverer {
         Ok(WebsiteDiscoverer {
             company_data_store,
             serp_service: GoogleSerpService::new(None),
+            worker_id: format!("website_discovery-{}", std::process::id()),
         })
     }
 
     pub async fn discover_website(&mut self) -> Result<(), Error> {
         // 1. grab a single undiscovered website from the data store
-        // (get a sid that doesn't have any entries in the companywebsites table)
-        let (sid, company) = self.company_data_store.get_next_undiscovered_company().await?;
+        let (_sid, company) = self.company_data_store.get_next_undiscovered_company().await?;
         println!("Company: {:?}", company);
 
         // 2. search for the company name on google
-        let query = construct_query(&company.get_company_name()?);
+        let query = construct_query(&company.get_company_name()?, true);
         let search_results = self.serp_service.search_query(&query).await?;
 
-        for (title, url) in search_results {
-            println!("Title: {}, URL: {}", title, url);
+        for website in search_results {
+            println!("Title: {}, URL: {}, Role: {}", website.title, website.url, website.role);
         }
         Ok(())
     }
 
-    pub async fn discover_and_upload(&mut self) -> Result<(), Error> {
-        // begin transaction
-        // 1. grab a single undiscovered website from the data store
-        // (get a sid that doesn't have any entries in the companywebsites table)
-        let (sid, company) = self.company_data_store.get_next_undiscovered_company().await?;
+    /// Claims the next company due for discovery, searches for it, and uploads the results.
+    /// Returns the sid of the company that was processed, or None if no company was due.
+    pub async fn discover_and_upload(&mut self) -> Result<Option<i32>, Error> {
+        // 1. claim a single company due for discovery from the data store
+        let claimed = self.company_data_store.claim_next_company(&self.worker_id, DISCOVERY_LEASE).await?;
+        let (sid, company) = match claimed {
+            Some(claimed) => claimed,
+            None => return Ok(None),
+        };
         println!("Company: {:?}", company);
 
+        // subsidiaries and brands often list their jobs on the parent's career page, which is reused for them;
+        // the company's own websites are still searched for
+        let mut inherits_career_page = false;
+        if company.career_pages.is_none() {
+            if let Some((parent_sid, career_pages)) = self.company_data_store.get_inherited_career_pages(&sid).await? {
+                println!("Using the career page of company {} ({})", parent_sid, career_pages[0].url);
+                self.company_data_store.record_inherited_career_page(&sid, &self.worker_id, &parent_sid).await?;
+                inherits_career_page = true;
+            }
+        }
+
         // 2. search for the company name on google
-        let query = construct_query(&company.get_company_name()?);
-        let search_results = self.serp_service.search_query(&query).await;
-        let search_results = match search_results {
+        let company_name = match company.get_company_name() {
+            Ok(name) => name,
+            Err(e) => {
+                self.company_data_store.fail_discovery(&sid, &self.worker_id, &e.to_string()).await?;
+                return Ok(Some(sid));
+            }
+        };
+        let query = construct_query(&company_name, !inherits_career_page);
+        let search_results = match self.serp_service.search_query(&query).await {
             Ok(v) => v,
-            Err(serp_service::SerpServiceError::JsonFailedError) => {
-            // but if error is "JSON response is not an object", then we should
-            // delete the company from the company table
-                let result = self.company_data_store.delete_company(&sid, false).await;
-                match result {
-                    Ok(_) => {},
-                    Err(e) => {
-                        bail!("Error: {:?}", e);
-                    }
-                }
-                return Ok(());
+            Err(e @ serp_service::SerpServiceError::JsonFailedError) => {
+                // but if error is "JSON response is not an object", then the company can never be found,
+                // so we delete it from the company table (softly, so it can be restored)
+                self.company_data_store.soft_delete_company(&sid, "SERP response could not be parsed", false).await?;
+                self.company_data_store.fail_discovery(&sid, &self.worker_id, &e.to_string()).await?;
+                return Ok(Some(sid));
             }
-            _ => {
-                bail!("Error: {:?}", search_results);
+            Err(e) => {
+                // the company is retried later with a backoff, rather than thrown away
+                self.company_data_store.fail_discovery(&sid, &self.worker_id, &e.to_string()).await?;
+                return Ok(Some(sid));
             }
         };
 
-        for (title, url) in &search_results {
-            println!("Title: {}, URL: {}", title, url);
+        if search_results.is_empty() {
+            self.company_data_store.complete_discovery(&sid, &self.worker_id, DiscoveryStatus::NoResults).await?;
+            return Ok(Some(sid));
         }
 
-        // 3. upload the search results to the data store
-        for (title, url) in &search_results {
-            let result = self.company_data_store.add_website(&sid, title, url, false, false).await;
-            match result {
-                Ok(_) => {},
-                Err(e) => {
-                    bail!("Error: {:?}", e);
-                }
+        for website in &search_results {
+            println!("Title: {}, URL: {}, Role: {}", website.title, website.url, website.role);
+        }
+
+        // 3. upload the search results to the data store, leaving out directories and social networks,
+        // which show up for every company and are never its own site
+        let blocklist = self.company_data_store.get_domain_blocklist().await?;
+        let provenance = Provenance::new(FactSource::Serp, concat!("website_discovery/", env!("CARGO_PKG_VERSION")), 0.5);
+        for website in search_results.iter().filter(|website| !blocklist.is_blocked(&website.url)) {
+            // results that aren't web pages (e.g. mailto: links) can't be stored, but shouldn't fail the company
+            if let Err(e) = CanonicalUrl::parse(&website.url) {
+                println!("Skipping {}: {}", website.url, e);
+                continue;
+            }
+            let result = self.company_data_store.add_website(&sid, website, &provenance, false).await;
+            if let Err(e) = result {
+                self.company_data_store.fail_discovery(&sid, &self.worker_id, &e.to_string()).await?;
+                bail!("Error: {:?}", e);
             }
         }
-        Ok(())
+        self.company_data_store.complete_discovery(&sid, &self.worker_id, DiscoveryStatus::Discovered).await?;
+        Ok(Some(sid))
     }
 
     // the intent is to spawn a thread for this function, which will spawn
     // tasks to discover websites from the data store, and then upload the
     // discovered websites to the data store. This will hopefully run forever.
     pub async fn discover_websites(mut self) -> Result<(), Error> {
-        // spawn a task to discover websites from the data store, and then
-        // spawn a task to upload the results to the data store
         loop {
             let discover_websites_task_result = self.discover_and_upload().await;
             match discover_websites_task_result {
-                Ok(_) => {},
+                Ok(Some(_)) => {},
+                Ok(None) => {
+                    println!("No companies due for discovery, waiting {:?}", IDLE_WAIT);
+                    tokio::time::sleep(IDLE_WAIT).await;
+                },
                 Err(e) => {
-                    // wait 30 minutes before retrying
-                    println!("Waiting 30 minutes before retrying: {:?}", e);
-                    tokio::time::sleep(std::time::Duration::from_secs(1800)).await;
+                    println!("Waiting {:?} before retrying: {:?}", IDLE_WAIT, e);
+                    tokio::time::sleep(IDLE_WAIT).await;
                 }
             }
         }
     }
 
     pub async fn discover_specific_company(&mut self, company_name: &str) -> Result<(), Error> {
-        let query = construct_query(company_name);
+        let query = construct_query(company_name, true);
         let search_results = self.serp_service.search_query(&query).await?;
-        for (title, url) in search_results {
-            println!("Title: {}, URL: {}", title, url);
+        for website in search_results {
+            println!("Title: {}, URL: {}, Role: {}", website.title, website.url, website.role);
         }
         Ok(())
     }
 }
 
-fn construct_query(query: &str) -> String {
+/// Searches for the company's career page if `careers`, and for its sites in general otherwise
+fn construct_query(query: &str, careers: bool) -> String {
     // remove "corp, llc, inc", etc, from the query, ignoring case
     let return_string =
         query
@@ -122,6 +161,9 @@ fn construct_query(query: &str) -> String {
             .replace("group", "");
 
     // append "careers"
+    if !careers {
+        return return_string;
+    }
     let return_string = format!("{} careers", return_string);
     return_string
 }